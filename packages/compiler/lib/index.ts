@@ -31,6 +31,52 @@ function findBinary(): string | null {
   return null;
 }
 
+// ─── Diagnostics ────────────────────────────────────────────
+export interface LuminDiagnostic {
+  message: string;
+  file: string;
+  line: number;
+  column: number;
+}
+
+/**
+ * Error for a build that reported diagnostics: the first one, with every
+ * diagnostic attached as `luminDiagnostics`. The compiler recovers from
+ * syntax errors, so the generated TypeScript is checked as well.
+ */
+function diagnosticsError(json: any, options: CompileOptions): Error {
+  const diagnostics: LuminDiagnostic[] = json.diagnostics.map((d: any) => ({
+    message: d.message,
+    file: options.input,
+    line: d.start.line,
+    column: d.start.col,
+  }));
+
+  if (options.checkTypes !== false && json.ts) {
+    const originalSource = fs.readFileSync(options.input, "utf-8");
+    for (const t of validateTypeScript(json.ts, options.input, originalSource)) {
+      const known = diagnostics.some(
+        (d) => d.line === t.line && d.column === t.column,
+      );
+      if (!known) {
+        diagnostics.push(t);
+      }
+    }
+  }
+
+  const d = json.diagnostics[0];
+  const err = new Error(d.message);
+  (err as any).luminStart = d.start;
+  (err as any).luminEnd = d.end;
+  (err as any).luminLoc = {
+    file: options.input,
+    line: d.start.line,
+    column: d.start.col,
+  };
+  (err as any).luminDiagnostics = diagnostics;
+  return err;
+}
+
 // ─── Compile ────────────────────────────────────────────────
 export interface CompileOptions {
   input: string;
@@ -39,7 +85,22 @@ export interface CompileOptions {
   checkTypes?: boolean;
 }
 
+/** Render mode selected by the component's `"use ..."` directive. */
+export type RenderMode = "prerender" | "server" | "static";
+
+export interface CompileResult {
+  js: string;
+  renderMode: RenderMode;
+}
+
 export async function compile(options: CompileOptions): Promise<string> {
+  return (await compileWithMetadata(options)).js;
+}
+
+/** Like `compile`, but also returns what the compiler found out about the component. */
+export async function compileWithMetadata(
+  options: CompileOptions,
+): Promise<CompileResult> {
   // Build CLI arguments (shared between binary and cargo run)
   const cliArgs = [
     "build",
@@ -82,6 +143,12 @@ export async function compile(options: CompileOptions): Promise<string> {
     const json = JSON.parse(stdout);
 
     if (json.js !== undefined) {
+      // Warnings (e.g. an unsanitized `{@html}`) don't fail the build.
+      for (const d of json.diagnostics ?? []) {
+        console.warn(
+          `${options.input}:${d.start.line}:${d.start.col}: warning: ${d.message}`,
+        );
+      }
       if (options.checkTypes !== false && json.ts) {
         const originalSource = fs.readFileSync(options.input, "utf-8");
         const typeDiags = validateTypeScript(
@@ -101,20 +168,11 @@ export async function compile(options: CompileOptions): Promise<string> {
           throw err;
         }
       }
-      return json.js;
+      return { js: json.js, renderMode: json.renderMode ?? "prerender" };
     }
 
     if (json.diagnostics && json.diagnostics.length > 0) {
-      const d = json.diagnostics[0];
-      const err = new Error(d.message);
-      (err as any).luminStart = d.start;
-      (err as any).luminEnd = d.end;
-      (err as any).luminLoc = {
-        file: options.input,
-        line: d.start.line,
-        column: d.start.col,
-      };
-      throw err;
+      throw diagnosticsError(json, options);
     }
 
     throw new Error(`Unexpected output from compiler: ${stdout}`);
@@ -129,16 +187,7 @@ export async function compile(options: CompileOptions): Promise<string> {
 
       if (json) {
         if (json.diagnostics && json.diagnostics.length > 0) {
-          const d = json.diagnostics[0];
-          const err = new Error(d.message);
-          (err as any).luminStart = d.start;
-          (err as any).luminEnd = d.end;
-          (err as any).luminLoc = {
-            file: options.input,
-            line: d.start.line,
-            column: d.start.col,
-          };
-          throw err;
+          throw diagnosticsError(json, options);
         } else if (json.error) {
           const err = new Error(json.error);
           if (json.line !== undefined && json.column !== undefined) {
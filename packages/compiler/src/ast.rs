@@ -8,6 +8,48 @@ pub struct ComponentFile {
     pub template: Vec<TemplateNode>,
     pub defined_slots: Vec<String>,
     pub head: Option<HeadMetadata>,
+    /// Set by the `<script>` directive prologue.
+    pub render_mode: RenderMode,
+}
+
+/// How a page is rendered, chosen with a directive at the top of `<script>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// `"use prerender"`: prerendered at build time, then fully hydrated (PIR).
+    #[default]
+    Prerender,
+    /// `"use server"`: rendered on every request (SSR).
+    Server,
+    /// `"use static"`: static HTML with interactive islands (SSG).
+    Static,
+}
+
+impl RenderMode {
+    pub fn from_directive(directive: &str) -> Option<Self> {
+        match directive {
+            "use prerender" => Some(RenderMode::Prerender),
+            "use server" => Some(RenderMode::Server),
+            "use static" => Some(RenderMode::Static),
+            _ => None,
+        }
+    }
+
+    pub fn directive(self) -> &'static str {
+        match self {
+            RenderMode::Prerender => "use prerender",
+            RenderMode::Server => "use server",
+            RenderMode::Static => "use static",
+        }
+    }
+
+    /// Name used in the CLI's JSON output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RenderMode::Prerender => "prerender",
+            RenderMode::Server => "server",
+            RenderMode::Static => "static",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +64,7 @@ pub struct HeadMetadata {
 pub struct StyleBlock {
     pub code: String,
     pub span: Option<SourceRange>,
+    pub global: bool, // `<style global>` opts out of scoping
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,6 +90,9 @@ pub enum ImportSpecifier {
 pub enum PropKind {
     Prop,
     Signal,
+    /// `const value = model(initial)`: a prop the parent can bind with
+    /// `bind:value={signal}`; writes from the child reach the parent.
+    Model,
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +115,25 @@ pub struct ScriptBlock {
     pub imports: Vec<ScriptImport>,
     pub props: Vec<Prop>,
     pub span: Option<SourceRange>,
+    pub mappings: Vec<CodeMapping>, // `code` offsets -> `original_code` offsets
+    pub directives: Vec<RenderDirective>,
+}
+
+/// A top-level `"use prerender" | "use server" | "use static"` statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderDirective {
+    pub mode: RenderMode,
+    pub span: SourceRange, // the string literal, in the .lumix source
+    /// Part of the leading run of directives, where it takes effect.
+    pub in_prologue: bool,
+}
+
+/// Maps a byte offset in generated code back to a byte offset in the code it
+/// was generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeMapping {
+    pub generated: usize,
+    pub original: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -84,6 +149,124 @@ pub enum TemplateNode {
     Expr(JsExpr),
     ControlFlow(ControlFlowBlock),
     Slot(SlotNode),
+    /// `<!-- ... -->`, text between the delimiters.
+    Comment(String),
+    /// `<!doctype html>`, `<![if !IE]>` and other `<!...>` markup, passed
+    /// through as written (text between `<!` and `>`).
+    Declaration(String),
+    /// `{@const total = item.price * item.qty}`, only at the top of a
+    /// control-flow branch.
+    Const(ConstTag),
+    /// `{@html expr}`: the string value is inserted as markup, unescaped.
+    Html(JsExpr),
+    /// `@{snippet row(item) { ... }}`: renders nothing where it is defined.
+    Snippet(SnippetNode),
+    /// `{@render row(item)}`.
+    Render(RenderTag),
+}
+
+/// A template-local constant, evaluated each time its branch renders.
+#[derive(Debug, Clone)]
+pub struct ConstTag {
+    /// The name or destructuring pattern as written.
+    pub binding: String,
+    pub expr: JsExpr,
+}
+
+/// Markup that can be rendered any number of times with `{@render}`. It is
+/// visible in the rest of the list it is defined in; as the direct child of a
+/// component, it is passed as the slot of the same name instead.
+#[derive(Debug, Clone)]
+pub struct SnippetNode {
+    pub name: String,
+    pub name_span: Option<SourceRange>,
+    /// The parameter list as written, without the parentheses.
+    pub params: String,
+    pub body: Vec<TemplateNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderTag {
+    /// What is called: a snippet name, or e.g. `props.slots.row`.
+    pub snippet: JsExpr,
+    /// Each argument as written, including a leading `...`.
+    pub args: Vec<JsExpr>,
+}
+
+/// HTML elements that never have content or a closing tag (`<br>`, `<input>`).
+pub const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+pub fn is_void_element(tag: &str) -> bool {
+    VOID_ELEMENTS.contains(&tag)
+}
+
+/// Elements whose content is kept verbatim as a single text node: no markup,
+/// no `{expr}`.
+pub fn is_raw_text_element(tag: &str) -> bool {
+    matches!(tag, "script" | "style")
+}
+
+/// Elements whose content is text and `{expr}` only; a `<` in it is text.
+/// The HTML parser keeps their content as a single text node.
+pub fn is_escapable_raw_text_element(tag: &str) -> bool {
+    matches!(tag, "textarea" | "title")
+}
+
+/// A `|modifier` after an `on*` attribute name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventModifier {
+    PreventDefault,
+    StopPropagation,
+    StopImmediatePropagation,
+    /// Only events dispatched on the element itself, not its descendants.
+    Self_,
+    /// Only events the user agent dispatched (`event.isTrusted`).
+    Trusted,
+    Once,
+    Capture,
+    Passive,
+}
+
+impl EventModifier {
+    pub const ALL: &'static [EventModifier] = &[
+        EventModifier::PreventDefault,
+        EventModifier::StopPropagation,
+        EventModifier::StopImmediatePropagation,
+        EventModifier::Self_,
+        EventModifier::Trusted,
+        EventModifier::Once,
+        EventModifier::Capture,
+        EventModifier::Passive,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|m| m.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventModifier::PreventDefault => "preventDefault",
+            EventModifier::StopPropagation => "stopPropagation",
+            EventModifier::StopImmediatePropagation => "stopImmediatePropagation",
+            EventModifier::Self_ => "self",
+            EventModifier::Trusted => "trusted",
+            EventModifier::Once => "once",
+            EventModifier::Capture => "capture",
+            EventModifier::Passive => "passive",
+        }
+    }
+
+    /// Passed to `addEventListener` rather than handled by a wrapper.
+    pub fn is_listener_option(self) -> bool {
+        matches!(self, EventModifier::Once | EventModifier::Capture | EventModifier::Passive)
+    }
+}
+
+/// `<!--[if IE]>...<![endif]-->`: kept even when comments are stripped.
+pub fn is_conditional_comment(text: &str) -> bool {
+    text.starts_with("[if ")
 }
 
 #[derive(Debug, Clone)]
@@ -100,11 +283,38 @@ pub enum ControlFlowBlock {
         else_ifs: Vec<(JsExpr, Vec<TemplateNode>)>,
         else_branch: Option<Vec<TemplateNode>>,
     },
+    /// `@{for (const item of items; index=i; key=item.id) { ... }}`.
     For {
-        params: String,
+        /// The loop variable as written: an identifier or a destructuring
+        /// pattern such as `{ id, name }` or `[i, item]`.
+        binding: String,
+        iterable: JsExpr,
+        /// `index=name`: the item's position in the list.
+        index: Option<String>,
         key_expr: Option<String>,
         body: Vec<TemplateNode>,
     },
+    /// `@{await (promise) { ... } then (value) { ... } catch (error) { ... }}`.
+    /// Every branch is optional; the first one shows while the promise is
+    /// pending.
+    Await {
+        promise: JsExpr,
+        pending: Vec<TemplateNode>,
+        /// `then (value)`: the name or destructuring pattern the resolved
+        /// value is bound to, if any.
+        then_binding: Option<String>,
+        then_branch: Vec<TemplateNode>,
+        catch_binding: Option<String>,
+        /// `None` without a `catch` branch: the rejection is left unhandled.
+        catch_branch: Option<Vec<TemplateNode>>,
+    },
+    /// `@{switch (expr) { case "a" { ... } default { ... } }}`. Cases are
+    /// compared with `===`, in order.
+    Switch {
+        discriminant: JsExpr,
+        cases: Vec<(JsExpr, Vec<TemplateNode>)>,
+        default: Option<Vec<TemplateNode>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -116,10 +326,28 @@ pub struct ElementNode {
     pub self_closing: bool,
 }
 
+/// An attribute of an element. The shorthand `{name}` is parsed as
+/// `name={name}`.
 #[derive(Debug, Clone)]
 pub enum AttributeNode {
     Static { name: String, value: String },
     Dynamic { name: String, expr: JsExpr },
-    EventHandler { name: String, expr: JsExpr },
+    /// `onClick|preventDefault|once={expr}`.
+    EventHandler {
+        name: String,
+        expr: JsExpr,
+        modifiers: Vec<EventModifier>,
+    },
+    /// `bind:property={signal}`; `bind:this={ref}` stores the element or
+    /// component instead.
     Bind { property: String, expr: JsExpr },
+    /// `{...expr}`: every property of the object, merged in source order.
+    Spread { expr: JsExpr },
+    /// `class:name={condition}`: toggles one class.
+    Class { name: String, expr: JsExpr },
+    /// `style:property={value}`: sets one CSS property (or `--custom-property`).
+    Style { property: String, expr: JsExpr },
+    /// `use:action={params}`: runs `action(node, params)` once the element
+    /// is created. `params` is optional.
+    Action { action: JsExpr, params: Option<JsExpr> },
 }
@@ -1,20 +1,25 @@
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
-use crate::ast::{ComponentFile, ImportSpecifier};
+use crate::ast::{ComponentFile, ImportSpecifier, PropKind};
+use crate::cache::{normalize_path, SourceCache};
+use crate::codegen::{CodegenOptions, CodegenTarget};
 use crate::diagnostic::{compute_line_starts, Diagnostic};
 use crate::js_validate::{
     diagnose_forbidden_lumin_imports_in_module, validate_js_snippet, JsSnippetKind,
 };
-use crate::parser::parse_component;
-use crate::validate_component_tags_imported;
+use crate::sourcemap::SourceMap;
+use crate::{merge_syntax_errors, validate_component_tags_imported, validate_render_directives};
 use swc_ecma_ast::{ModuleDecl, ModuleItem};
 use swc_ecma_parser::{EsConfig, Parser, StringInput, Syntax};
-use swc_common::{FileName, SourceMap};
+use swc_common::FileName;
 
 #[derive(Debug, Clone)]
 pub struct BundleResult {
     pub js: String,
+    pub map: SourceMap,
+    /// Deduplicated CSS of every component, dependencies first.
+    pub css: String,
     pub diagnostics: Vec<Diagnostic>,
 }
 
@@ -33,6 +38,8 @@ fn collect_used_component_tags(nodes: &[crate::ast::TemplateNode], out: &mut Has
             collect_used_component_tags(&el.children, out);
         } else if let crate::ast::TemplateNode::Slot(slot) = n {
             collect_used_component_tags(&slot.fallback, out);
+        } else if let crate::ast::TemplateNode::Snippet(snippet) = n {
+            collect_used_component_tags(&snippet.body, out);
         }
     }
 }
@@ -43,7 +50,7 @@ fn diagnose_non_lumin_imports_in_script_for_bundle(
     module_code: &str,
     snippet_start_offset: usize,
 ) -> Vec<crate::diagnostic::Diagnostic> {
-    let cm: SourceMap = Default::default();
+    let cm: swc_common::SourceMap = Default::default();
     let fm = cm.new_source_file(FileName::Custom("lumin_script_bundle_check.js".into()), module_code.into());
 
     let syntax = Syntax::Es(EsConfig {
@@ -84,34 +91,72 @@ fn diagnose_non_lumin_imports_in_script_for_bundle(
 }
 
 pub fn bundle_entry(entry_path: &Path) -> Result<BundleResult, anyhow::Error> {
-    let mut compiler = GraphCompiler::new();
+    bundle_entry_with_options(entry_path, &CodegenOptions::default())
+}
+
+pub fn bundle_entry_with_options(
+    entry_path: &Path,
+    options: &CodegenOptions,
+) -> Result<BundleResult, anyhow::Error> {
+    bundle_entry_cached(entry_path, options, &mut SourceCache::new())
+}
+
+/// Like `bundle_entry_with_options`, reading and parsing every file through
+/// `cache`.
+pub fn bundle_entry_cached(
+    entry_path: &Path,
+    options: &CodegenOptions,
+    cache: &mut SourceCache,
+) -> Result<BundleResult, anyhow::Error> {
+    let mut compiler = GraphCompiler::new(cache);
     compiler.compile(entry_path)?;
+    if compiler.has_syntax_errors {
+        return Ok(BundleResult {
+            js: String::new(),
+            map: SourceMap::new("bundle.js"),
+            css: String::new(),
+            diagnostics: compiler.diagnostics,
+        });
+    }
 
+    let (js, map) = compiler.emit_bundle(entry_path, options)?;
     Ok(BundleResult {
-        js: compiler.emit_bundle(entry_path)?,
+        js,
+        map,
+        css: compiler.emit_css(),
         diagnostics: compiler.diagnostics,
     })
 }
 
-struct GraphCompiler {
+struct GraphCompiler<'a> {
+    cache: &'a mut SourceCache,
     components_by_path: HashMap<PathBuf, ComponentFile>,
     component_names_by_path: HashMap<PathBuf, String>,
+    sources_by_path: HashMap<PathBuf, String>,
+    /// Paths in the order they finished compiling (dependencies first).
+    compile_order: Vec<PathBuf>,
     diagnostics: Vec<Diagnostic>,
+    /// Whether any file only parsed with errors (see `parse_recovering`).
+    has_syntax_errors: bool,
     visiting: HashSet<PathBuf>,
 }
 
-impl GraphCompiler {
-    fn new() -> Self {
+impl<'a> GraphCompiler<'a> {
+    fn new(cache: &'a mut SourceCache) -> Self {
         Self {
+            cache,
             components_by_path: HashMap::new(),
             component_names_by_path: HashMap::new(),
+            sources_by_path: HashMap::new(),
+            compile_order: Vec::new(),
             diagnostics: Vec::new(),
+            has_syntax_errors: false,
             visiting: HashSet::new(),
         }
     }
 
     fn compile(&mut self, path: &Path) -> Result<(), anyhow::Error> {
-        let path = path.canonicalize()?;
+        let path = normalize_path(path);
         if self.components_by_path.contains_key(&path) {
             return Ok(());
         }
@@ -120,10 +165,12 @@ impl GraphCompiler {
         }
         self.visiting.insert(path.clone());
 
-        let source = std::fs::read_to_string(&path)?;
+        let source = self.cache.read(&path)?;
         let line_starts = compute_line_starts(&source);
 
-        let component = parse_component(&source).map_err(|e| anyhow::Error::new(e))?;
+        let (component, syntax_errors) = self.cache.parse_recovering(&path, &source);
+        self.has_syntax_errors |= !syntax_errors.is_empty();
+        let first_diagnostic = self.diagnostics.len();
 
         // SWC validation for this file
         if let Some(script) = &component.script {
@@ -131,14 +178,14 @@ impl GraphCompiler {
                 self.diagnostics.extend(validate_js_snippet(
                     &source,
                     &line_starts,
-                    &script.code,
+                    &script.original_code,
                     span.start,
                     JsSnippetKind::ScriptModule,
                 ));
                 self.diagnostics.extend(diagnose_forbidden_lumin_imports_in_module(
                     &source,
                     &line_starts,
-                    &script.code,
+                    &script.original_code,
                     span.start,
                 ));
 
@@ -159,6 +206,9 @@ impl GraphCompiler {
             &line_starts,
             &component,
         ));
+        self.diagnostics.extend(validate_render_directives(&line_starts, &component));
+        let validation = self.diagnostics.split_off(first_diagnostic);
+        self.diagnostics.extend(merge_syntax_errors(syntax_errors, validation));
 
         // Determine component name (file stem)
         let name = path
@@ -195,9 +245,11 @@ impl GraphCompiler {
             }
         }
 
-        self.validate_slots_in_nodes(&component.template, &import_map, &source, &line_starts);
+        self.validate_slots_in_nodes(&component.template, &import_map, &line_starts);
 
         self.component_names_by_path.insert(path.clone(), name);
+        self.sources_by_path.insert(path.clone(), source);
+        self.compile_order.push(path.clone());
         self.components_by_path.insert(path.clone(), component);
         self.visiting.remove(&path);
         Ok(())
@@ -207,7 +259,6 @@ impl GraphCompiler {
         &mut self,
         nodes: &[crate::ast::TemplateNode],
         import_map: &HashMap<String, PathBuf>,
-        source: &str,
         line_starts: &[usize],
     ) {
         for n in nodes {
@@ -224,46 +275,54 @@ impl GraphCompiler {
                         let mut used_slots = HashSet::new();
                         if let Some(child_path) = import_map.get(&el.tag_name) {
                             if let Some(child_comp) = self.components_by_path.get(child_path) {
+                                self.diagnostics.extend(diagnose_component_binds(el, child_comp, line_starts));
                                 for child in &el.children {
-                                    if let crate::ast::TemplateNode::Element(child_el) = child {
-                                        for attr in &child_el.attributes {
-                                            if let crate::ast::AttributeNode::Static { name, value } = attr {
-                                                if name == "slot" {
-                                                    // Duplicate check
-                                                    if used_slots.contains(value) {
-                                                        let span = child_el.tag_span.clone().unwrap_or(crate::ast::SourceRange { start: 0, end: 0 });
-                                                        let (lc_start, lc_end) = crate::diagnostic::range_to_line_cols(line_starts, span.start, span.end);
-                                                        self.diagnostics.push(Diagnostic {
-                                                            severity: crate::diagnostic::DiagnosticSeverity::Warning,
-                                                            message: format!("Duplicate slot '{}' in component '{}'", value, el.tag_name),
-                                                            range: to_diag_range(span),
-                                                            start: lc_start,
-                                                            end: lc_end,
-                                                        });
-                                                    }
-                                                    used_slots.insert(value.clone());
-
-                                                    // Existence check
-                                                    if !child_comp.defined_slots.contains(value) {
-                                                        let span = child_el.tag_span.clone().unwrap_or(crate::ast::SourceRange { start: 0, end: 0 });
-                                                        let (lc_start, lc_end) = crate::diagnostic::range_to_line_cols(line_starts, span.start, span.end);
-                                                        self.diagnostics.push(Diagnostic {
-                                                            severity: crate::diagnostic::DiagnosticSeverity::Warning,
-                                                            message: format!("Component '{}' does not define a slot named '{}'", el.tag_name, value),
-                                                            range: to_diag_range(span),
-                                                            start: lc_start,
-                                                            end: lc_end,
-                                                        });
-                                                    }
-                                                }
+                                    // `slot="name"` on an element, or a snippet passed as the slot of its name.
+                                    let (value, span) = match child {
+                                        crate::ast::TemplateNode::Element(child_el) => {
+                                            let slot = child_el.attributes.iter().find_map(|attr| match attr {
+                                                crate::ast::AttributeNode::Static { name, value } if name == "slot" => Some(value),
+                                                _ => None,
+                                            });
+                                            match slot {
+                                                Some(value) => (value, child_el.tag_span),
+                                                None => continue,
                                             }
                                         }
+                                        crate::ast::TemplateNode::Snippet(snippet) => (&snippet.name, snippet.name_span),
+                                        _ => continue,
+                                    };
+                                    let span = span.unwrap_or(crate::ast::SourceRange { start: 0, end: 0 });
+
+                                    // Duplicate check
+                                    if used_slots.contains(value) {
+                                        let (lc_start, lc_end) = crate::diagnostic::range_to_line_cols(line_starts, span.start, span.end);
+                                        self.diagnostics.push(Diagnostic {
+                                            severity: crate::diagnostic::DiagnosticSeverity::Warning,
+                                            message: format!("Duplicate slot '{}' in component '{}'", value, el.tag_name),
+                                            range: to_diag_range(span),
+                                            start: lc_start,
+                                            end: lc_end,
+                                        });
+                                    }
+                                    used_slots.insert(value.clone());
+
+                                    // Existence check
+                                    if !child_comp.defined_slots.contains(value) {
+                                        let (lc_start, lc_end) = crate::diagnostic::range_to_line_cols(line_starts, span.start, span.end);
+                                        self.diagnostics.push(Diagnostic {
+                                            severity: crate::diagnostic::DiagnosticSeverity::Warning,
+                                            message: format!("Component '{}' does not define a slot named '{}'", el.tag_name, value),
+                                            range: to_diag_range(span),
+                                            start: lc_start,
+                                            end: lc_end,
+                                        });
                                     }
                                 }
                             }
                         }
                     }
-                    self.validate_slots_in_nodes(&el.children, import_map, source, line_starts);
+                    self.validate_slots_in_nodes(&el.children, import_map, line_starts);
                 }
                 crate::ast::TemplateNode::ControlFlow(cf) => match cf {
                     crate::ast::ControlFlowBlock::If {
@@ -272,29 +331,84 @@ impl GraphCompiler {
                         else_branch,
                         ..
                     } => {
-                        self.validate_slots_in_nodes(then_branch, import_map, source, line_starts);
+                        self.validate_slots_in_nodes(then_branch, import_map, line_starts);
                         for (_, branch) in else_ifs {
-                            self.validate_slots_in_nodes(branch, import_map, source, line_starts);
+                            self.validate_slots_in_nodes(branch, import_map, line_starts);
                         }
                         if let Some(branch) = else_branch {
-                            self.validate_slots_in_nodes(branch, import_map, source, line_starts);
+                            self.validate_slots_in_nodes(branch, import_map, line_starts);
                         }
                     }
                     crate::ast::ControlFlowBlock::For { body, .. } => {
-                        self.validate_slots_in_nodes(body, import_map, source, line_starts);
+                        self.validate_slots_in_nodes(body, import_map, line_starts);
+                    }
+                    crate::ast::ControlFlowBlock::Await {
+                        pending,
+                        then_branch,
+                        catch_branch,
+                        ..
+                    } => {
+                        self.validate_slots_in_nodes(pending, import_map, line_starts);
+                        self.validate_slots_in_nodes(then_branch, import_map, line_starts);
+                        if let Some(branch) = catch_branch {
+                            self.validate_slots_in_nodes(branch, import_map, line_starts);
+                        }
+                    }
+                    crate::ast::ControlFlowBlock::Switch { cases, default, .. } => {
+                        for (_, branch) in cases {
+                            self.validate_slots_in_nodes(branch, import_map, line_starts);
+                        }
+                        if let Some(branch) = default {
+                            self.validate_slots_in_nodes(branch, import_map, line_starts);
+                        }
                     }
                 },
+                crate::ast::TemplateNode::Snippet(snippet) => {
+                    self.validate_slots_in_nodes(&snippet.body, import_map, line_starts);
+                }
                 _ => {}
             }
         }
     }
 
-    fn emit_bundle(&self, entry_path: &Path) -> Result<String, anyhow::Error> {
-        let entry = entry_path.canonicalize()?;
+    /// Concatenates component CSS in dependency order, skipping duplicates.
+    fn emit_css(&self) -> String {
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut out = String::new();
+        for path in &self.compile_order {
+            let Some(component) = self.components_by_path.get(path) else {
+                continue;
+            };
+            let Some(css) = crate::codegen::generate_css(component) else {
+                continue;
+            };
+            let css = css.trim();
+            if css.is_empty() || !seen.insert(css.to_string()) {
+                continue;
+            }
+            let name = self
+                .component_names_by_path
+                .get(path)
+                .map(String::as_str)
+                .unwrap_or("Component");
+            out.push_str(&format!("/* {} */\n{}\n\n", name, css));
+        }
+        out
+    }
+
+    fn emit_bundle(
+        &self,
+        entry_path: &Path,
+        options: &CodegenOptions,
+    ) -> Result<(String, SourceMap), anyhow::Error> {
+        let entry = normalize_path(entry_path);
+        let entry_dir = entry.parent().unwrap_or(Path::new("."));
+        let mut map = SourceMap::new("bundle.js");
 
         let mut out = String::new();
         out.push_str("// Generated by lumix-js bundle\n");
-        out.push_str("import { h, hydrate as __hydrate, effect, signal, onMount, onDestroy, __if, __for } from 'lumix-js';\n\n");
+        out.push_str("import * as __LUMIX__ from 'lumix-js';\n");
+        out.push_str("import { effect, signal, onMount, onDestroy } from 'lumix-js';\n\n");
 
         // Stable ordering: sort by path display
         let mut items: Vec<_> = self.components_by_path.iter().collect();
@@ -315,7 +429,7 @@ impl GraphCompiler {
         }
         out.push('\n');
 
-        out.push_str("const __luminComponents = {};\n");
+        out.push_str("const __lumixComponents = {};\n");
 
         for (path, component) in items {
             let name = self
@@ -323,9 +437,14 @@ impl GraphCompiler {
                 .get(path)
                 .cloned()
                 .unwrap_or_else(|| "Component".into());
-            let js = crate::codegen::generate_component_factory_js(&name, component);
-            out.push_str(&js);
-            out.push_str("\n");
+            let source_name = path.strip_prefix(entry_dir).unwrap_or(path).display().to_string();
+            let source = self.sources_by_path.get(path).map(String::as_str).unwrap_or("");
+            let src = map.add_source(source_name.replace('\\', "/"), source);
+            let line_offset = out.matches('\n').count() as u32;
+            out.push_str(&map.add_generated(src, line_offset, || {
+                crate::codegen::generate_component_factory_js_marked(&name, component, options)
+            }));
+            out.push('\n');
         }
 
         let entry_name = self
@@ -334,17 +453,62 @@ impl GraphCompiler {
             .cloned()
             .unwrap_or_else(|| "App".into());
 
-        out.push_str("\n");
-        out.push_str(&format!(
-            "export const components = __luminComponents;\nexport function hydrate(root) {{ __hydrate(root, __luminComponents[\"{}\"], {{}}); }}\n",
-            entry_name
-        ));
+        out.push('\n');
+        out.push_str("export const components = __lumixComponents;\n");
+        if options.target == CodegenTarget::Ssr {
+            out.push_str(&format!(
+                "export function render(props) {{ return __lumixComponents[\"{}\"].render(props); }}\n",
+                entry_name
+            ));
+        } else {
+            out.push_str(&format!(
+                "export function hydrate(root) {{ __LUMIX__.hydrate(root, __lumixComponents[\"{}\"].default, {{}}); }}\n",
+                entry_name
+            ));
+        }
 
-        Ok(out)
+        Ok((out, map))
     }
 }
 
 
+/// `bind:name={signal}` on a child component: `name` must be a prop the
+/// child declares with `model()`.
+fn diagnose_component_binds(
+    el: &crate::ast::ElementNode,
+    child: &ComponentFile,
+    line_starts: &[usize],
+) -> Vec<Diagnostic> {
+    let props = child.script.as_ref().map(|s| s.props.as_slice()).unwrap_or_default();
+    let mut diags = Vec::new();
+    for attr in &el.attributes {
+        let crate::ast::AttributeNode::Bind { property, expr } = attr else {
+            continue;
+        };
+        if property == "this" {
+            continue;
+        }
+        let message = match props.iter().find(|p| p.name == *property) {
+            Some(prop) if prop.kind == PropKind::Model => continue,
+            Some(_) => format!(
+                "Prop '{}' of component '{}' is not bindable; declare it with model()",
+                property, el.tag_name
+            ),
+            None => format!("Component '{}' has no prop named '{}' to bind", el.tag_name, property),
+        };
+        let span = expr.span.or(el.tag_span).unwrap_or(crate::ast::SourceRange { start: 0, end: 0 });
+        let (start, end) = crate::diagnostic::range_to_line_cols(line_starts, span.start, span.end);
+        diags.push(Diagnostic {
+            severity: crate::diagnostic::DiagnosticSeverity::Error,
+            message,
+            range: to_diag_range(span),
+            start,
+            end,
+        });
+    }
+    diags
+}
+
 fn to_diag_range(r: crate::ast::SourceRange) -> crate::diagnostic::SourceRange {
     crate::diagnostic::SourceRange {
         start: r.start,
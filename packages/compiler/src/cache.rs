@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ast::ComponentFile;
+use crate::diagnostic::{compute_line_starts, Diagnostic};
+use crate::error::CompileError;
+use crate::parser::parse_component_with_errors;
+
+/// Parsed components kept by default before the least recently used is dropped.
+const DEFAULT_CAPACITY: usize = 512;
+
+/// Sources and parsed components kept between compiles (see `luminc serve`).
+///
+/// Editors can hand in unsaved text with `set_source`; it shadows the file on
+/// disk for every compile that reads that path, including bundle
+/// dependencies. Parsed components are reused while their source is unchanged.
+pub struct SourceCache {
+    overlay: HashMap<PathBuf, String>,
+    parsed: HashMap<PathBuf, Parsed>,
+    capacity: usize,
+    /// Bumped on every parse, to find the least recently used entry.
+    clock: u64,
+}
+
+/// Source, component and syntax errors of the last parse of a path.
+struct Parsed {
+    source: String,
+    component: ComponentFile,
+    errors: Vec<CompileError>,
+    last_used: u64,
+}
+
+impl Default for SourceCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl SourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps at most `capacity` parsed components.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            overlay: HashMap::new(),
+            parsed: HashMap::new(),
+            capacity: capacity.max(1),
+            clock: 0,
+        }
+    }
+
+    /// Whether a parse of `path` is still cached.
+    pub fn is_cached(&self, path: &Path) -> bool {
+        self.parsed.contains_key(&normalize_path(path))
+    }
+
+    /// Use `source` for `path` instead of reading the file.
+    pub fn set_source(&mut self, path: &Path, source: String) {
+        self.overlay.insert(normalize_path(path), source);
+    }
+
+    /// Go back to reading `path` from disk.
+    pub fn remove_source(&mut self, path: &Path) {
+        self.overlay.remove(&normalize_path(path));
+    }
+
+    /// Drops everything, in-memory sources included.
+    pub fn clear(&mut self) {
+        self.overlay.clear();
+        self.parsed.clear();
+    }
+
+    pub fn read(&self, path: &Path) -> std::io::Result<String> {
+        match self.overlay.get(&normalize_path(path)) {
+            Some(source) => Ok(source.clone()),
+            None => std::fs::read_to_string(path),
+        }
+    }
+
+    /// `parser::parse_component`, memoized per path.
+    pub fn parse(&mut self, path: &Path, source: &str) -> Result<ComponentFile, CompileError> {
+        let (component, errors) = self.parse_with_errors(path, source);
+        match errors.first() {
+            Some(err) => Err(err.clone()),
+            None => Ok(component.clone()),
+        }
+    }
+
+    /// `parser::parse_component_recovering`, memoized per path.
+    pub fn parse_recovering(&mut self, path: &Path, source: &str) -> (ComponentFile, Vec<Diagnostic>) {
+        let (component, errors) = self.parse_with_errors(path, source);
+        let line_starts = compute_line_starts(source);
+        let diagnostics = errors.iter().map(|err| err.to_diagnostic(&line_starts)).collect();
+        (component.clone(), diagnostics)
+    }
+
+    fn parse_with_errors(&mut self, path: &Path, source: &str) -> (&ComponentFile, &[CompileError]) {
+        let path = normalize_path(path);
+        self.clock += 1;
+        match self.parsed.get_mut(&path) {
+            Some(entry) if entry.source == source => entry.last_used = self.clock,
+            cached => {
+                if cached.is_none() && self.parsed.len() >= self.capacity {
+                    self.evict_least_recently_used();
+                }
+                let (component, errors) = parse_component_with_errors(source);
+                self.parsed.insert(
+                    path.clone(),
+                    Parsed {
+                        source: source.to_string(),
+                        component,
+                        errors,
+                        last_used: self.clock,
+                    },
+                );
+            }
+        }
+        let entry = &self.parsed[&path];
+        (&entry.component, &entry.errors)
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .parsed
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(path, _)| path.clone());
+        if let Some(path) = oldest {
+            self.parsed.remove(&path);
+        }
+    }
+}
+
+/// Canonical form of `path`. Files that only exist in memory are made
+/// absolute instead.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
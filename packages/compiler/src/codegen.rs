@@ -1,20 +1,138 @@
 use crate::ast::*;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use crate::css;
+use crate::hydrate_codegen;
+use crate::sourcemap::{self, SourceMap};
+use crate::ssr_codegen;
 use crate::transpiler::transpile_ts_snippet;
 use serde_json;
 
 // transpile_ts_to_js moved to transpiler::transpile_ts_snippet
 
+/// How a component's `<style>` reaches the page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CssOutput {
+    /// The component injects a `<style>` element into `document.head` when it runs.
+    #[default]
+    Injected,
+    /// The CSS is returned separately (see `generate_css`) and the generated
+    /// JS does not touch the DOM for styles.
+    External,
+}
+
+/// What the generated component functions produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodegenTarget {
+    /// DOM nodes built with `__LUMIX__.h` (the browser build).
+    #[default]
+    Dom,
+    /// An HTML string, for rendering on the server (see `ssr_codegen`).
+    Ssr,
+}
+
+/// What happens to `<!-- -->` comments in the template.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommentOutput {
+    /// Leave them out. Conditional comments (`<!--[if IE]>`) are kept.
+    #[default]
+    Strip,
+    /// Render them like any other node (server and client builds must agree
+    /// for hydration to adopt them).
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodegenOptions {
+    pub css: CssOutput,
+    pub target: CodegenTarget,
+    pub comments: CommentOutput,
+}
+
 pub fn generate_js(component: &ComponentFile, component_name: &str) -> String {
     generate_component_js_esm(component, component_name)
 }
 
+pub fn generate_js_with_options(
+    component: &ComponentFile,
+    component_name: &str,
+    options: &CodegenOptions,
+) -> String {
+    generate_component_js_esm_marked(component, component_name, options)
+}
+
+/// Like `generate_js_with_options`, but also returns a Source Map v3 mapping
+/// the output back to `source` (the `.lumix` text the component was parsed from).
+pub fn generate_js_with_source_map(
+    component: &ComponentFile,
+    component_name: &str,
+    source: &str,
+    source_name: &str,
+    options: &CodegenOptions,
+) -> (String, SourceMap) {
+    let mut map = SourceMap::new(format!("{}.js", component_name));
+    let src = map.add_source(source_name, source);
+    let js = map.add_generated(src, 0, || generate_component_js_esm_marked(component, component_name, options));
+    (js, map)
+}
+
 pub fn generate_component_js_esm(component: &ComponentFile, component_name: &str) -> String {
+    generate_js_with_options(component, component_name, &CodegenOptions::default())
+}
+
+/// The component's final CSS (scoped unless `<style global>`), if it has any.
+pub fn generate_css(component: &ComponentFile) -> Option<String> {
+    let style = component.style.as_ref()?;
+    if style.global {
+        Some(style.code.clone())
+    } else {
+        Some(css::scope_css(&style.code, &css::scope_attribute(&style.code)))
+    }
+}
+
+fn generate_component_js_esm_marked(
+    component: &ComponentFile,
+    component_name: &str,
+    options: &CodegenOptions,
+) -> String {
+    let component = &*with_comments(component, options.comments);
+    if options.target == CodegenTarget::Ssr {
+        return ssr_codegen::generate_ssr_component_js_esm_marked(component, component_name);
+    }
+
     let mut out = String::new();
 
     out.push_str("// Generated by LumixJS\n");
     out.push_str("import * as __LUMIX__ from 'lumix-js';\n");
 
+    out.push_str(&generate_imports(component));
+    
+    // Already transpiled in parser
+    let script_body = component.script.as_ref().map(script_code_with_marks).unwrap_or_default();
+    
+    out.push_str(&generate_component_body(component, true, &script_body, component_name, options));
+
+    // Export head metadata if present
+    if let Some(head) = &component.head {
+        out.push_str("\n// Head metadata\n");
+        out.push_str("export const head = ");
+        
+        // Serialize head to JSON
+        let head_json = serialize_head_metadata(head);
+        out.push_str(&head_json);
+        out.push_str(";\n");
+    }
+
+    out.push_str("\nexport function hydrate(root, props) {\n");
+    out.push_str(&format!("  __LUMIX__.hydrate(root, {}, props);\n", component_name));
+    out.push_str("}\n");
+
+    out
+}
+
+/// Hoisted `---` and `<script>` imports, followed by a blank line.
+pub(crate) fn generate_imports(component: &ComponentFile) -> String {
+    let mut out = String::new();
+
     // Hoist --- imports
     for imp in &component.imports {
         out.push_str("import ");
@@ -44,32 +162,11 @@ pub fn generate_component_js_esm(component: &ComponentFile, component_name: &str
         }
     }
     out.push('\n');
-    
-    let script_body_raw = component.script.as_ref().map(|s| s.code.as_str()).unwrap_or("");
-    // Already transpiled in parser
-    let script_body = script_body_raw.to_string();
-    
-    out.push_str(&generate_component_body(component, true, &script_body, component_name));
-
-    // Export head metadata if present
-    if let Some(head) = &component.head {
-        out.push_str("\n// Head metadata\n");
-        out.push_str("export const head = ");
-        
-        // Serialize head to JSON
-        let head_json = serialize_head_metadata(head);
-        out.push_str(&head_json);
-        out.push_str(";\n");
-    }
-
-    out.push_str("\nexport function hydrate(root, props) {\n");
-    out.push_str(&format!("  __LUMIX__.hydrate(root, {}, props);\n", component_name));
-    out.push_str("}\n");
 
     out
 }
 
-fn serialize_head_metadata(head: &HeadMetadata) -> String {
+pub(crate) fn serialize_head_metadata(head: &HeadMetadata) -> String {
     let mut parts = Vec::new();
     
     if let Some(title) = &head.title {
@@ -95,46 +192,93 @@ fn serialize_head_metadata(head: &HeadMetadata) -> String {
 }
 
 pub fn generate_component_factory_js(name: &str, component: &ComponentFile) -> String {
+    generate_component_factory_js_marked(name, component, &CodegenOptions::default())
+}
+
+/// Factory JS, with source map markers when run under `SourceMap::add_generated`.
+pub(crate) fn generate_component_factory_js_marked(
+    name: &str,
+    component: &ComponentFile,
+    options: &CodegenOptions,
+) -> String {
+    let component = &*with_comments(component, options.comments);
+    if options.target == CodegenTarget::Ssr {
+        return ssr_codegen::generate_ssr_component_factory_js_marked(name, component);
+    }
+
     let mut out = String::new();
 
     out.push_str(&format!("__lumixComponents[\"{}\"] = (function() {{\n", name));
     
-    let script_body_raw = component.script.as_ref().map(|s| s.code.as_str()).unwrap_or("");
     // Already transpiled in parser
-    let script_body = script_body_raw.to_string();
+    let script_body = component.script.as_ref().map(script_code_with_marks).unwrap_or_default();
 
     // Use "Component" as internal name for factory
     let fn_name = "Component";
-    out.push_str(&generate_component_body(component, false, &script_body, fn_name));
+    out.push_str(&generate_component_body(component, false, &script_body, fn_name, options));
     out.push_str(&format!("\n  return {{ default: {}, hydrate: (root, props) => __LUMIX__.hydrate(root, {}, props) }};\n", fn_name, fn_name));
     out.push_str("})();\n");
 
     out
 }
 
-fn generate_component_body(component: &ComponentFile, is_esm: bool, script_body: &str, fn_name: &str) -> String {
+/// Script code with a marker in front of each mapped statement.
+pub(crate) fn script_code_with_marks(script: &ScriptBlock) -> String {
+    let Some(span) = script.span else {
+        return script.code.clone();
+    };
+    let mut code = script.code.clone();
+    let mut mappings = script.mappings.clone();
+    mappings.sort_by_key(|m| std::cmp::Reverse(m.generated));
+    for m in mappings {
+        if code.is_char_boundary(m.generated) {
+            code.insert_str(m.generated, &sourcemap::mark(span.start + m.original));
+        }
+    }
+    code
+}
+
+pub(crate) fn mark(span: Option<SourceRange>) -> String {
+    span.map(|s| sourcemap::mark(s.start)).unwrap_or_default()
+}
+
+/// Prop destructuring, required-prop checks and the component's script body,
+/// indented for the inside of the component function.
+pub(crate) fn generate_script_prologue(component: &ComponentFile, script_body: &str, fn_name: &str) -> String {
     let mut out = String::new();
-    
-    out.push_str(&format!("function {}(props = {{}}) {{\n", fn_name));
 
     // Emit prop destructuring and validation
     if let Some(script) = &component.script {
         if !script.props.is_empty() {
             let mut destructuring = Vec::new();
             let mut required_props = Vec::new();
+            let mut models = Vec::new();
             for prop in &script.props {
-                if let Some(default) = &prop.default_value {
+                if prop.kind == PropKind::Model {
+                    let initial = prop.default_value.as_deref().map(transpile_ts_snippet);
+                    models.push(format!(
+                        "  const {} = __LUMIX__.__modelProp(props, '{}', {});\n",
+                        prop.name,
+                        prop.name,
+                        initial.as_deref().map(str::trim).unwrap_or("undefined")
+                    ));
+                } else if let Some(default) = &prop.default_value {
                     destructuring.push(format!("{} = {}", prop.name, transpile_ts_snippet(default).trim()));
                 } else {
                     destructuring.push(prop.name.clone());
                     required_props.push(prop.name.clone());
                 }
             }
-            out.push_str(&format!("  let {{ {} }} = props;\n", destructuring.join(", ")));
+            if !destructuring.is_empty() {
+                out.push_str(&format!("  let {{ {} }} = props;\n", destructuring.join(", ")));
+            }
             
             for prop_name in required_props {
                 out.push_str(&format!("  if ({} === undefined) throw new Error(\"LumixJS: Component '{}' missing required prop '{}'\");\n", prop_name, fn_name, prop_name));
             }
+            for model in models {
+                out.push_str(&model);
+            }
             out.push('\n');
         }
     }
@@ -151,33 +295,85 @@ fn generate_component_body(component: &ComponentFile, is_esm: bool, script_body:
         out.push('\n');
     }
 
-    if let Some(style) = &component.style {
+    out
+}
+
+fn generate_component_body(
+    component: &ComponentFile,
+    is_esm: bool,
+    script_body: &str,
+    fn_name: &str,
+    options: &CodegenOptions,
+) -> String {
+    let mut out = String::new();
+    
+    // `__c` is only passed when adopting server markup (see `hydrate_codegen`).
+    out.push_str(&format!("function {}(props = {{}}, __c) {{\n", fn_name));
+
+    out.push_str(&generate_script_prologue(component, script_body, fn_name));
+
+    // Scoped styles: rewrite selectors and stamp the matching attribute on
+    // every element this component renders.
+    let scoped_component;
+    let mut style_css = None;
+    let mut style_id = format!("lumix-style-{}", fn_name.to_lowercase());
+    let component = match &component.style {
+        Some(style) if !style.global => {
+            let attr = css::scope_attribute(&style.code);
+            scoped_component = with_scope_attribute(component, &attr);
+            style_css = Some(css::scope_css(&style.code, &attr));
+            style_id = format!("lumix-style-{}", &attr["data-lx-".len()..]);
+            &scoped_component
+        }
+        Some(style) => {
+            style_css = Some(style.code.clone());
+            component
+        }
+        None => component,
+    };
+
+    if options.css == CssOutput::External {
+        style_css = None;
+    }
+
+    if let Some(style_css) = &style_css {
         out.push_str("  // Styles\n");
         out.push_str("  if (typeof document !== 'undefined') {\n");
-        out.push_str(&format!("    const styleId = 'lumix-style-{}';\n", fn_name.to_lowercase()));
+        out.push_str(&format!("    const styleId = '{}';\n", style_id));
         out.push_str("    if (!document.getElementById(styleId)) {\n");
         out.push_str("      const s = document.createElement('style');\n");
         out.push_str("      s.id = styleId;\n");
-        out.push_str(&format!("      s.textContent = `{}`;\n", escape_backticks(&style.code)));
+        out.push_str(&format!("      s.textContent = `{}`;\n", escape_backticks(style_css)));
         out.push_str("      document.head.appendChild(s);\n");
         out.push_str("    }\n");
         out.push_str("  }\n\n");
     }
 
+    out.push_str("  if (__c instanceof __LUMIX__.HydrationCursor) {\n");
+    out.push_str(&hydrate_codegen::generate_hydrate_nodes(&component.template, 4, !is_esm));
+    out.push_str("    return __LUMIX__.HYDRATED;\n");
+    out.push_str("  }\n\n");
+
+    let snippets = snippet_declarations(&component.template, 2, !is_esm);
+    if !snippets.is_empty() {
+        out.push_str(&format!("  {}\n", snippets.trim_end()));
+    }
+    let roots: Vec<&TemplateNode> =
+        component.template.iter().filter(|n| !matches!(n, TemplateNode::Snippet(_))).collect();
     out.push_str("  return ");
-    if component.template.len() == 1 {
+    if roots.len() == 1 {
         // Single root: return that node directly.
-        out.push_str(&generate_node_h(&component.template[0], 2, !is_esm, false));
+        out.push_str(&generate_node_h(roots[0], 2, !is_esm, false));
     } else {
         // Multiple roots: use Fragment so we don't introduce an extra wrapper div.
         out.push_str("__LUMIX__.h(__LUMIX__.Fragment, null, ");
-        for (i, node) in component.template.iter().enumerate() {
+        for (i, node) in roots.into_iter().enumerate() {
             if i > 0 {
                 out.push_str(", ");
             }
             out.push_str(&generate_node_h(node, 2, !is_esm, false));
         }
-        out.push_str(")");
+        out.push(')');
     }
     out.push_str(";\n");
     out.push_str("}\n");
@@ -194,15 +390,460 @@ fn generate_component_body(component: &ComponentFile, is_esm: bool, script_body:
     out
 }
 
-fn generate_node_h(node: &TemplateNode, indent: usize, is_bundle: bool, strip_slot_attr: bool) -> String {
+/// `component` without its template comments, unless they are kept.
+pub(crate) fn with_comments(component: &ComponentFile, comments: CommentOutput) -> Cow<'_, ComponentFile> {
+    if comments == CommentOutput::Keep {
+        return Cow::Borrowed(component);
+    }
+    let mut c = component.clone();
+    strip_comments(&mut c.template);
+    Cow::Owned(c)
+}
+
+fn strip_comments(nodes: &mut Vec<TemplateNode>) {
+    nodes.retain(|node| !matches!(node, TemplateNode::Comment(text) if !is_conditional_comment(text)));
+    for node in nodes {
+        match node {
+            TemplateNode::Element(el) => strip_comments(&mut el.children),
+            TemplateNode::Slot(slot) => strip_comments(&mut slot.fallback),
+            TemplateNode::ControlFlow(ControlFlowBlock::If {
+                then_branch,
+                else_ifs,
+                else_branch,
+                ..
+            }) => {
+                strip_comments(then_branch);
+                for (_, branch) in else_ifs {
+                    strip_comments(branch);
+                }
+                if let Some(branch) = else_branch {
+                    strip_comments(branch);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::For { body, .. }) => strip_comments(body),
+            TemplateNode::ControlFlow(ControlFlowBlock::Await {
+                pending,
+                then_branch,
+                catch_branch,
+                ..
+            }) => {
+                strip_comments(pending);
+                strip_comments(then_branch);
+                if let Some(branch) = catch_branch {
+                    strip_comments(branch);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::Switch { cases, default, .. }) => {
+                for (_, branch) in cases {
+                    strip_comments(branch);
+                }
+                if let Some(branch) = default {
+                    strip_comments(branch);
+                }
+            }
+            TemplateNode::Snippet(snippet) => strip_comments(&mut snippet.body),
+            TemplateNode::Text(_)
+            | TemplateNode::Expr(_)
+            | TemplateNode::Html(_)
+            | TemplateNode::Render(_)
+            | TemplateNode::Comment(_)
+            | TemplateNode::Declaration(_)
+            | TemplateNode::Const(_) => {}
+        }
+    }
+}
+
+/// A copy of `component` whose native elements carry the scope attribute.
+pub(crate) fn with_scope_attribute(component: &ComponentFile, attr: &str) -> ComponentFile {
+    let mut c = component.clone();
+    css::stamp_scope_attribute(&mut c.template, attr);
+    c
+}
+
+/// Parameters of the per-item render function of a `@{for}` block:
+/// `binding` or `binding, index`.
+pub(crate) fn for_item_params(binding: &str, index: Option<&String>) -> String {
+    match index {
+        Some(index) => format!("{}, {}", binding, index),
+        None => binding.to_string(),
+    }
+}
+
+/// `(binding) => [...]`: one branch of an `@{await}` block.
+fn await_branch_h(binding: Option<&str>, nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    format!("({}) => {}", binding.unwrap_or(""), branch_h(nodes, indent, is_bundle))
+}
+
+/// `const name = value; ` for each `{@const}` tag of a branch.
+pub(crate) fn const_declarations(nodes: &[TemplateNode]) -> String {
+    let mut s = String::new();
+    for node in nodes {
+        if let TemplateNode::Const(tag) = node {
+            s.push_str(&format!(
+                "const {} = {}{}; ",
+                tag.binding,
+                mark(tag.expr.span),
+                transpile_ts_snippet(&tag.expr.code).trim()
+            ));
+        }
+    }
+    s
+}
+
+/// `const name = ...; ` for each snippet defined in `nodes`.
+pub(crate) fn snippet_declarations(nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    let mut s = String::new();
+    for node in nodes {
+        if let TemplateNode::Snippet(snippet) = node {
+            s.push_str(&format!(
+                "const {}{} = {}; ",
+                mark(snippet.name_span),
+                snippet.name,
+                snippet_h(snippet, indent, is_bundle)
+            ));
+        }
+    }
+    s
+}
+
+/// A snippet's parameters without their type annotations.
+pub(crate) fn snippet_params(params: &str) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let arrow = transpile_ts_snippet(&format!("({}) => {{}}", params));
+    let params = arrow.rsplit_once("=>").map_or(params, |(params, _)| params.trim());
+    params
+        .strip_prefix('(')
+        .and_then(|p| p.strip_suffix(')'))
+        .unwrap_or(params)
+        .to_string()
+}
+
+/// `__LUMIX__.__snippet((params) => [...], (__c, params) => { ... })`: the
+/// render function of a snippet, with the one adopting its server markup.
+pub(crate) fn snippet_h(snippet: &SnippetNode, indent: usize, is_bundle: bool) -> String {
+    let params = snippet_params(&snippet.params);
+    let hydrate_params = if params.is_empty() { "__c".to_string() } else { format!("__c, {}", params) };
+    format!(
+        "__LUMIX__.__snippet(({}) => {}, ({}) => {{\n{}{}}})",
+        params,
+        branch_h(&snippet.body, indent, is_bundle),
+        hydrate_params,
+        hydrate_codegen::generate_hydrate_nodes(&snippet.body, indent + 2, is_bundle),
+        " ".repeat(indent)
+    )
+}
+
+/// `row(a, b)`: the call made by a `{@render}` tag.
+pub(crate) fn render_call(tag: &RenderTag) -> String {
+    let args: Vec<&str> = tag.args.iter().map(|arg| arg.code.as_str()).collect();
+    transpile_ts_snippet(&format!("{}({})", tag.snippet.code, args.join(", ")))
+}
+
+/// The slot passed as a snippet: the snippet itself, which can take arguments.
+pub(crate) fn slot_snippet(nodes: &[TemplateNode]) -> Option<&SnippetNode> {
+    match nodes {
+        [TemplateNode::Snippet(snippet)] => Some(snippet),
+        _ => None,
+    }
+}
+
+/// Body of the arrow function rendering a control-flow branch: the array of
+/// its nodes, in a block declaring its `{@const}` tags and snippets first if
+/// it has any.
+pub(crate) fn branch_h(nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    let mut declarations = const_declarations(nodes);
+    declarations.push_str(&snippet_declarations(nodes, indent, is_bundle));
+    let (mut s, close) = if declarations.is_empty() {
+        ("[\n".to_string(), "]")
+    } else {
+        (format!("{{ {}return [\n", declarations), "]; }")
+    };
+    for child in nodes.iter().filter(|n| !matches!(n, TemplateNode::Const(_) | TemplateNode::Snippet(_))) {
+        s.push_str(&" ".repeat(indent + 2));
+        s.push_str(&generate_node_h(child, indent + 2, is_bundle, false));
+        s.push_str(",\n");
+    }
+    s.push_str(&" ".repeat(indent));
+    s.push_str(close);
+    s
+}
+
+/// `params` as the parameter list of an arrow function: `item` stays bare,
+/// anything else (`{ id }`, `item, i`) is parenthesized.
+pub(crate) fn arrow_params(params: &str) -> String {
+    if params.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$') {
+        params.to_string()
+    } else {
+        format!("({})", params)
+    }
+}
+
+/// Children of a component element grouped by slot: the default slot, then
+/// named slots (`slot="name"` on a child element, or a snippet) sorted by
+/// name.
+pub(crate) fn split_slot_children(el: &ElementNode) -> (Vec<TemplateNode>, Vec<(String, Vec<TemplateNode>)>) {
+    let mut default_children = Vec::new();
+    let mut slots: Vec<(String, Vec<TemplateNode>)> = Vec::new();
+    for child in &el.children {
+        let slot_name = match child {
+            TemplateNode::Element(child_el) => child_el.attributes.iter().find_map(|a| match a {
+                AttributeNode::Static { name, value } if name == "slot" => Some(value.clone()),
+                _ => None,
+            }),
+            TemplateNode::Snippet(snippet) => Some(snippet.name.clone()),
+            _ => None,
+        };
+        match slot_name {
+            Some(name) => match slots.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, nodes)) => nodes.push(child.clone()),
+                None => slots.push((name, vec![child.clone()])),
+            },
+            None => default_children.push(child.clone()),
+        }
+    }
+    slots.sort_by(|a, b| a.0.cmp(&b.0));
+    (default_children, slots)
+}
+
+/// The listener for an `on*` attribute. Modifiers that act on the event wrap
+/// the handler; `once`, `capture` and `passive` become listener options.
+pub(crate) fn event_handler_value(code: &str, modifiers: &[EventModifier]) -> String {
+    let handler = transpile_ts_snippet(code).trim().to_string();
+    if modifiers.is_empty() {
+        return handler;
+    }
+
+    let mut value = handler.clone();
+    let mut body = String::new();
+    for modifier in modifiers {
+        match modifier {
+            EventModifier::Self_ => body.push_str("if (e.target !== e.currentTarget) return; "),
+            EventModifier::Trusted => body.push_str("if (!e.isTrusted) return; "),
+            _ => {}
+        }
+    }
+    for modifier in modifiers {
+        match modifier {
+            EventModifier::PreventDefault => body.push_str("e.preventDefault(); "),
+            EventModifier::StopPropagation => body.push_str("e.stopPropagation(); "),
+            EventModifier::StopImmediatePropagation => body.push_str("e.stopImmediatePropagation(); "),
+            _ => {}
+        }
+    }
+    if !body.is_empty() {
+        value = format!("function (e) {{ {}return ({}).call(this, e); }}", body, handler);
+    }
+
+    let options: Vec<String> = modifiers
+        .iter()
+        .filter(|m| m.is_listener_option())
+        .map(|m| format!("{}: true", m.name()))
+        .collect();
+    if !options.is_empty() {
+        value = format!("__LUMIX__.listener({}, {{ {} }})", value, options.join(", "));
+    }
+    value
+}
+
+/// The value of a `bind:property={expr}` entry: the signal itself, or for
+/// `bind:this` a setter that stores the node in a signal or a variable.
+pub(crate) fn bind_value(property: &str, code: &str) -> String {
+    let target = transpile_ts_snippet(code).trim().to_string();
+    if property == "this" {
+        format!(
+            "(__v) => {{ if (__LUMIX__.isSignal({0})) {0}(__v); else {0} = __v; }}",
+            target
+        )
+    } else {
+        target
+    }
+}
+
+/// The props entry for the `index`-th `{...expr}` of an element. The runtime
+/// recognizes the `...` key prefix and merges the object in its place.
+pub(crate) fn spread_prop(index: &mut usize, expr: &JsExpr) -> String {
+    let entry = format!(
+        "{}'...{}': () => ({})",
+        mark(expr.span),
+        index,
+        transpile_ts_snippet(&expr.code).trim()
+    );
+    *index += 1;
+    entry
+}
+
+/// The props entry for `use:action={params}`: the action and a closure
+/// reading its parameters, so the runtime can re-run `update` when they change.
+pub(crate) fn action_prop(action: &JsExpr, params: Option<&JsExpr>) -> String {
+    let params = match params {
+        Some(expr) => format!(
+            ", {}() => ({})",
+            mark(expr.span),
+            transpile_ts_snippet(&expr.code).trim()
+        ),
+        None => String::new(),
+    };
+    format!("{}'use:{}': [{}{}]", mark(action.span), action.code, action.code, params)
+}
+
+pub(crate) fn is_component_tag(tag_name: &str) -> bool {
+    tag_name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_uppercase())
+        .unwrap_or(false)
+}
+
+/// The props argument `__LUMIX__.h` gets for `el`: `null`, or an object with
+/// its attributes and, for components, the `children`/`slots` render functions.
+pub(crate) fn generate_props_object(
+    el: &ElementNode,
+    indent: usize,
+    is_bundle: bool,
+    strip_slot_attr: bool,
+) -> String {
+    let mut s = String::new();
+    let is_component = is_component_tag(&el.tag_name);
+
+    let (default_children, slots) = if is_component {
+        split_slot_children(el)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    // Attributes and Slots
+    if el.attributes.is_empty() && slots.is_empty() && default_children.is_empty() {
+        s.push_str("null");
+    } else {
+        s.push_str("{\n");
+        let mut spreads = 0;
+        for (i, attr) in el.attributes.iter().enumerate() {
+            s.push_str(&" ".repeat(indent + 2));
+            match attr {
+                AttributeNode::Static { name, value } => {
+                    if strip_slot_attr && name == "slot" {
+                        continue;
+                    }
+                    s.push_str(&format!("'{}': '{}'", name, escape_single_quotes(value)));
+                }
+                AttributeNode::Dynamic { name, expr } => {
+                    s.push_str(&mark(expr.span));
+                    s.push_str(&format!("'{}': {}", name, dynamic_prop_value(&expr.code)));
+                }
+                AttributeNode::EventHandler { name, expr, modifiers } => {
+                    s.push_str(&mark(expr.span));
+                    s.push_str(&format!("'{}': {}", name, event_handler_value(&expr.code, modifiers)));
+                }
+                AttributeNode::Bind { property, expr } => {
+                    s.push_str(&mark(expr.span));
+                    s.push_str(&format!("'bind:{}': {}", property, bind_value(property, &expr.code)));
+                }
+                AttributeNode::Spread { expr } => s.push_str(&spread_prop(&mut spreads, expr)),
+                AttributeNode::Class { name, expr } => {
+                    s.push_str(&mark(expr.span));
+                    s.push_str(&format!("'class:{}': {}", name, dynamic_prop_value(&expr.code)));
+                }
+                AttributeNode::Style { property, expr } => {
+                    s.push_str(&mark(expr.span));
+                    s.push_str(&format!("'style:{}': {}", property, dynamic_prop_value(&expr.code)));
+                }
+                AttributeNode::Action { action, params } => s.push_str(&action_prop(action, params.as_ref())),
+            }
+            if i < el.attributes.len() - 1 || !slots.is_empty() || !default_children.is_empty() {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+
+        if is_component {
+            // Default slot (children)
+            if !default_children.is_empty() {
+                s.push_str(&" ".repeat(indent + 2));
+                s.push_str("'children': () => [\n");
+                for (i, child) in default_children.iter().enumerate() {
+                    s.push_str(&" ".repeat(indent + 4));
+                    s.push_str(&generate_node_h(child, indent + 4, is_bundle, true));
+                    if i < default_children.len() - 1 {
+                        s.push(',');
+                    }
+                    s.push('\n');
+                }
+                s.push_str(&" ".repeat(indent + 2));
+                s.push(']');
+                if !slots.is_empty() {
+                    s.push(',');
+                }
+                s.push('\n');
+            }
+
+            // Named slots
+            if !slots.is_empty() {
+                s.push_str(&" ".repeat(indent + 2));
+                s.push_str("'slots': {\n");
+                for (sk_idx, (name, children)) in slots.iter().enumerate() {
+                    s.push_str(&" ".repeat(indent + 4));
+                    if let Some(snippet) = slot_snippet(children) {
+                        s.push_str(&format!("'{}': {}", name, snippet_h(snippet, indent + 4, is_bundle)));
+                        if sk_idx < slots.len() - 1 {
+                            s.push(',');
+                        }
+                        s.push('\n');
+                        continue;
+                    }
+                    s.push_str(&format!("'{}': () => [\n", name));
+                    for (i, child) in children.iter().enumerate() {
+                        s.push_str(&" ".repeat(indent + 6));
+                        s.push_str(&generate_node_h(child, indent + 6, is_bundle, true));
+                        if i < children.len() - 1 {
+                            s.push(',');
+                        }
+                        s.push('\n');
+                    }
+                    s.push_str(&" ".repeat(indent + 4));
+                    s.push(']');
+                    if sk_idx < slots.len() - 1 {
+                        s.push(',');
+                    }
+                    s.push('\n');
+                }
+                s.push_str(&" ".repeat(indent + 2));
+                s.push_str("}\n");
+            }
+        }
+
+        s.push_str(&format!("{}}}", " ".repeat(indent)));
+    }
+
+    s
+}
+
+pub(crate) fn generate_node_h(node: &TemplateNode, indent: usize, is_bundle: bool, strip_slot_attr: bool) -> String {
     let mut s = String::new();
     match node {
         TemplateNode::Text(t) => {
             s.push_str(&format!("`{}`", escape_backticks(t)));
         }
+        TemplateNode::Comment(text) => {
+            s.push_str(&format!("document.createComment(`{}`)", escape_backticks(text)));
+        }
+        // A doctype has no place inside the element the client renders into.
+        TemplateNode::Declaration(_) => s.push_str("null"),
+        // Declared by `branch_h`.
+        TemplateNode::Const(_) | TemplateNode::Snippet(_) => s.push_str("null"),
+        TemplateNode::Render(tag) => {
+            s.push_str(&mark(tag.snippet.span));
+            s.push_str(&format!("() => ({})", render_call(tag)));
+        }
         TemplateNode::Expr(expr) => {
+            s.push_str(&mark(expr.span));
             s.push_str(&format!("() => ({})", transpile_ts_snippet(&expr.code).trim()));
         }
+        TemplateNode::Html(expr) => {
+            s.push_str(&mark(expr.span));
+            s.push_str(&format!("__LUMIX__.__html(() => ({}))", transpile_ts_snippet(&expr.code).trim()));
+        }
         TemplateNode::Slot(slot) => {
             let is_default = slot.name.is_none();
             let mut fallback_js = String::from("[]");
@@ -236,92 +877,120 @@ fn generate_node_h(node: &TemplateNode, indent: usize, is_bundle: bool, strip_sl
                     else_branch,
                 } => {
                     s.push_str("__LUMIX__.__if(() => (");
+                    s.push_str(&mark(condition.span));
                     s.push_str(transpile_ts_snippet(&condition.code).trim());
                     s.push_str("), [\n");
                     
                     // Main branch
                     s.push_str(&" ".repeat(indent + 2));
-                    s.push_str("{ body: () => [\n");
-                    for child in then_branch {
-                        s.push_str(&" ".repeat(indent + 4));
-                        s.push_str(&generate_node_h(child, indent + 4, is_bundle, false));
-                        s.push_str(",\n");
-                    }
-                    s.push_str(&" ".repeat(indent + 2));
-                    s.push_str("] },\n");
+                    s.push_str("{ body: () => ");
+                    s.push_str(&branch_h(then_branch, indent + 2, is_bundle));
+                    s.push_str(" },\n");
                     // Else-ifs
                     for (cond, branch) in else_ifs {
                         s.push_str(&" ".repeat(indent + 2));
                         s.push_str("{ cond: () => (");
+                        s.push_str(&mark(cond.span));
                         s.push_str(transpile_ts_snippet(&cond.code).trim());
-                        s.push_str("), body: () => [\n");
-                        for child in branch {
-                            s.push_str(&" ".repeat(indent + 4));
-                            s.push_str(&generate_node_h(child, indent + 4, is_bundle, false));
-                            s.push_str(",\n");
-                        }
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("] },\n");
+                        s.push_str("), body: () => ");
+                        s.push_str(&branch_h(branch, indent + 2, is_bundle));
+                        s.push_str(" },\n");
                     }
 
                     // Else
                     if let Some(branch) = else_branch {
                         s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("{ body: () => [\n");
-                        for child in branch {
-                            s.push_str(&" ".repeat(indent + 4));
-                            s.push_str(&generate_node_h(child, indent + 4, is_bundle, false));
-                            s.push_str(",\n");
-                        }
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("] },\n");
+                        s.push_str("{ body: () => ");
+                        s.push_str(&branch_h(branch, indent + 2, is_bundle));
+                        s.push_str(" },\n");
                     }
 
                     s.push_str(&" ".repeat(indent));
                     s.push_str("])");
                 }
-                ControlFlowBlock::For { params, key_expr, body } => {
-                    // Primitive parsing of "let item of list"
-                    let parts: Vec<&str> = params.split(" of ").collect();
-                    if parts.len() == 2 {
-                        let item_part = parts[0].trim()
-                            .replace("let ", "")
-                            .replace("const ", "")
-                            .replace("var ", "")
-                                .trim()
-                            .to_string();
-                        let list_part = transpile_ts_snippet(parts[1].trim());
-
-                        s.push_str(&format!("__LUMIX__.__for(() => ({}), {} => [\n", list_part.trim(), item_part));
-                        for child in body {
-                            s.push_str(&" ".repeat(indent + 2));
-                            s.push_str(&generate_node_h(child, indent + 2, is_bundle, false));
-                            s.push_str(",\n");
-                        }
-                        s.push_str(&" ".repeat(indent));
-                        
-                        if let Some(key) = key_expr {
-                            s.push_str(&format!("], {} => ({}))", item_part, transpile_ts_snippet(key).trim()));
-                        } else {
-                            s.push_str("])");
-                        }
+                ControlFlowBlock::For {
+                    binding,
+                    iterable,
+                    index,
+                    key_expr,
+                    body,
+                } => {
+                    s.push_str(&format!(
+                        "__LUMIX__.__for(() => ({}{}), {} => {}",
+                        mark(iterable.span),
+                        transpile_ts_snippet(&iterable.code).trim(),
+                        arrow_params(&for_item_params(binding, index.as_ref())),
+                        branch_h(body, indent, is_bundle)
+                    ));
+
+                    if let Some(key) = key_expr {
+                        s.push_str(&format!(", {} => ({}))", arrow_params(binding), transpile_ts_snippet(key).trim()));
                     } else {
-                        s.push_str(&format!("`[Invalid For syntax: {}]`", params));
+                        s.push(')');
                     }
                 }
+                ControlFlowBlock::Await {
+                    promise,
+                    pending,
+                    then_binding,
+                    then_branch,
+                    catch_binding,
+                    catch_branch,
+                } => {
+                    s.push_str(&format!(
+                        "__LUMIX__.__await(() => ({}{}), ",
+                        mark(promise.span),
+                        transpile_ts_snippet(&promise.code).trim()
+                    ));
+                    s.push_str(&await_branch_h(None, pending, indent, is_bundle));
+                    s.push_str(", ");
+                    s.push_str(&await_branch_h(then_binding.as_deref(), then_branch, indent, is_bundle));
+                    if let Some(branch) = catch_branch {
+                        s.push_str(", ");
+                        s.push_str(&await_branch_h(catch_binding.as_deref(), branch, indent, is_bundle));
+                    }
+                    s.push(')');
+                }
+                ControlFlowBlock::Switch {
+                    discriminant,
+                    cases,
+                    default,
+                } => {
+                    s.push_str(&format!(
+                        "__LUMIX__.__switch(() => ({}{}), [\n",
+                        mark(discriminant.span),
+                        transpile_ts_snippet(&discriminant.code).trim()
+                    ));
+                    // The default branch goes last, whatever its position.
+                    let branches = cases
+                        .iter()
+                        .map(|(value, branch)| (Some(value), branch))
+                        .chain(default.iter().map(|branch| (None, branch)));
+                    for (value, branch) in branches {
+                        s.push_str(&" ".repeat(indent + 2));
+                        s.push('{');
+                        if let Some(value) = value {
+                            s.push_str(&format!(
+                                " value: () => ({}{}),",
+                                mark(value.span),
+                                transpile_ts_snippet(&value.code).trim()
+                            ));
+                        }
+                        s.push_str(" body: () => ");
+                        s.push_str(&branch_h(branch, indent + 2, is_bundle));
+                        s.push_str(" },\n");
+                    }
+                    s.push_str(&" ".repeat(indent));
+                    s.push_str("])");
+                }
             }
         }
         TemplateNode::Element(el) => {
-            let is_component = el
-                .tag_name
-                .chars()
-                .next()
-                .map(|c| c.is_ascii_uppercase())
-                .unwrap_or(false);
+            let is_component = is_component_tag(&el.tag_name);
 
             let tag = if is_component {
                 if is_bundle {
-                    format!("__luminComponents['{}'].default", el.tag_name)
+                    format!("__lumixComponents['{}'].default", el.tag_name)
                 } else {
                     el.tag_name.clone()
                 }
@@ -329,137 +998,10 @@ fn generate_node_h(node: &TemplateNode, indent: usize, is_bundle: bool, strip_sl
                 format!("'{}'", el.tag_name)
             };
 
+            s.push_str(&mark(el.tag_span));
             s.push_str(&format!("__LUMIX__.h({}, ", tag));
 
-            let mut slots: HashMap<String, Vec<&TemplateNode>> = HashMap::new();
-            let mut default_children: Vec<&TemplateNode> = Vec::new();
-
-            if is_component {
-                for child in &el.children {
-                    let mut assigned_slot = None;
-                    if let TemplateNode::Element(child_el) = child {
-                        for attr in &child_el.attributes {
-                            if let AttributeNode::Static { name, value } = attr {
-                                if name == "slot" {
-                                    assigned_slot = Some(value.clone());
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    
-                    if let Some(name) = assigned_slot {
-                        slots.entry(name).or_default().push(child);
-                    } else {
-                        default_children.push(child);
-                    }
-                }
-            }
-
-            // Attributes and Slots
-            if el.attributes.is_empty() && slots.is_empty() && default_children.is_empty() {
-                s.push_str("null");
-            } else {
-                s.push_str("{\n");
-                for (i, attr) in el.attributes.iter().enumerate() {
-                    s.push_str(&" ".repeat(indent + 2));
-                    match attr {
-                        AttributeNode::Static { name, value } => {
-                            if strip_slot_attr && name == "slot" {
-                                continue;
-                            }
-                            s.push_str(&format!("'{}': '{}'", name, escape_backticks(value)));
-                        }
-                        AttributeNode::Dynamic { name, expr } => {
-                            let t = transpile_ts_snippet(&expr.code);
-                            let e = t.trim();
-                            
-                            // Check if the expression is a simple literal (number, string, boolean, null, undefined)
-                            // If so, don't wrap it in a function - just use the value directly
-                            let is_literal = e.parse::<f64>().is_ok() // number
-                                || e == "true" || e == "false" // boolean
-                                || e == "null" || e == "undefined" // null/undefined
-                                || (e.starts_with('"') && e.ends_with('"')) // string literal
-                                || (e.starts_with('\'') && e.ends_with('\'')); // string literal
-                            
-                            if is_literal {
-                                // For literals, pass the value directly without wrapping
-                                s.push_str(&format!("'{}': {}", name, e));
-                            } else {
-                                // For variables/expressions, wrap to auto-unwrap signals/functions
-                                // This makes `value={name}` behave like `value={name()}` when `name` is a Signal
-                                s.push_str(&format!(
-                                    "'{}': () => {{ const __v = ({}); return (typeof __v === 'function') ? __v() : __v; }}",
-                                    name, e
-                                ));
-                            }
-                        }
-                        AttributeNode::EventHandler { name, expr } => {
-                            s.push_str(&format!("'{}': {}", name, transpile_ts_snippet(&expr.code).trim()));
-                        }
-                        AttributeNode::Bind { property, expr } => {
-                            s.push_str(&format!("'bind:{}': {}", property, transpile_ts_snippet(&expr.code).trim()));
-                        }
-                    }
-                    if i < el.attributes.len() - 1 || !slots.is_empty() || !default_children.is_empty() {
-                        s.push(',');
-                    }
-                    s.push('\n');
-                }
-
-                if is_component {
-                    // Default slot (children)
-                    if !default_children.is_empty() {
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("'children': () => [\n");
-                        for (i, child) in default_children.iter().enumerate() {
-                            s.push_str(&" ".repeat(indent + 4));
-                            s.push_str(&generate_node_h(child, indent + 4, is_bundle, true));
-                            if i < default_children.len() - 1 {
-                                s.push(',');
-                            }
-                            s.push('\n');
-                        }
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("]");
-                        if !slots.is_empty() {
-                            s.push(',');
-                        }
-                        s.push('\n');
-                    }
-
-                    // Named slots
-                    if !slots.is_empty() {
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("'slots': {\n");
-                        let mut slot_keys: Vec<_> = slots.keys().collect();
-                        slot_keys.sort();
-                        for (sk_idx, name) in slot_keys.iter().enumerate() {
-                            let children = &slots[*name];
-                            s.push_str(&" ".repeat(indent + 4));
-                            s.push_str(&format!("'{}': () => [\n", name));
-                            for (i, child) in children.iter().enumerate() {
-                                s.push_str(&" ".repeat(indent + 6));
-                                s.push_str(&generate_node_h(child, indent + 6, is_bundle, true));
-                                if i < children.len() - 1 {
-                                    s.push(',');
-                                }
-                                s.push('\n');
-                            }
-                            s.push_str(&" ".repeat(indent + 4));
-                            s.push_str("]");
-                            if sk_idx < slot_keys.len() - 1 {
-                                s.push(',');
-                            }
-                            s.push('\n');
-                        }
-                        s.push_str(&" ".repeat(indent + 2));
-                        s.push_str("}\n");
-                    }
-                }
-
-                s.push_str(&format!("{}}}", " ".repeat(indent)));
-            }
+            s.push_str(&generate_props_object(el, indent, is_bundle, strip_slot_attr));
 
             // For non-components, children still go as the third argument
             if !is_component && !el.children.is_empty() {
@@ -481,6 +1023,44 @@ fn generate_node_h(node: &TemplateNode, indent: usize, is_bundle: bool, strip_sl
     s
 }
 
+/// JS for a dynamic attribute/prop value. Literals are passed as is; anything
+/// else is wrapped so `value={name}` behaves like `value={name()}` when `name`
+/// is a Signal.
+pub(crate) fn dynamic_prop_value(code: &str) -> String {
+    let t = transpile_ts_snippet(code);
+    let e = t.trim();
+    
+    // Check if the expression is a simple literal (number, string, boolean, null, undefined)
+    // If so, don't wrap it in a function - just use the value directly
+    let is_literal = e.parse::<f64>().is_ok() // number
+        || e == "true" || e == "false" // boolean
+        || e == "null" || e == "undefined" // null/undefined
+        || (e.starts_with('"') && e.ends_with('"')) // string literal
+        || (e.starts_with('\'') && e.ends_with('\'')); // string literal
+    
+    if is_literal {
+        // For literals, pass the value directly without wrapping
+        e.to_string()
+    } else {
+        // For variables/expressions, wrap to auto-unwrap signals/functions
+        format!("() => {{ const __v = ({}); return (typeof __v === 'function') ? __v() : __v; }}", e)
+    }
+}
+
 fn escape_backticks(input: &str) -> String {
-    input.replace('`', "\\`").replace('$', "\\$")
+    input.replace('\\', "\\\\").replace('`', "\\`").replace('$', "\\$")
+}
+
+/// `s` as a double-quoted JS string literal.
+pub(crate) fn js_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| "\"\"".into())
+}
+
+/// `input` as the body of a single-quoted JS string.
+fn escape_single_quotes(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
 }
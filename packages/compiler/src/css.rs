@@ -0,0 +1,457 @@
+use crate::ast::{AttributeNode, ControlFlowBlock, TemplateNode};
+
+/// At-rules whose block contains regular style rules that need scoping.
+/// Anything else (`@keyframes`, `@font-face`, `@page`, ...) is copied as is.
+const NESTED_RULE_AT_RULES: &[&str] = &["media", "supports", "container", "layer", "document"];
+
+/// Legacy pseudo-elements that may be written with a single colon.
+const LEGACY_PSEUDO_ELEMENTS: &[&str] = &[":before", ":after", ":first-line", ":first-letter"];
+
+/// Attribute stamped on every element of a component with a scoped `<style>`,
+/// e.g. `data-lx-1a2b3c`. The hash only depends on the CSS text, so it is
+/// stable across builds.
+pub fn scope_attribute(css: &str) -> String {
+    // FNV-1a
+    let mut hash: u32 = 0x811c9dc5;
+    for b in css.bytes() {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("data-lx-{:06x}", hash & 0xff_ffff)
+}
+
+/// Rewrites every selector in `css` so it only matches elements carrying
+/// `attr`. `:global(...)` parts are unwrapped and left unscoped, as are
+/// `:root`, `html` and `body`.
+pub fn scope_css(css: &str, attr: &str) -> String {
+    let mut out = String::with_capacity(css.len() + css.len() / 4);
+    scope_rules(css, &format!("[{attr}]"), &mut out);
+    out
+}
+
+/// Adds `attr` (with an empty value) to every native element in `nodes`.
+pub fn stamp_scope_attribute(nodes: &mut [TemplateNode], attr: &str) {
+    for node in nodes {
+        match node {
+            TemplateNode::Element(el) => {
+                let is_component = el
+                    .tag_name
+                    .chars()
+                    .next()
+                    .map(|c| c.is_ascii_uppercase())
+                    .unwrap_or(false);
+                if !is_component {
+                    el.attributes.push(AttributeNode::Static {
+                        name: attr.to_string(),
+                        value: String::new(),
+                    });
+                }
+                stamp_scope_attribute(&mut el.children, attr);
+            }
+            TemplateNode::ControlFlow(cf) => match cf {
+                ControlFlowBlock::If {
+                    then_branch,
+                    else_ifs,
+                    else_branch,
+                    ..
+                } => {
+                    stamp_scope_attribute(then_branch, attr);
+                    for (_, branch) in else_ifs {
+                        stamp_scope_attribute(branch, attr);
+                    }
+                    if let Some(branch) = else_branch {
+                        stamp_scope_attribute(branch, attr);
+                    }
+                }
+                ControlFlowBlock::For { body, .. } => {
+                    stamp_scope_attribute(body, attr);
+                }
+                ControlFlowBlock::Await {
+                    pending,
+                    then_branch,
+                    catch_branch,
+                    ..
+                } => {
+                    stamp_scope_attribute(pending, attr);
+                    stamp_scope_attribute(then_branch, attr);
+                    if let Some(branch) = catch_branch {
+                        stamp_scope_attribute(branch, attr);
+                    }
+                }
+                ControlFlowBlock::Switch { cases, default, .. } => {
+                    for (_, branch) in cases {
+                        stamp_scope_attribute(branch, attr);
+                    }
+                    if let Some(branch) = default {
+                        stamp_scope_attribute(branch, attr);
+                    }
+                }
+            },
+            TemplateNode::Slot(slot) => {
+                stamp_scope_attribute(&mut slot.fallback, attr);
+            }
+            TemplateNode::Snippet(snippet) => {
+                stamp_scope_attribute(&mut snippet.body, attr);
+            }
+            TemplateNode::Text(_)
+            | TemplateNode::Expr(_)
+            | TemplateNode::Html(_)
+            | TemplateNode::Render(_)
+            | TemplateNode::Comment(_)
+            | TemplateNode::Declaration(_)
+            | TemplateNode::Const(_) => {}
+        }
+    }
+}
+
+fn scope_rules(css: &str, attr_selector: &str, out: &mut String) {
+    let mut pos = 0;
+    while pos < css.len() {
+        let rest = &css[pos..];
+        let trimmed = rest.trim_start();
+        let ws = rest.len() - trimmed.len();
+        out.push_str(&rest[..ws]);
+        pos += ws;
+        if trimmed.is_empty() {
+            break;
+        }
+
+        if trimmed.starts_with("/*") {
+            let end = trimmed.find("*/").map(|i| i + 2).unwrap_or(trimmed.len());
+            out.push_str(&trimmed[..end]);
+            pos += end;
+            continue;
+        }
+
+        // Prelude runs up to the next top-level `{` or `;`.
+        let Some((prelude_end, terminator)) = find_prelude_end(trimmed) else {
+            out.push_str(trimmed);
+            break;
+        };
+        let prelude = &trimmed[..prelude_end];
+
+        if terminator == ';' {
+            // `@import ...;`, `@charset ...;` or a stray declaration.
+            out.push_str(&trimmed[..=prelude_end]);
+            pos += prelude_end + 1;
+            continue;
+        }
+
+        let block_start = prelude_end + 1;
+        let block_end = find_block_end(trimmed, block_start);
+        let block = &trimmed[block_start..block_end];
+        let closed = block_end < trimmed.len();
+
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            let name: String = at_rule
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect();
+            out.push_str(prelude);
+            out.push('{');
+            if NESTED_RULE_AT_RULES.contains(&name.to_ascii_lowercase().as_str()) {
+                scope_rules(block, attr_selector, out);
+            } else {
+                out.push_str(block);
+            }
+        } else {
+            // A comment between a compound and the scope attribute would
+            // read as a descendant combinator, so drop them from selectors.
+            let prelude = strip_comments(prelude);
+            let trailing_ws = prelude.len() - prelude.trim_end().len();
+            out.push_str(&scope_selector_list(prelude.trim_end(), attr_selector));
+            out.push_str(&prelude[prelude.len() - trailing_ws..]);
+            out.push('{');
+            out.push_str(block);
+        }
+
+        if closed {
+            out.push('}');
+            pos += block_end + 1;
+        } else {
+            pos += block_end;
+        }
+    }
+}
+
+/// Byte index of the first top-level `{` or `;` in `css`, skipping strings
+/// and comments.
+fn find_prelude_end(css: &str) -> Option<(usize, char)> {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut chars = css.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if c == '\\' {
+                chars.next();
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '/' if css[i + 1..].starts_with('*') => {
+                let end = css[i + 2..].find("*/").map(|e| i + 2 + e + 2)?;
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+            }
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '{' | ';' if depth <= 0 => return Some((i, c)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Byte index of the `}` closing the block that starts at `start`, or the end
+/// of input if it is never closed.
+fn find_block_end(css: &str, start: usize) -> usize {
+    let mut depth = 1i32;
+    let mut quote: Option<char> = None;
+    let bytes = css.as_bytes();
+    let mut i = start;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if let Some(q) = quote {
+            if c == '\\' {
+                i += 1;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '/' && bytes.get(i + 1) == Some(&b'*') {
+            i = css[i + 2..].find("*/").map(|e| i + 2 + e + 1).unwrap_or(bytes.len());
+        } else {
+            match c {
+                '"' | '\'' => quote = Some(c),
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i;
+                    }
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// `s` without its `/* ... */` comments.
+fn strip_comments(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quote: Option<char> = None;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if c == '\\' {
+                out.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    out.push(escaped);
+                }
+                continue;
+            } else if c == q {
+                quote = None;
+            }
+            out.push(c);
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                quote = Some(c);
+                out.push(c);
+            }
+            '/' if s[i + 1..].starts_with('*') => {
+                let end = s[i + 2..].find("*/").map(|e| i + 2 + e + 2).unwrap_or(s.len());
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Splits `s` on `sep` outside of parentheses, brackets and strings.
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut last = 0;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if c == '\\' {
+                chars.next();
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ if c == sep && depth == 0 => {
+                parts.push(&s[last..i]);
+                last = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[last..]);
+    parts
+}
+
+fn scope_selector_list(selectors: &str, attr_selector: &str) -> String {
+    split_top_level(selectors, ',')
+        .into_iter()
+        .map(|sel| {
+            let leading = &sel[..sel.len() - sel.trim_start().len()];
+            format!("{}{}", leading, scope_complex_selector(sel.trim(), attr_selector))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+enum SelectorPart {
+    Compound(String),
+    Combinator(char),
+}
+
+fn scope_complex_selector(selector: &str, attr_selector: &str) -> String {
+    // Break `a > b c` into compounds and combinators.
+    let mut parts: Vec<SelectorPart> = Vec::new();
+    let mut current = String::new();
+    let mut pending_combinator: Option<char> = None;
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+
+    let flush = |current: &mut String, parts: &mut Vec<SelectorPart>, pending: &mut Option<char>| {
+        if current.is_empty() {
+            return;
+        }
+        if !parts.is_empty() {
+            parts.push(SelectorPart::Combinator(pending.take().unwrap_or(' ')));
+        }
+        *pending = None;
+        parts.push(SelectorPart::Compound(std::mem::take(current)));
+    };
+
+    for c in selector.chars() {
+        if let Some(q) = quote {
+            current.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                quote = Some(c);
+                current.push(c);
+            }
+            '(' | '[' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' | ']' => {
+                depth -= 1;
+                current.push(c);
+            }
+            '>' | '+' | '~' if depth == 0 => {
+                flush(&mut current, &mut parts, &mut pending_combinator);
+                pending_combinator = Some(c);
+            }
+            _ if c.is_whitespace() && depth == 0 => {
+                flush(&mut current, &mut parts, &mut pending_combinator);
+            }
+            _ => current.push(c),
+        }
+    }
+    flush(&mut current, &mut parts, &mut pending_combinator);
+
+    // Scope the last compound that is not `:global(...)`. `:root`, `html` and
+    // `body` are never rendered by a component, so they stay unscoped.
+    let target = parts.iter().rposition(|p| match p {
+        SelectorPart::Compound(c) => !c.contains(":global(") && !is_document_compound(c),
+        SelectorPart::Combinator(_) => false,
+    });
+
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            SelectorPart::Combinator(' ') => out.push(' '),
+            SelectorPart::Combinator(c) => {
+                out.push(' ');
+                out.push(*c);
+                out.push(' ');
+            }
+            SelectorPart::Compound(compound) => {
+                let compound = unwrap_global(compound);
+                if Some(i) == target {
+                    out.push_str(&insert_scope(&compound, attr_selector));
+                } else {
+                    out.push_str(&compound);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// `:root`, `html` or `body`, optionally with more qualifiers (`body.dark`).
+fn is_document_compound(compound: &str) -> bool {
+    if compound.starts_with(":root") {
+        return true;
+    }
+    let tag: String = compound
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    tag.eq_ignore_ascii_case("html") || tag.eq_ignore_ascii_case("body")
+}
+
+/// `:global(.a).b` -> `.a.b`
+fn unwrap_global(compound: &str) -> String {
+    let mut out = String::new();
+    let mut rest = compound;
+    while let Some(idx) = rest.find(":global(") {
+        out.push_str(&rest[..idx]);
+        let inner_start = idx + ":global(".len();
+        let mut depth = 1i32;
+        let mut end = rest.len();
+        for (i, c) in rest[inner_start..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = inner_start + i;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        out.push_str(&rest[inner_start..end]);
+        rest = if end < rest.len() { &rest[end + 1..] } else { "" };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Appends the attribute selector, keeping pseudo-elements last.
+fn insert_scope(compound: &str, attr_selector: &str) -> String {
+    let pseudo_element = compound.find("::").or_else(|| {
+        LEGACY_PSEUDO_ELEMENTS
+            .iter()
+            .filter_map(|p| compound.find(p))
+            .min()
+    });
+    match pseudo_element {
+        Some(idx) => format!("{}{}{}", &compound[..idx], attr_selector, &compound[idx..]),
+        None => format!("{}{}", compound, attr_selector),
+    }
+}
@@ -27,6 +27,11 @@ pub struct Diagnostic {
     pub end: LineCol,
 }
 
+/// Whether any of `diags` is an error; warnings alone don't fail a build.
+pub fn has_errors(diags: &[Diagnostic]) -> bool {
+    diags.iter().any(|d| d.severity == DiagnosticSeverity::Error)
+}
+
 pub fn compute_line_starts(source: &str) -> Vec<usize> {
     let mut starts = vec![0usize];
     for (i, b) in source.bytes().enumerate() {
@@ -1,7 +1,7 @@
-use crate::diagnostic::SourceRange;
+use crate::diagnostic::{range_to_line_cols, Diagnostic, DiagnosticSeverity, SourceRange};
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum CompileError {
     #[error("syntax error: {message}")]
     Syntax {
@@ -21,3 +21,42 @@ pub enum CompileError {
         range: Option<SourceRange>,
     },
 }
+
+impl CompileError {
+    /// Where in the source the error was found, when known.
+    pub fn range(&self) -> Option<&SourceRange> {
+        match self {
+            CompileError::Syntax { range, .. }
+            | CompileError::InvalidStructure { range, .. }
+            | CompileError::Template { range, .. } => range.as_ref(),
+        }
+    }
+
+    /// The same error, located at `range` unless it already has a location.
+    pub fn with_range(mut self, range: Option<SourceRange>) -> Self {
+        match &mut self {
+            CompileError::Syntax { range: r, .. }
+            | CompileError::InvalidStructure { range: r, .. }
+            | CompileError::Template { range: r, .. } => {
+                if r.is_none() {
+                    *r = range;
+                }
+            }
+        }
+        self
+    }
+
+    /// The error as an error diagnostic (at the start of the file when it
+    /// has no location).
+    pub fn to_diagnostic(&self, line_starts: &[usize]) -> Diagnostic {
+        let range = self.range().cloned().unwrap_or(SourceRange { start: 0, end: 0 });
+        let (start, end) = range_to_line_cols(line_starts, range.start, range.end);
+        Diagnostic {
+            severity: DiagnosticSeverity::Error,
+            message: self.to_string(),
+            range,
+            start,
+            end,
+        }
+    }
+}
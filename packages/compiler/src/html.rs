@@ -0,0 +1,239 @@
+use crate::ast::*;
+use std::borrow::Cow;
+
+// HTML text semantics applied to the parsed template: character references
+// in text and static attribute values are decoded, and whitespace that the
+// browser would not render is collapsed or dropped so it does not turn into
+// text nodes.
+
+/// Attribute that keeps the whitespace of an element and its descendants as
+/// written. It is removed from the output.
+pub const PRESERVE_WHITESPACE_ATTR: &str = "preserve-whitespace";
+
+/// Elements that start on a new line or render nothing, so the browser does
+/// not show whitespace between two of them.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "base", "blockquote", "body", "caption", "col", "colgroup", "dd", "details",
+    "dialog", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5",
+    "h6", "head", "header", "hgroup", "hr", "html", "li", "link", "main", "menu", "meta", "nav", "noscript", "ol",
+    "optgroup", "option", "p", "pre", "script", "section", "style", "summary", "table", "tbody", "td", "template",
+    "tfoot", "th", "thead", "title", "tr", "ul",
+];
+
+/// Named character references, the commonly used part of the HTML list.
+const NAMED_REFERENCES: &[(&str, char)] = &[
+    ("amp", '&'), ("lt", '<'), ("gt", '>'), ("quot", '"'), ("apos", '\''), ("nbsp", '\u{a0}'),
+    ("shy", '\u{ad}'), ("copy", '©'), ("reg", '®'), ("trade", '™'), ("deg", '°'), ("plusmn", '±'),
+    ("times", '×'), ("divide", '÷'), ("micro", 'µ'), ("para", '¶'), ("sect", '§'), ("middot", '·'),
+    ("laquo", '«'), ("raquo", '»'), ("lsaquo", '‹'), ("rsaquo", '›'), ("lsquo", '‘'), ("rsquo", '’'),
+    ("sbquo", '‚'), ("ldquo", '“'), ("rdquo", '”'), ("bdquo", '„'), ("ndash", '–'), ("mdash", '—'),
+    ("hellip", '…'), ("bull", '•'), ("prime", '′'), ("Prime", '″'), ("dagger", '†'), ("Dagger", '‡'),
+    ("permil", '‰'), ("cent", '¢'), ("pound", '£'), ("yen", '¥'), ("euro", '€'), ("curren", '¤'),
+    ("iexcl", '¡'), ("iquest", '¿'), ("ordf", 'ª'), ("ordm", 'º'), ("sup1", '¹'), ("sup2", '²'),
+    ("sup3", '³'), ("frac14", '¼'), ("frac12", '½'), ("frac34", '¾'), ("not", '¬'), ("macr", '¯'),
+    ("acute", '´'), ("cedil", '¸'), ("uml", '¨'), ("brvbar", '¦'), ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'), ("thinsp", '\u{2009}'), ("zwnj", '\u{200c}'), ("zwj", '\u{200d}'),
+    ("lrm", '\u{200e}'), ("rlm", '\u{200f}'), ("larr", '←'), ("uarr", '↑'), ("rarr", '→'),
+    ("darr", '↓'), ("harr", '↔'), ("lArr", '⇐'), ("rArr", '⇒'), ("hArr", '⇔'), ("minus", '−'),
+    ("le", '≤'), ("ge", '≥'), ("ne", '≠'), ("asymp", '≈'), ("equiv", '≡'), ("infin", '∞'),
+    ("sum", '∑'), ("prod", '∏'), ("radic", '√'), ("part", '∂'), ("nabla", '∇'), ("isin", '∈'),
+    ("forall", '∀'), ("exist", '∃'), ("empty", '∅'), ("and", '∧'), ("or", '∨'), ("cap", '∩'),
+    ("cup", '∪'), ("int", '∫'), ("there4", '∴'), ("sim", '∼'), ("sub", '⊂'), ("sup", '⊃'),
+    ("loz", '◊'), ("spades", '♠'), ("clubs", '♣'), ("hearts", '♥'), ("diams", '♦'), ("check", '✓'),
+    ("star", '☆'), ("starf", '★'), ("Agrave", 'À'), ("Aacute", 'Á'), ("Acirc", 'Â'), ("Atilde", 'Ã'),
+    ("Auml", 'Ä'), ("Aring", 'Å'), ("AElig", 'Æ'), ("Ccedil", 'Ç'), ("Egrave", 'È'), ("Eacute", 'É'),
+    ("Ecirc", 'Ê'), ("Euml", 'Ë'), ("Igrave", 'Ì'), ("Iacute", 'Í'), ("Icirc", 'Î'), ("Iuml", 'Ï'),
+    ("Ntilde", 'Ñ'), ("Ograve", 'Ò'), ("Oacute", 'Ó'), ("Ocirc", 'Ô'), ("Otilde", 'Õ'), ("Ouml", 'Ö'),
+    ("Oslash", 'Ø'), ("Ugrave", 'Ù'), ("Uacute", 'Ú'), ("Ucirc", 'Û'), ("Uuml", 'Ü'), ("Yacute", 'Ý'),
+    ("szlig", 'ß'), ("agrave", 'à'), ("aacute", 'á'), ("acirc", 'â'), ("atilde", 'ã'), ("auml", 'ä'),
+    ("aring", 'å'), ("aelig", 'æ'), ("ccedil", 'ç'), ("egrave", 'è'), ("eacute", 'é'), ("ecirc", 'ê'),
+    ("euml", 'ë'), ("igrave", 'ì'), ("iacute", 'í'), ("icirc", 'î'), ("iuml", 'ï'), ("ntilde", 'ñ'),
+    ("ograve", 'ò'), ("oacute", 'ó'), ("ocirc", 'ô'), ("otilde", 'õ'), ("ouml", 'ö'), ("oslash", 'ø'),
+    ("ugrave", 'ù'), ("uacute", 'ú'), ("ucirc", 'û'), ("uuml", 'ü'), ("yacute", 'ý'), ("yuml", 'ÿ'),
+    ("OElig", 'Œ'), ("oelig", 'œ'), ("Scaron", 'Š'), ("scaron", 'š'), ("Yuml", 'Ÿ'), ("fnof", 'ƒ'),
+    ("Alpha", 'Α'), ("Beta", 'Β'), ("Gamma", 'Γ'), ("Delta", 'Δ'), ("Theta", 'Θ'), ("Lambda", 'Λ'),
+    ("Pi", 'Π'), ("Sigma", 'Σ'), ("Phi", 'Φ'), ("Psi", 'Ψ'), ("Omega", 'Ω'), ("alpha", 'α'),
+    ("beta", 'β'), ("gamma", 'γ'), ("delta", 'δ'), ("epsilon", 'ε'), ("theta", 'θ'), ("lambda", 'λ'),
+    ("mu", 'μ'), ("pi", 'π'), ("rho", 'ρ'), ("sigma", 'σ'), ("tau", 'τ'), ("phi", 'φ'), ("psi", 'ψ'),
+    ("omega", 'ω'),
+];
+
+/// `text` with `&name;`, `&#123;` and `&#x7b;` references decoded. Unknown
+/// or unterminated references are left as they are.
+pub fn decode_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        match decode_reference(rest) {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// The character `input` (starting at `&`) refers to and the length of the
+/// reference, `;` included.
+fn decode_reference(input: &str) -> Option<(char, usize)> {
+    let end = input[1..].find(';')? + 1;
+    let name = &input[1..end];
+    let c = if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse::<u32>().ok()?,
+        };
+        // Like the browser, invalid code points become U+FFFD.
+        char::from_u32(code).filter(|c| *c != '\0').unwrap_or('\u{fffd}')
+    } else {
+        NAMED_REFERENCES.iter().find(|(n, _)| *n == name)?.1
+    };
+    Some((c, end + 1))
+}
+
+/// Collapses insignificant whitespace in `nodes` (the template or a child
+/// list), unless `preserve` is set:
+///
+/// - runs of whitespace in text become a single space;
+/// - whitespace-only text is dropped at the start and end of a child list,
+///   and between two block-level or non-rendering nodes (`<li>`, `<div>`,
+///   comments, ...); elsewhere it is one space, as the browser renders it;
+/// - leading or trailing whitespace containing a newline is dropped at the
+///   start and end of a child list.
+///
+/// `<pre>`, `<textarea>`, `<script>`, `<style>` and elements with the
+/// `preserve-whitespace` attribute keep their content as written.
+pub fn normalize_whitespace(nodes: &mut Vec<TemplateNode>, preserve: bool) {
+    for node in nodes.iter_mut() {
+        match node {
+            TemplateNode::Element(el) => {
+                let len = el.attributes.len();
+                el.attributes
+                    .retain(|a| !matches!(a, AttributeNode::Static { name, .. } if name == PRESERVE_WHITESPACE_ATTR));
+                let keep = preserve
+                    || el.attributes.len() != len
+                    || el.tag_name == "pre"
+                    || is_raw_text_element(&el.tag_name)
+                    || is_escapable_raw_text_element(&el.tag_name);
+                normalize_whitespace(&mut el.children, keep);
+            }
+            TemplateNode::Slot(slot) => normalize_whitespace(&mut slot.fallback, preserve),
+            TemplateNode::ControlFlow(ControlFlowBlock::If {
+                then_branch,
+                else_ifs,
+                else_branch,
+                ..
+            }) => {
+                normalize_whitespace(then_branch, preserve);
+                for (_, branch) in else_ifs {
+                    normalize_whitespace(branch, preserve);
+                }
+                if let Some(branch) = else_branch {
+                    normalize_whitespace(branch, preserve);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::For { body, .. }) => normalize_whitespace(body, preserve),
+            TemplateNode::ControlFlow(ControlFlowBlock::Await {
+                pending,
+                then_branch,
+                catch_branch,
+                ..
+            }) => {
+                normalize_whitespace(pending, preserve);
+                normalize_whitespace(then_branch, preserve);
+                if let Some(branch) = catch_branch {
+                    normalize_whitespace(branch, preserve);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::Switch { cases, default, .. }) => {
+                for (_, branch) in cases {
+                    normalize_whitespace(branch, preserve);
+                }
+                if let Some(branch) = default {
+                    normalize_whitespace(branch, preserve);
+                }
+            }
+            TemplateNode::Snippet(snippet) => normalize_whitespace(&mut snippet.body, preserve),
+            TemplateNode::Text(_)
+            | TemplateNode::Expr(_)
+            | TemplateNode::Html(_)
+            | TemplateNode::Render(_)
+            | TemplateNode::Comment(_)
+            | TemplateNode::Declaration(_)
+            | TemplateNode::Const(_) => {}
+        }
+    }
+    if preserve {
+        return;
+    }
+
+    let is_block = |node: Option<&TemplateNode>| match node {
+        Some(TemplateNode::Element(el)) => BLOCK_ELEMENTS.contains(&el.tag_name.to_ascii_lowercase().as_str()),
+        Some(TemplateNode::Comment(_) | TemplateNode::Declaration(_) | TemplateNode::Const(_) | TemplateNode::Snippet(_)) => true,
+        _ => false,
+    };
+    let mut out = Vec::with_capacity(nodes.len());
+    let mut iter = std::mem::take(nodes).into_iter().peekable();
+    while let Some(node) = iter.next() {
+        let TemplateNode::Text(text) = node else {
+            out.push(node);
+            continue;
+        };
+        let body = text.trim_matches(is_html_space);
+        let leading = &text[..text.len() - text.trim_start_matches(is_html_space).len()];
+        let trailing = &text[text.trim_end_matches(is_html_space).len()..];
+        let (first, last) = (out.is_empty(), iter.peek().is_none());
+
+        let collapsed = if body.is_empty() {
+            // Indentation between tags.
+            if first || last || (is_block(out.last()) && is_block(iter.peek())) {
+                continue;
+            }
+            " ".to_string()
+        } else {
+            let mut collapsed = collapse_whitespace(&text);
+            if first && leading.contains('\n') {
+                collapsed = collapsed.trim_start_matches(' ').to_string();
+            }
+            if last && trailing.contains('\n') {
+                collapsed = collapsed.trim_end_matches(' ').to_string();
+            }
+            collapsed
+        };
+        out.push(TemplateNode::Text(collapsed));
+    }
+    *nodes = out;
+}
+
+/// The ASCII whitespace HTML collapses (not `&nbsp;`).
+fn is_html_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if is_html_space(c) {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
@@ -0,0 +1,412 @@
+use crate::ast::*;
+use crate::codegen::{
+    action_prop, arrow_params, bind_value, branch_h, dynamic_prop_value, event_handler_value, for_item_params,
+    generate_props_object, is_component_tag, js_string, mark, slot_snippet, snippet_h, snippet_params,
+    split_slot_children, spread_prop,
+};
+use crate::transpiler::transpile_ts_snippet;
+
+// Hydration codegen: the statements a compiled component runs when it is
+// called with a `HydrationCursor` (see `hydration.ts` in the runtime). They
+// walk the server markup in template order and attach effects, listeners and
+// `bind:` directives to the existing nodes instead of creating new ones.
+// Anything that may re-render later (`@{if}` branches, `@{for}` items) also
+// carries its regular `__LUMIX__.h` body.
+
+/// Hydration statements for `nodes`, using the cursor in scope as `__c`.
+pub(crate) fn generate_hydrate_nodes(nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    // Snippets are declared before anything renders them, after the
+    // `{@const}` tags they may use.
+    let mut ordered: Vec<&TemplateNode> = nodes.iter().collect();
+    ordered.sort_by_key(|node| match node {
+        TemplateNode::Const(_) => 0,
+        TemplateNode::Snippet(_) => 1,
+        _ => 2,
+    });
+    let mut s = String::new();
+    for node in ordered {
+        s.push_str(&generate_hydrate_node(node, indent, is_bundle));
+    }
+    s
+}
+
+fn generate_hydrate_node(node: &TemplateNode, indent: usize, is_bundle: bool) -> String {
+    let pad = " ".repeat(indent);
+    match node {
+        TemplateNode::Text(_) => format!("{}__c.text();\n", pad),
+        TemplateNode::Const(tag) => format!(
+            "{}const {} = {}{};\n",
+            pad,
+            tag.binding,
+            mark(tag.expr.span),
+            transpile_ts_snippet(&tag.expr.code).trim()
+        ),
+        TemplateNode::Comment(_) => format!("{}__c.comment();\n", pad),
+        // Only valid outside the element the client renders into.
+        TemplateNode::Declaration(_) => String::new(),
+        TemplateNode::Expr(expr) => format!(
+            "{}{}__LUMIX__.__hydrateBlock(__c, () => ({}));\n",
+            pad,
+            mark(expr.span),
+            transpile_ts_snippet(&expr.code).trim()
+        ),
+        TemplateNode::Html(expr) => format!(
+            "{}{}__LUMIX__.__hydrateHtml(__c, () => ({}));\n",
+            pad,
+            mark(expr.span),
+            transpile_ts_snippet(&expr.code).trim()
+        ),
+        TemplateNode::Snippet(snippet) => format!(
+            "{}const {}{} = {};\n",
+            pad,
+            mark(snippet.name_span),
+            snippet.name,
+            snippet_h(snippet, indent, is_bundle)
+        ),
+        TemplateNode::Render(tag) => {
+            let args: Vec<String> = tag
+                .args
+                .iter()
+                .map(|arg| match arg.code.strip_prefix("...") {
+                    Some(rest) => format!("...{}", transpile_ts_snippet(rest)),
+                    None => transpile_ts_snippet(&arg.code),
+                })
+                .collect();
+            format!(
+                "{}{}__LUMIX__.__hydrateRender(__c, () => ({}), () => [{}]);\n",
+                pad,
+                mark(tag.snippet.span),
+                transpile_ts_snippet(&tag.snippet.code),
+                args.join(", ")
+            )
+        }
+        TemplateNode::Slot(slot) => {
+            let (provided, key) = match &slot.name {
+                None => ("props.children".to_string(), "default".to_string()),
+                Some(name) => (format!("props.slots?.{}", name), name.clone()),
+            };
+            let mut s = format!("{}if ({}) {{\n", pad, provided);
+            s.push_str(&format!("{}  props.__hydrateSlots?.{}?.(__c);\n", pad, key));
+            s.push_str(&format!("{}}}", pad));
+            if !slot.fallback.is_empty() {
+                s.push_str(" else {\n");
+                s.push_str(&generate_hydrate_nodes(&slot.fallback, indent + 2, is_bundle));
+                s.push_str(&format!("{}}}", pad));
+            }
+            s.push('\n');
+            s
+        }
+        TemplateNode::ControlFlow(cf) => generate_hydrate_control_flow(cf, indent, is_bundle),
+        TemplateNode::Element(el) if is_component_tag(&el.tag_name) => {
+            generate_hydrate_component(el, indent, is_bundle)
+        }
+        TemplateNode::Element(el) => {
+            if !needs_hydration(std::slice::from_ref(node)) {
+                // Nothing to attach: just step over the element.
+                return format!("{}{}__c.element('{}');\n", pad, mark(el.tag_span), el.tag_name);
+            }
+
+            // With a spread, every attribute is passed so that later ones
+            // still take precedence over it.
+            let has_spread = el.attributes.iter().any(|a| matches!(a, AttributeNode::Spread { .. }));
+            let mut spreads = 0;
+            let mut props = Vec::new();
+            for attr in &el.attributes {
+                match attr {
+                    AttributeNode::Static { name, value } => {
+                        // Otherwise already in the markup.
+                        if has_spread {
+                            props.push(format!("'{}': {}", name, js_string(value)));
+                        }
+                    }
+                    AttributeNode::Dynamic { name, expr } => {
+                        if has_spread || !is_literal_expr(&expr.code) {
+                            props.push(format!("{}'{}': {}", mark(expr.span), name, dynamic_prop_value(&expr.code)));
+                        }
+                    }
+                    AttributeNode::EventHandler { name, expr, modifiers } => {
+                        props.push(format!(
+                            "{}'{}': {}",
+                            mark(expr.span),
+                            name,
+                            event_handler_value(&expr.code, modifiers)
+                        ));
+                    }
+                    AttributeNode::Bind { property, expr } => {
+                        props.push(format!("{}'bind:{}': {}", mark(expr.span), property, bind_value(property, &expr.code)));
+                    }
+                    AttributeNode::Spread { expr } => props.push(spread_prop(&mut spreads, expr)),
+                    // Always passed: the runtime re-applies them when
+                    // `class`/`style` is rewritten.
+                    AttributeNode::Class { name, expr } => {
+                        props.push(format!("{}'class:{}': {}", mark(expr.span), name, dynamic_prop_value(&expr.code)));
+                    }
+                    AttributeNode::Action { action, params } => props.push(action_prop(action, params.as_ref())),
+                    AttributeNode::Style { property, expr } => {
+                        props.push(format!(
+                            "{}'style:{}': {}",
+                            mark(expr.span),
+                            property,
+                            dynamic_prop_value(&expr.code)
+                        ));
+                    }
+                }
+            }
+            let props = if props.is_empty() {
+                "null".to_string()
+            } else {
+                format!("{{ {} }}", props.join(", "))
+            };
+
+            let mut s = format!(
+                "{}{}__LUMIX__.__hydrateElement(__c, '{}', {}",
+                pad,
+                mark(el.tag_span),
+                el.tag_name,
+                props
+            );
+            if is_escapable_raw_text_element(&el.tag_name) && needs_hydration(&el.children) {
+                let parts: Vec<String> = el
+                    .children
+                    .iter()
+                    .filter_map(|child| match child {
+                        TemplateNode::Text(t) => Some(js_string(t)),
+                        TemplateNode::Expr(expr) => Some(format!(
+                            "{}() => ({})",
+                            mark(expr.span),
+                            transpile_ts_snippet(&expr.code).trim()
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                s.push_str(&format!(
+                    ", (__c) => __LUMIX__.__hydrateText(__c, () => [{}])",
+                    parts.join(", ")
+                ));
+            } else if needs_hydration(&el.children) {
+                s.push_str(", (__c) => {\n");
+                s.push_str(&generate_hydrate_nodes(&el.children, indent + 2, is_bundle));
+                s.push_str(&format!("{}}}", pad));
+            }
+            s.push_str(");\n");
+            s
+        }
+    }
+}
+
+fn generate_hydrate_control_flow(cf: &ControlFlowBlock, indent: usize, is_bundle: bool) -> String {
+    let pad = " ".repeat(indent);
+    match cf {
+        ControlFlowBlock::If {
+            condition,
+            then_branch,
+            else_ifs,
+            else_branch,
+        } => {
+            let mut s = format!(
+                "{}__LUMIX__.__hydrateIf(__c, () => ({}{}), [\n",
+                pad,
+                mark(condition.span),
+                transpile_ts_snippet(&condition.code).trim()
+            );
+            s.push_str(&hydrate_branch(None, then_branch, indent + 2, is_bundle));
+            for (cond, branch) in else_ifs {
+                let cond = format!("{}{}", mark(cond.span), transpile_ts_snippet(&cond.code).trim());
+                s.push_str(&hydrate_branch(Some(("cond", &cond)), branch, indent + 2, is_bundle));
+            }
+            if let Some(branch) = else_branch {
+                s.push_str(&hydrate_branch(None, branch, indent + 2, is_bundle));
+            }
+            s.push_str(&format!("{}]);\n", pad));
+            s
+        }
+        ControlFlowBlock::For {
+            binding,
+            iterable,
+            index,
+            key_expr,
+            body,
+        } => {
+            let params = for_item_params(binding, index.as_ref());
+            let mut s = format!(
+                "{}__LUMIX__.__hydrateFor(__c, () => ({}{}), {} => {}, (__c, {}) => {{\n",
+                pad,
+                mark(iterable.span),
+                transpile_ts_snippet(&iterable.code).trim(),
+                arrow_params(&params),
+                branch_h(body, indent, is_bundle),
+                params
+            );
+            s.push_str(&generate_hydrate_nodes(body, indent + 2, is_bundle));
+            s.push_str(&format!("{}}}", pad));
+            if let Some(key) = key_expr {
+                s.push_str(&format!(", {} => ({})", arrow_params(binding), transpile_ts_snippet(key).trim()));
+            }
+            s.push_str(");\n");
+            s
+        }
+        ControlFlowBlock::Await {
+            promise,
+            pending,
+            then_binding,
+            then_branch,
+            catch_binding,
+            catch_branch,
+        } => {
+            let mut s = format!(
+                "{}__LUMIX__.__hydrateAwait(__c, () => ({}{}), ",
+                pad,
+                mark(promise.span),
+                transpile_ts_snippet(&promise.code).trim()
+            );
+            s.push_str(&hydrate_await_branch(None, pending, indent, is_bundle));
+            s.push_str(", ");
+            s.push_str(&hydrate_await_branch(then_binding.as_deref(), then_branch, indent, is_bundle));
+            if let Some(branch) = catch_branch {
+                s.push_str(", ");
+                s.push_str(&hydrate_await_branch(catch_binding.as_deref(), branch, indent, is_bundle));
+            }
+            s.push_str(");\n");
+            s
+        }
+        ControlFlowBlock::Switch {
+            discriminant,
+            cases,
+            default,
+        } => {
+            let mut s = format!(
+                "{}__LUMIX__.__hydrateSwitch(__c, () => ({}{}), [\n",
+                pad,
+                mark(discriminant.span),
+                transpile_ts_snippet(&discriminant.code).trim()
+            );
+            for (value, branch) in cases {
+                let value = format!("{}{}", mark(value.span), transpile_ts_snippet(&value.code).trim());
+                s.push_str(&hydrate_branch(Some(("value", &value)), branch, indent + 2, is_bundle));
+            }
+            if let Some(branch) = default {
+                s.push_str(&hydrate_branch(None, branch, indent + 2, is_bundle));
+            }
+            s.push_str(&format!("{}]);\n", pad));
+            s
+        }
+    }
+}
+
+/// `{ body, hydrate }` for one branch of `__hydrateAwait`.
+fn hydrate_await_branch(binding: Option<&str>, nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    let pad = " ".repeat(indent);
+    let mut s = format!(
+        "{{\n{}  body: ({}) => {},\n",
+        pad,
+        binding.unwrap_or(""),
+        branch_h(nodes, indent + 2, is_bundle)
+    );
+    match binding {
+        Some(binding) => s.push_str(&format!("{}  hydrate: (__c, {}) => {{\n", pad, binding)),
+        None => s.push_str(&format!("{}  hydrate: (__c) => {{\n", pad)),
+    }
+    s.push_str(&generate_hydrate_nodes(nodes, indent + 4, is_bundle));
+    s.push_str(&format!("{}  }},\n", pad));
+    s.push_str(&format!("{}}}", pad));
+    s
+}
+
+/// `{ cond?, body, hydrate }` entry for `__hydrateIf`, or `{ value?, ... }`
+/// for `__hydrateSwitch`: `test` is the key and the expression.
+fn hydrate_branch(test: Option<(&str, &str)>, nodes: &[TemplateNode], indent: usize, is_bundle: bool) -> String {
+    let pad = " ".repeat(indent);
+    let mut s = format!("{}{{\n", pad);
+    if let Some((key, code)) = test {
+        s.push_str(&format!("{}  {}: () => ({}),\n", pad, key, code));
+    }
+    s.push_str(&format!("{}  body: () => {},\n", pad, branch_h(nodes, indent + 2, is_bundle)));
+    s.push_str(&format!("{}  hydrate: (__c) => {{\n", pad));
+    s.push_str(&generate_hydrate_nodes(nodes, indent + 4, is_bundle));
+    s.push_str(&format!("{}  }},\n", pad));
+    s.push_str(&format!("{}}},\n", pad));
+    s
+}
+
+fn generate_hydrate_component(el: &ElementNode, indent: usize, is_bundle: bool) -> String {
+    let pad = " ".repeat(indent);
+    let tag = if is_bundle {
+        format!("__lumixComponents['{}'].default", el.tag_name)
+    } else {
+        el.tag_name.clone()
+    };
+
+    // The child gets the same props as on the client, plus one hydrate
+    // function per slot so it can adopt the slotted markup in place.
+    let (default_children, slots) = split_slot_children(el);
+
+    let mut s = format!(
+        "{}{}__LUMIX__.__hydrateComponent(__c, {}, {{ ...{}",
+        pad,
+        mark(el.tag_span),
+        tag,
+        generate_props_object(el, indent + 2, is_bundle, true)
+    );
+    if !default_children.is_empty() || !slots.is_empty() {
+        s.push_str(", '__hydrateSlots': {\n");
+        if !default_children.is_empty() {
+            s.push_str(&format!("{}    'default': (__c) => {{\n", pad));
+            s.push_str(&generate_hydrate_nodes(&default_children, indent + 6, is_bundle));
+            s.push_str(&format!("{}    }},\n", pad));
+        }
+        for (name, nodes) in &slots {
+            match slot_snippet(nodes) {
+                Some(snippet) => {
+                    let params = snippet_params(&snippet.params);
+                    let params = if params.is_empty() { params } else { format!(", {}", params) };
+                    s.push_str(&format!("{}    '{}': (__c{}) => {{\n", pad, name, params));
+                    s.push_str(&generate_hydrate_nodes(&snippet.body, indent + 6, is_bundle));
+                }
+                None => {
+                    s.push_str(&format!("{}    '{}': (__c) => {{\n", pad, name));
+                    s.push_str(&generate_hydrate_nodes(nodes, indent + 6, is_bundle));
+                }
+            }
+            s.push_str(&format!("{}    }},\n", pad));
+        }
+        s.push_str(&format!("{}  }}", pad));
+    }
+    s.push_str(" });\n");
+    s
+}
+
+/// Whether any node needs more than being stepped over: reactive blocks,
+/// slots, components, or elements with dynamic attributes or listeners.
+fn needs_hydration(nodes: &[TemplateNode]) -> bool {
+    nodes.iter().any(|node| match node {
+        TemplateNode::Text(_)
+        | TemplateNode::Comment(_)
+        | TemplateNode::Declaration(_)
+        | TemplateNode::Const(_)
+        | TemplateNode::Snippet(_) => false,
+        TemplateNode::Expr(_)
+        | TemplateNode::Html(_)
+        | TemplateNode::Render(_)
+        | TemplateNode::ControlFlow(_)
+        | TemplateNode::Slot(_) => true,
+        TemplateNode::Element(el) => {
+            is_component_tag(&el.tag_name)
+                || el.attributes.iter().any(|a| match a {
+                    AttributeNode::Static { .. } => false,
+                    AttributeNode::Dynamic { expr, .. } => !is_literal_expr(&expr.code),
+                    AttributeNode::EventHandler { .. }
+                    | AttributeNode::Bind { .. }
+                    | AttributeNode::Spread { .. }
+                    | AttributeNode::Class { .. }
+                    | AttributeNode::Style { .. }
+                    | AttributeNode::Action { .. } => true,
+                })
+                || needs_hydration(&el.children)
+        }
+    })
+}
+
+/// Literal attribute values are rendered once and never change.
+fn is_literal_expr(code: &str) -> bool {
+    !dynamic_prop_value(code).starts_with("() =>")
+}
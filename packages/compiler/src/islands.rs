@@ -0,0 +1,543 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{Map, Value};
+use swc_common::{FileName, SourceMap};
+use swc_ecma_ast::{BindingIdent, Callee, Decl, Expr, MemberProp, ModuleItem, Pat, PropName, Stmt, VarDeclarator};
+use swc_ecma_parser::{Parser, StringInput, Syntax, TsConfig};
+use swc_ecma_visit::{Visit, VisitWith};
+
+use crate::ast::*;
+use crate::codegen::{self, dynamic_prop_value, is_component_tag};
+use crate::diagnostic::Diagnostic;
+use crate::sourcemap;
+use crate::ssr_codegen::{self, IslandIds};
+
+// Islands for `"use static"` pages: the page is rendered to plain HTML and
+// only its interactive subtrees ship JS. A subtree is interactive when it has
+// event handlers or `bind:`, reads a signal from the page script, or contains
+// a child component that is interactive itself.
+
+/// Runtime factories whose result is reactive state.
+const REACTIVE_FACTORIES: &[&str] = &["signal", "computed", "store", "persist", "derived"];
+
+/// One interactive subtree of a static page.
+pub struct Island {
+    /// Value of the `data-lumix-island` attribute on its wrapper.
+    pub id: String,
+    /// Component whose code hydrates the island, relative to the page.
+    pub component: String,
+    /// File name of the hydration entry (`js`).
+    pub entry: String,
+    /// Props the entry is hydrated with.
+    pub props: Map<String, Value>,
+    /// Hydration entry: a module whose default export adopts the island markup.
+    pub js: String,
+}
+
+/// Module running the page script once, shared by the islands that need it
+/// so they see the same signals.
+pub struct IslandScript {
+    /// File name of the module.
+    pub entry: String,
+    pub js: String,
+}
+
+/// Output of `compile_static_file`.
+pub struct StaticBuild {
+    /// Server module rendering the page (see `ssr_codegen`).
+    pub js: String,
+    pub map: sourcemap::SourceMap,
+    pub css: Option<String>,
+    pub islands: Vec<Island>,
+    /// Imported by every island that hydrates part of the page itself.
+    pub script: Option<IslandScript>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl StaticBuild {
+    /// Every client module of the page as `(file name, js)`: the island
+    /// entries, then the shared island script.
+    pub fn client_modules(&self) -> impl Iterator<Item = (&str, &str)> {
+        let islands = self.islands.iter().map(|island| (island.entry.as_str(), island.js.as_str()));
+        islands.chain(self.script.iter().map(|script| (script.entry.as_str(), script.js.as_str())))
+    }
+
+    /// Manifest listing every island of the page, for the client loader.
+    pub fn manifest(&self, component_file: &str) -> Value {
+        let islands: Vec<Value> = self
+            .islands
+            .iter()
+            .map(|island| {
+                serde_json::json!({
+                    "id": island.id,
+                    "component": island.component,
+                    "entry": island.entry,
+                    "props": island.props,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "component": component_file,
+            "islands": islands,
+        })
+    }
+}
+
+/// Names the script binds to reactive state (`const x = signal(...)`, ...).
+pub fn reactive_bindings(component: &ComponentFile) -> HashSet<String> {
+    struct Collector(HashSet<String>);
+    impl Visit for Collector {
+        fn visit_var_declarator(&mut self, decl: &VarDeclarator) {
+            if let (Pat::Ident(name), Some(init)) = (&decl.name, &decl.init) {
+                if let Expr::Call(call) = &**init {
+                    if let Callee::Expr(callee) = &call.callee {
+                        if let Expr::Ident(ident) = &**callee {
+                            if REACTIVE_FACTORIES.contains(&&*ident.sym) {
+                                self.0.insert(name.id.sym.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            decl.visit_children_with(self);
+        }
+    }
+
+    let mut collector = Collector(HashSet::new());
+    if let Some(module) = component.script.as_ref().and_then(|s| parse_module(&s.original_code)) {
+        module.visit_with(&mut collector);
+    }
+    collector.0
+}
+
+/// Whether any part of `component` needs JS on the client.
+/// `child_interactive` answers the same question for a child component tag.
+pub fn is_interactive_component(component: &ComponentFile, child_interactive: &dyn Fn(&str) -> bool) -> bool {
+    let analyzer = Analyzer::new(component, child_interactive);
+    component.template.iter().any(|n| analyzer.is_interactive(n))
+}
+
+struct Analyzer<'a> {
+    reactive: HashSet<String>,
+    child_interactive: &'a dyn Fn(&str) -> bool,
+    /// Snippets of the template whose markup is static; rendering one of them
+    /// with static arguments is static too.
+    static_snippets: HashSet<String>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(component: &ComponentFile, child_interactive: &'a dyn Fn(&str) -> bool) -> Self {
+        let mut snippets = Vec::new();
+        collect_snippets(&component.template, &mut snippets);
+        let mut analyzer = Analyzer {
+            reactive: reactive_bindings(component),
+            child_interactive,
+            static_snippets: snippets.iter().map(|s| s.name.clone()).collect(),
+        };
+        // Snippets can render each other: repeat until none turns out to be
+        // interactive.
+        loop {
+            let interactive: Vec<String> = snippets
+                .iter()
+                .filter(|s| analyzer.static_snippets.contains(&s.name) && s.body.iter().any(|n| analyzer.is_interactive(n)))
+                .map(|s| s.name.clone())
+                .collect();
+            if interactive.is_empty() {
+                return analyzer;
+            }
+            for name in interactive {
+                analyzer.static_snippets.remove(&name);
+            }
+        }
+    }
+}
+
+impl Analyzer<'_> {
+    fn reads_signal(&self, code: &str) -> bool {
+        !self.reactive.is_empty() && referenced_identifiers(code).iter().any(|id| self.reactive.contains(id))
+    }
+
+    fn is_interactive(&self, node: &TemplateNode) -> bool {
+        match node {
+            TemplateNode::Text(_) | TemplateNode::Comment(_) | TemplateNode::Declaration(_) => false,
+            TemplateNode::Expr(expr) | TemplateNode::Html(expr) | TemplateNode::Const(ConstTag { expr, .. }) => {
+                self.reads_signal(&expr.code)
+            }
+            TemplateNode::Slot(slot) => slot.fallback.iter().any(|n| self.is_interactive(n)),
+            TemplateNode::Snippet(snippet) => snippet.body.iter().any(|n| self.is_interactive(n)),
+            TemplateNode::Render(tag) => {
+                !self.static_snippets.contains(tag.snippet.code.trim()) || tag.args.iter().any(|arg| self.reads_signal(&arg.code))
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::If {
+                condition,
+                then_branch,
+                else_ifs,
+                else_branch,
+            }) => {
+                self.reads_signal(&condition.code)
+                    || then_branch.iter().any(|n| self.is_interactive(n))
+                    || else_ifs
+                        .iter()
+                        .any(|(c, b)| self.reads_signal(&c.code) || b.iter().any(|n| self.is_interactive(n)))
+                    || else_branch.iter().flatten().any(|n| self.is_interactive(n))
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::For { iterable, body, .. }) => {
+                self.reads_signal(&iterable.code)
+                    || body.iter().any(|n| self.is_interactive(n))
+            }
+            // The server renders the pending branch; the client swaps in the result.
+            TemplateNode::ControlFlow(ControlFlowBlock::Await { .. }) => true,
+            TemplateNode::ControlFlow(ControlFlowBlock::Switch {
+                discriminant,
+                cases,
+                default,
+            }) => {
+                self.reads_signal(&discriminant.code)
+                    || cases
+                        .iter()
+                        .any(|(value, b)| self.reads_signal(&value.code) || b.iter().any(|n| self.is_interactive(n)))
+                    || default.iter().flatten().any(|n| self.is_interactive(n))
+            }
+            TemplateNode::Element(el) => {
+                self.has_interactive_attributes(el)
+                    || (is_component_tag(&el.tag_name) && (self.child_interactive)(&el.tag_name))
+                    || el.children.iter().any(|n| self.is_interactive(n))
+            }
+        }
+    }
+
+    fn has_interactive_attributes(&self, el: &ElementNode) -> bool {
+        el.attributes.iter().any(|attr| match attr {
+            AttributeNode::Static { .. } => false,
+            AttributeNode::Dynamic { expr, .. }
+            | AttributeNode::Spread { expr }
+            | AttributeNode::Class { expr, .. }
+            | AttributeNode::Style { expr, .. } => self.reads_signal(&expr.code),
+            AttributeNode::EventHandler { .. } | AttributeNode::Bind { .. } | AttributeNode::Action { .. } => true,
+        })
+    }
+
+    /// Outermost interactive nodes. Only plain elements with static
+    /// attributes are descended into; anything else that is interactive
+    /// (a component, a control-flow block, ...) becomes one island as a whole.
+    fn collect_roots<'n>(&self, nodes: &'n [TemplateNode], out: &mut Vec<&'n TemplateNode>) {
+        for node in nodes {
+            if !self.is_interactive(node) {
+                continue;
+            }
+            match node {
+                TemplateNode::Element(el) if !is_component_tag(&el.tag_name) && !self.has_interactive_attributes(el) => {
+                    self.collect_roots(&el.children, out);
+                }
+                // Included in the islands that render it.
+                TemplateNode::Snippet(_) => {}
+                _ => out.push(node),
+            }
+        }
+    }
+}
+
+/// Renders a `"use static"` page: the server module for its HTML plus one
+/// hydration entry per island.
+pub(crate) fn build_static(
+    component: &ComponentFile,
+    component_name: &str,
+    source: &str,
+    source_name: &str,
+    child_interactive: &dyn Fn(&str) -> bool,
+) -> StaticBuild {
+    let scoped = ssr_codegen::scoped_component(component);
+    let analyzer = Analyzer::new(component, child_interactive);
+    let mut roots = Vec::new();
+    analyzer.collect_roots(&scoped.template, &mut roots);
+
+    let imports: HashMap<&str, &str> = component
+        .imports
+        .iter()
+        .flat_map(|imp| {
+            imp.specifiers.iter().filter_map(move |s| match s {
+                ImportSpecifier::Default(name) => Some((name.as_str(), imp.source.as_str())),
+                _ => None,
+            })
+        })
+        .collect();
+
+    let script_entry = format!("{}.island-script.js", component_name);
+    let island_script = component.script.as_ref().map(|script| island_script_stub(script, &script_entry));
+
+    let mut ids = IslandIds::new();
+    let mut islands = Vec::new();
+    let mut uses_script = false;
+    for (i, node) in roots.into_iter().enumerate() {
+        let id = format!("{}-{}", component_name, i);
+        ids.insert(node as *const TemplateNode, id.clone());
+        let entry = format!("{}.island-{}.js", component_name, i);
+
+        // A lone child component with literal props only needs that
+        // component: its props are serialized into the manifest.
+        let component_island = match node {
+            TemplateNode::Element(el) if is_component_tag(&el.tag_name) && el.children.is_empty() => {
+                imports.get(el.tag_name.as_str()).zip(literal_props(el))
+            }
+            _ => None,
+        };
+
+        let island = match component_island {
+            Some((import_source, props)) => Island {
+                id,
+                component: import_source.to_string(),
+                entry,
+                props,
+                js: format!("export {{ default }} from '{}';\n", import_source),
+            },
+            None => {
+                // Anything else hydrates just this subtree (and the snippets
+                // it may render), taking the page script's bindings from the
+                // shared island script.
+                uses_script = true;
+                let mut template: Vec<TemplateNode> =
+                    scoped.template.iter().filter(|n| matches!(n, TemplateNode::Snippet(_))).cloned().collect();
+                template.push(node.clone());
+                let island_component = ComponentFile {
+                    imports: component.imports.clone(),
+                    script: island_script.clone(),
+                    style: None,
+                    template,
+                    defined_slots: Vec::new(),
+                    head: None,
+                    render_mode: component.render_mode,
+                };
+                Island {
+                    id,
+                    component: format!("./{}", source_name),
+                    entry,
+                    props: Map::new(),
+                    js: codegen::generate_js(&island_component, &format!("{}Island{}", component_name, i)),
+                }
+            }
+        };
+        islands.push(island);
+    }
+
+    let script = match &component.script {
+        Some(script) if uses_script => Some(IslandScript {
+            entry: script_entry,
+            js: island_script_module(component, script, component_name),
+        }),
+        _ => None,
+    };
+
+    let mut map = sourcemap::SourceMap::new(format!("{}.js", component_name));
+    let src = map.add_source(source_name, source);
+    let js = map.add_generated(src, 0, || {
+        ssr_codegen::generate_static_ssr_module_marked(component, &scoped, component_name, &ids)
+    });
+
+    StaticBuild {
+        js,
+        map,
+        css: codegen::generate_css(component),
+        islands,
+        script,
+        diagnostics: Vec::new(),
+    }
+}
+
+/// The shared island script: the page's props and script run on the first
+/// call, and every later call returns the same bindings.
+fn island_script_module(component: &ComponentFile, script: &ScriptBlock, component_name: &str) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by LumixJS (island script)\n");
+    out.push_str("import * as __LUMIX__ from 'lumix-js';\n");
+    for imp in &script.imports {
+        out.push_str(&imp.code);
+        out.push('\n');
+    }
+    out.push_str("\nlet __script;\n\n");
+    out.push_str("export default function __islandScript(props = {}) {\n");
+    out.push_str("  if (__script) return __script;\n");
+    out.push_str(&codegen::generate_script_prologue(component, &script.code, component_name));
+    out.push_str(&format!("  __script = {{ {} }};\n", script_bindings(script).join(", ")));
+    out.push_str("  return __script;\n");
+    out.push_str("}\n");
+    out
+}
+
+/// Script for an island component: the page script's bindings, taken from
+/// the shared island script in `entry`.
+fn island_script_stub(script: &ScriptBlock, entry: &str) -> ScriptBlock {
+    let bindings = script_bindings(script);
+    let code = if bindings.is_empty() {
+        "__islandScript(props);\n".to_string()
+    } else {
+        format!("const {{ {} }} = __islandScript(props);\n", bindings.join(", "))
+    };
+    let mut imports = script.imports.clone();
+    imports.push(ScriptImport {
+        code: format!("import __islandScript from './{}';", entry),
+    });
+    ScriptBlock {
+        original_code: code.clone(),
+        code,
+        imports,
+        props: Vec::new(),
+        span: None,
+        mappings: Vec::new(),
+        directives: Vec::new(),
+    }
+}
+
+/// Props and top-level names the page script declares, in order.
+fn script_bindings(script: &ScriptBlock) -> Vec<String> {
+    struct Collector(Vec<String>);
+    impl Visit for Collector {
+        fn visit_binding_ident(&mut self, ident: &BindingIdent) {
+            self.0.push(ident.id.sym.to_string());
+        }
+        // Default values in patterns do not declare anything.
+        fn visit_expr(&mut self, _: &Expr) {}
+    }
+
+    let mut names = Collector(script.props.iter().map(|p| p.name.clone()).collect());
+    if let Some(module) = parse_module(&script.code) {
+        for item in &module.body {
+            match item {
+                ModuleItem::Stmt(Stmt::Decl(Decl::Var(var))) => {
+                    for decl in &var.decls {
+                        decl.name.visit_with(&mut names);
+                    }
+                }
+                ModuleItem::Stmt(Stmt::Decl(Decl::Fn(f))) => names.0.push(f.ident.sym.to_string()),
+                ModuleItem::Stmt(Stmt::Decl(Decl::Class(c))) => names.0.push(c.ident.sym.to_string()),
+                _ => {}
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    names.0.retain(|name| seen.insert(name.clone()));
+    names.0
+}
+
+/// Every snippet defined in `nodes`, at any depth.
+fn collect_snippets<'n>(nodes: &'n [TemplateNode], out: &mut Vec<&'n SnippetNode>) {
+    for node in nodes {
+        match node {
+            TemplateNode::Snippet(snippet) => {
+                out.push(snippet);
+                collect_snippets(&snippet.body, out);
+            }
+            TemplateNode::Element(el) => collect_snippets(&el.children, out),
+            TemplateNode::Slot(slot) => collect_snippets(&slot.fallback, out),
+            TemplateNode::ControlFlow(ControlFlowBlock::If {
+                then_branch,
+                else_ifs,
+                else_branch,
+                ..
+            }) => {
+                collect_snippets(then_branch, out);
+                for (_, branch) in else_ifs {
+                    collect_snippets(branch, out);
+                }
+                if let Some(branch) = else_branch {
+                    collect_snippets(branch, out);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::For { body, .. }) => collect_snippets(body, out),
+            TemplateNode::ControlFlow(ControlFlowBlock::Await {
+                pending,
+                then_branch,
+                catch_branch,
+                ..
+            }) => {
+                collect_snippets(pending, out);
+                collect_snippets(then_branch, out);
+                if let Some(branch) = catch_branch {
+                    collect_snippets(branch, out);
+                }
+            }
+            TemplateNode::ControlFlow(ControlFlowBlock::Switch { cases, default, .. }) => {
+                for (_, branch) in cases {
+                    collect_snippets(branch, out);
+                }
+                if let Some(branch) = default {
+                    collect_snippets(branch, out);
+                }
+            }
+            TemplateNode::Text(_)
+            | TemplateNode::Expr(_)
+            | TemplateNode::Html(_)
+            | TemplateNode::Render(_)
+            | TemplateNode::Comment(_)
+            | TemplateNode::Declaration(_)
+            | TemplateNode::Const(_) => {}
+        }
+    }
+}
+
+/// Props of a component element as JSON, if every one is a compile-time value.
+fn literal_props(el: &ElementNode) -> Option<Map<String, Value>> {
+    let mut props = Map::new();
+    for attr in &el.attributes {
+        match attr {
+            AttributeNode::Static { name, value } => {
+                props.insert(name.clone(), Value::String(value.clone()));
+            }
+            AttributeNode::Dynamic { name, expr } => {
+                let code = dynamic_prop_value(&expr.code);
+                let value = if code == "undefined" {
+                    continue;
+                } else if let Some(s) = code.strip_prefix('\'').and_then(|c| c.strip_suffix('\'')) {
+                    Value::String(s.to_string())
+                } else {
+                    serde_json::from_str(&code).ok()?
+                };
+                props.insert(name.clone(), value);
+            }
+            AttributeNode::EventHandler { .. }
+            | AttributeNode::Bind { .. }
+            | AttributeNode::Spread { .. }
+            | AttributeNode::Class { .. }
+            | AttributeNode::Style { .. }
+            | AttributeNode::Action { .. } => return None,
+        }
+    }
+    Some(props)
+}
+
+fn parse_module(code: &str) -> Option<swc_ecma_ast::Module> {
+    let cm: SourceMap = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("island_script.ts".into()), code.into());
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    parser.parse_module().ok()
+}
+
+/// Identifiers an expression refers to (property names excluded).
+fn referenced_identifiers(code: &str) -> HashSet<String> {
+    struct Collector(HashSet<String>);
+    impl Visit for Collector {
+        fn visit_ident(&mut self, ident: &swc_ecma_ast::Ident) {
+            self.0.insert(ident.sym.to_string());
+        }
+        fn visit_member_prop(&mut self, prop: &MemberProp) {
+            if let MemberProp::Computed(c) = prop {
+                c.visit_with(self);
+            }
+        }
+        fn visit_prop_name(&mut self, name: &PropName) {
+            if let PropName::Computed(c) = name {
+                c.visit_with(self);
+            }
+        }
+    }
+
+    let cm: SourceMap = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("island_expr.ts".into()), code.into());
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    let mut collector = Collector(HashSet::new());
+    match parser.parse_expr() {
+        Ok(expr) => expr.visit_with(&mut collector),
+        // Unparseable expressions are reported elsewhere; assume the worst.
+        Err(_) => return code.split(|c: char| !c.is_alphanumeric() && c != '_' && c != '$').map(String::from).collect(),
+    }
+    collector.0
+}
@@ -1,5 +1,5 @@
 use swc_common::{FileName, SourceMap, Span, Spanned};
-use swc_ecma_ast::{ModuleDecl, ModuleItem};
+use swc_ecma_ast::{Callee, Expr, Lit, MemberProp, ModuleDecl, ModuleItem};
 use swc_ecma_parser::{Parser, StringInput, Syntax, TsConfig};
 
 use crate::diagnostic::{range_to_line_cols, Diagnostic, DiagnosticSeverity, SourceRange};
@@ -64,6 +64,59 @@ pub fn diagnose_forbidden_lumin_imports_in_module(
     diags
 }
 
+/// Calls whose result `{@html}` accepts without a warning, by the last name
+/// of the callee: `sanitize(x)`, `DOMPurify.sanitize(x)`, `trustedHtml(x)`.
+const HTML_SANITIZERS: &[&str] = &["sanitize", "sanitizeHtml", "xss", "trustedHtml"];
+
+/// Warns about a `{@html}` expression that is neither a string literal nor
+/// the result of a recognized sanitizer or `trustedHtml(...)`.
+pub fn diagnose_unsanitized_html(line_starts: &[usize], expr_code: &str, snippet_start_offset: usize) -> Vec<Diagnostic> {
+    let cm: SourceMap = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("lumin_html.ts".into()), expr_code.into());
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    let Ok(expr) = parser.parse_expr() else {
+        // Reported by validate_js_snippet.
+        return Vec::new();
+    };
+    if is_sanitized_html(&expr) {
+        return Vec::new();
+    }
+
+    let start = snippet_start_offset;
+    let end = snippet_start_offset + expr_code.len();
+    let (lc_start, lc_end) = range_to_line_cols(line_starts, start, end);
+    vec![Diagnostic {
+        severity: DiagnosticSeverity::Warning,
+        message: "{@html} inserts markup without escaping it; pass the value through a sanitizer such as DOMPurify.sanitize(...), or wrap it in trustedHtml(...) if it is safe".into(),
+        range: SourceRange { start, end },
+        start: lc_start,
+        end: lc_end,
+    }]
+}
+
+fn is_sanitized_html(expr: &Expr) -> bool {
+    match expr {
+        Expr::Paren(paren) => is_sanitized_html(&paren.expr),
+        Expr::Lit(Lit::Str(_)) => true,
+        Expr::Tpl(tpl) => tpl.exprs.is_empty(),
+        Expr::Call(call) => {
+            let Callee::Expr(callee) = &call.callee else {
+                return false;
+            };
+            let name = match &**callee {
+                Expr::Ident(ident) => &ident.sym,
+                Expr::Member(member) => match &member.prop {
+                    MemberProp::Ident(ident) => &ident.sym,
+                    _ => return false,
+                },
+                _ => return false,
+            };
+            HTML_SANITIZERS.contains(&&**name)
+        }
+        _ => false,
+    }
+}
+
 pub fn validate_js_snippet(
     lumin_source: &str,
     line_starts: &[usize],
@@ -1,10 +1,17 @@
 pub mod ast;
 pub mod bundler;
+pub mod cache;
+pub mod css;
 pub mod diagnostic;
 pub mod error;
+pub mod html;
+pub mod hydrate_codegen;
+pub mod islands;
 pub mod js_validate;
 pub mod parser;
 pub mod codegen;
+pub mod sourcemap;
+pub mod ssr_codegen;
 pub mod ts_codegen;
 pub mod transpiler;
 
@@ -14,7 +21,7 @@ use anyhow::Result;
 
 use crate::diagnostic::{compute_line_starts, Diagnostic};
 use crate::js_validate::{
-    diagnose_forbidden_lumin_imports_in_module, validate_js_snippet, JsSnippetKind,
+    diagnose_forbidden_lumin_imports_in_module, diagnose_unsanitized_html, validate_js_snippet, JsSnippetKind,
 };
 use crate::diagnostic::{range_to_line_cols, DiagnosticSeverity, SourceRange};
 
@@ -28,9 +35,9 @@ pub fn compile_file<P: AsRef<Path>>(input_path: P) -> Result<String> {
         .unwrap_or("Component");
         
     let (js, diags) = compile_source_with_diagnostics(&source, component_name)
-        .map_err(|e| anyhow::Error::new(e))?;
+        .map_err(anyhow::Error::new)?;
 
-    if !diags.is_empty() {
+    if diagnostic::has_errors(&diags) {
         // Keep compile_file signature; caller can use compile_file_with_diagnostics.
         return Err(anyhow::anyhow!("compile failed with diagnostics"));
     }
@@ -49,16 +56,195 @@ pub fn compile_file_with_diagnostics<P: AsRef<Path>>(
         .and_then(|s| s.to_str())
         .unwrap_or("Component");
 
-    compile_source_with_diagnostics(&source, component_name).map_err(|e| anyhow::Error::new(e))
+    compile_source_with_diagnostics(&source, component_name).map_err(anyhow::Error::new)
+}
+
+/// Output of `compile_file_with_options`.
+pub struct CompileResult {
+    pub js: String,
+    pub map: sourcemap::SourceMap,
+    /// The component's CSS, when it has a `<style>` block.
+    pub css: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compile a `.lumix` file and return generated JS, a Source Map v3 for it
+/// and diagnostics.
+pub fn compile_file_with_source_map<P: AsRef<Path>>(
+    input_path: P,
+) -> Result<(String, sourcemap::SourceMap, Vec<Diagnostic>)> {
+    let res = compile_file_with_options(input_path, &codegen::CodegenOptions::default())?;
+    Ok((res.js, res.map, res.diagnostics))
+}
+
+/// Compile a `.lumix` file with explicit codegen options and return every
+/// output the compiler produces for it.
+pub fn compile_file_with_options<P: AsRef<Path>>(
+    input_path: P,
+    options: &codegen::CodegenOptions,
+) -> Result<CompileResult> {
+    let source: String = std::fs::read_to_string(&input_path)?;
+    compile_source_with_options(input_path.as_ref(), &source, options, &mut cache::SourceCache::new())
+}
+
+/// Compile `source` as the `.lumix` file at `path`, reusing the parse from
+/// `cache` when the source has not changed.
+pub fn compile_source_with_options(
+    path: &Path,
+    source: &str,
+    options: &codegen::CodegenOptions,
+    cache: &mut cache::SourceCache,
+) -> Result<CompileResult> {
+    let component_name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Component");
+    let source_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Component.lumix");
+
+    // Syntax errors don't stop validation: report everything found at once.
+    let (component, syntax_errors) = cache.parse_recovering(path, source);
+    let parsed = syntax_errors.is_empty();
+    let diagnostics = merge_syntax_errors(syntax_errors, validate_component(source, &component));
+    if !parsed {
+        // Nothing to generate from a best-effort parse.
+        return Ok(CompileResult {
+            js: String::new(),
+            map: sourcemap::SourceMap::new(format!("{}.js", component_name)),
+            css: None,
+            diagnostics,
+        });
+    }
+    let (js, map) = codegen::generate_js_with_source_map(
+        &component,
+        component_name,
+        source,
+        source_name,
+        options,
+    );
+    Ok(CompileResult {
+        js,
+        map,
+        css: codegen::generate_css(&component),
+        diagnostics,
+    })
+}
+
+/// Parse and validate `source` as the `.lumix` file at `path`, without
+/// generating code. Syntax errors are recovered from, so the result holds
+/// every problem in the file.
+pub fn validate_source(path: &Path, source: &str, cache: &mut cache::SourceCache) -> Vec<Diagnostic> {
+    let (component, syntax_errors) = cache.parse_recovering(path, source);
+    merge_syntax_errors(syntax_errors, validate_component(source, &component))
+}
+
+/// Syntax errors followed by the validation diagnostics of the same file,
+/// minus those reporting a problem already found by the parser (a broken
+/// `<script>` shows up in both).
+pub(crate) fn merge_syntax_errors(mut syntax_errors: Vec<Diagnostic>, diags: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let reported: Vec<SourceRange> = syntax_errors.iter().map(|d| d.range.clone()).collect();
+    syntax_errors.extend(diags.into_iter().filter(|d| !reported.contains(&d.range)));
+    syntax_errors
+}
+
+/// Compile a `"use static"` page into its server module plus one hydration
+/// entry per interactive island (see `islands`).
+pub fn compile_static_file<P: AsRef<Path>>(input_path: P) -> Result<islands::StaticBuild> {
+    let source: String = std::fs::read_to_string(&input_path)?;
+    compile_static_source(input_path.as_ref(), &source, &mut cache::SourceCache::new())
+}
+
+/// `compile_static_file` for in-memory source. Child components are read
+/// through `cache`.
+pub fn compile_static_source(
+    path: &Path,
+    source: &str,
+    cache: &mut cache::SourceCache,
+) -> Result<islands::StaticBuild> {
+    let component_name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Component");
+    let source_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Component.lumix");
+
+    let component = cache.parse(path, source).map_err(anyhow::Error::new)?;
+    let diagnostics = validate_component(source, &component);
+    let cache = &*cache;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut visiting = std::collections::HashSet::new();
+    visiting.insert(path.to_path_buf());
+    let child_interactive = |tag: &str| imported_component_is_interactive(&component, tag, base, &visiting, cache);
+
+    let rendered = codegen::with_comments(&component, codegen::CommentOutput::Strip);
+    let mut build = islands::build_static(&rendered, component_name, source, source_name, &child_interactive);
+    build.diagnostics = diagnostics;
+    Ok(build)
+}
+
+/// Whether the `.lumix` file `component` imports as `tag` needs client JS.
+/// Files that cannot be read or parsed are assumed to be interactive.
+fn imported_component_is_interactive(
+    component: &ast::ComponentFile,
+    tag: &str,
+    base: &Path,
+    visiting: &std::collections::HashSet<std::path::PathBuf>,
+    cache: &cache::SourceCache,
+) -> bool {
+    let source = component.imports.iter().find_map(|imp| {
+        imp.specifiers.iter().find_map(|s| match s {
+            ast::ImportSpecifier::Default(name) if name == tag => Some(imp.source.as_str()),
+            _ => None,
+        })
+    });
+    let Some(source) = source.filter(|s| s.ends_with(".lumix")) else {
+        return true;
+    };
+    let child_path = base.join(source);
+    if visiting.contains(&child_path) {
+        // Import cycle: the rest of the cycle decides.
+        return false;
+    }
+    let Ok(child_source) = cache.read(&child_path) else {
+        return true;
+    };
+    let Ok(child) = parser::parse_component(&child_source) else {
+        return true;
+    };
+
+    let mut visiting = visiting.clone();
+    visiting.insert(child_path.clone());
+    let child_base = child_path.parent().unwrap_or(base).to_path_buf();
+    islands::is_interactive_component(&child, &|tag: &str| {
+        imported_component_is_interactive(&child, tag, &child_base, &visiting, cache)
+    })
 }
 
 fn compile_source_with_diagnostics(
     source: &str,
     component_name: &str,
 ) -> std::result::Result<(String, Vec<Diagnostic>), error::CompileError> {
-    let line_starts = compute_line_starts(source);
+    let (component, diags) = parse_and_validate(source)?;
+    let js: String = codegen::generate_js(&component, component_name);
+    Ok((js, diags))
+}
 
+fn parse_and_validate(
+    source: &str,
+) -> std::result::Result<(ast::ComponentFile, Vec<Diagnostic>), error::CompileError> {
     let component: ast::ComponentFile = parser::parse_component(source)?;
+    let diags = validate_component(source, &component);
+    Ok((component, diags))
+}
+
+/// Diagnostics for a parsed component: script and expression syntax,
+/// imports and render directives.
+fn validate_component(source: &str, component: &ast::ComponentFile) -> Vec<Diagnostic> {
+    let line_starts = compute_line_starts(source);
 
     let mut diags: Vec<Diagnostic> = Vec::new();
 
@@ -68,7 +254,7 @@ fn compile_source_with_diagnostics(
             diags.extend(validate_js_snippet(
                 source,
                 &line_starts,
-                &script.code,
+                &script.original_code,
                 span.start,
                 JsSnippetKind::ScriptModule,
             ));
@@ -77,7 +263,7 @@ fn compile_source_with_diagnostics(
             diags.extend(diagnose_forbidden_lumin_imports_in_module(
                 source,
                 &line_starts,
-                &script.code,
+                &script.original_code,
                 span.start,
             ));
         }
@@ -87,10 +273,12 @@ fn compile_source_with_diagnostics(
     lib_collect_expr_diagnostics(source, &line_starts, &component.template, &mut diags);
 
     // Semantic validation: component tags must be imported
-    diags.extend(validate_component_tags_imported(source, &line_starts, &component));
+    diags.extend(validate_component_tags_imported(source, &line_starts, component));
 
-    let js: String = codegen::generate_js(&component, component_name);
-    Ok((js, diags))
+    // Render directives must lead <script>, and only one is allowed
+    diags.extend(validate_render_directives(&line_starts, component));
+
+    diags
 }
 
 pub(crate) fn lib_collect_expr_diagnostics(
@@ -112,7 +300,24 @@ pub(crate) fn lib_collect_expr_diagnostics(
                     ));
                 }
             }
-            ast::TemplateNode::Text(_) => {}
+            ast::TemplateNode::Html(e) => {
+                if let Some(span) = e.span.as_ref() {
+                    out.extend(validate_js_snippet(
+                        source,
+                        line_starts,
+                        &e.code,
+                        span.start,
+                        JsSnippetKind::Expression,
+                    ));
+                    out.extend(diagnose_unsanitized_html(line_starts, &e.code, span.start));
+                }
+            }
+            // Parsed by swc already.
+            ast::TemplateNode::Text(_)
+            | ast::TemplateNode::Comment(_)
+            | ast::TemplateNode::Declaration(_)
+            | ast::TemplateNode::Const(_)
+            | ast::TemplateNode::Render(_) => {}
             ast::TemplateNode::Element(el) => {
                 for a in &el.attributes {
                     match a {
@@ -138,8 +343,12 @@ pub(crate) fn lib_collect_expr_diagnostics(
                                 ));
                             }
                         }
-                        ast::AttributeNode::Static { .. } => {}
-                        ast::AttributeNode::Bind { expr, .. } => {
+                        ast::AttributeNode::Static { .. } | ast::AttributeNode::Action { params: None, .. } => {}
+                        ast::AttributeNode::Bind { expr, .. }
+                        | ast::AttributeNode::Spread { expr }
+                        | ast::AttributeNode::Class { expr, .. }
+                        | ast::AttributeNode::Style { expr, .. }
+                        | ast::AttributeNode::Action { params: Some(expr), .. } => {
                             if let Some(span) = expr.span.as_ref() {
                                 out.extend(validate_js_snippet(
                                     source,
@@ -191,14 +400,108 @@ pub(crate) fn lib_collect_expr_diagnostics(
                 ast::ControlFlowBlock::For { body, .. } => {
                     lib_collect_expr_diagnostics(source, line_starts, body, out);
                 }
+                ast::ControlFlowBlock::Await {
+                    promise,
+                    pending,
+                    then_branch,
+                    catch_branch,
+                    ..
+                } => {
+                    if let Some(span) = promise.span.as_ref() {
+                        out.extend(validate_js_snippet(
+                            source,
+                            line_starts,
+                            &promise.code,
+                            span.start,
+                            JsSnippetKind::Expression,
+                        ));
+                    }
+                    lib_collect_expr_diagnostics(source, line_starts, pending, out);
+                    lib_collect_expr_diagnostics(source, line_starts, then_branch, out);
+                    if let Some(branch) = catch_branch {
+                        lib_collect_expr_diagnostics(source, line_starts, branch, out);
+                    }
+                }
+                ast::ControlFlowBlock::Switch {
+                    discriminant,
+                    cases,
+                    default,
+                } => {
+                    for expr in std::iter::once(discriminant).chain(cases.iter().map(|(value, _)| value)) {
+                        if let Some(span) = expr.span.as_ref() {
+                            out.extend(validate_js_snippet(
+                                source,
+                                line_starts,
+                                &expr.code,
+                                span.start,
+                                JsSnippetKind::Expression,
+                            ));
+                        }
+                    }
+                    for (_, branch) in cases {
+                        lib_collect_expr_diagnostics(source, line_starts, branch, out);
+                    }
+                    if let Some(branch) = default {
+                        lib_collect_expr_diagnostics(source, line_starts, branch, out);
+                    }
+                }
             },
             ast::TemplateNode::Slot(slot) => {
                 lib_collect_expr_diagnostics(source, line_starts, &slot.fallback, out);
             }
+            ast::TemplateNode::Snippet(snippet) => {
+                lib_collect_expr_diagnostics(source, line_starts, &snippet.body, out);
+            }
         }
     }
 }
 
+pub(crate) fn validate_render_directives(
+    line_starts: &[usize],
+    component: &ast::ComponentFile,
+) -> Vec<Diagnostic> {
+    let Some(script) = &component.script else {
+        return Vec::new();
+    };
+
+    let mut out = Vec::new();
+    let mut chosen: Option<ast::RenderMode> = None;
+    for directive in &script.directives {
+        let message = if !directive.in_prologue {
+            format!(
+                "'{}' has no effect here. Render directives must come first in <script>, before any other statement.",
+                directive.mode.directive()
+            )
+        } else if let Some(mode) = chosen {
+            if mode == directive.mode {
+                format!("Duplicate '{}' directive.", directive.mode.directive())
+            } else {
+                format!(
+                    "'{}' conflicts with '{}'. Only one render directive per component is allowed.",
+                    directive.mode.directive(),
+                    mode.directive()
+                )
+            }
+        } else {
+            chosen = Some(directive.mode);
+            continue;
+        };
+
+        let (start, end) = range_to_line_cols(line_starts, directive.span.start, directive.span.end);
+        out.push(Diagnostic {
+            severity: DiagnosticSeverity::Error,
+            message,
+            range: SourceRange {
+                start: directive.span.start,
+                end: directive.span.end,
+            },
+            start,
+            end,
+        });
+    }
+    out
+}
+
 pub(crate) fn validate_component_tags_imported(
     _source: &str,
     line_starts: &[usize],
@@ -268,6 +571,8 @@ fn validate_component_tags_imported_in_nodes(
             validate_component_tags_imported_in_nodes(line_starts, imported, &el.children, out);
         } else if let ast::TemplateNode::Slot(slot) = n {
             validate_component_tags_imported_in_nodes(line_starts, imported, &slot.fallback, out);
+        } else if let ast::TemplateNode::Snippet(snippet) = n {
+            validate_component_tags_imported_in_nodes(line_starts, imported, &snippet.body, out);
         }
     }
 }
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use lumixjs::ast::{is_void_element, ComponentFile, ImportSpecifier, PropKind};
+use lumixjs::cache::{normalize_path, SourceCache};
+use lumixjs::diagnostic::DiagnosticSeverity;
+
+// `luminc lsp`: language server for .lumix files, over stdin/stdout.
+//
+// Documents are synced in full. Open documents shadow the disk in the
+// `SourceCache`, so hover and completion see unsaved changes in imported
+// components too. Completion and navigation work on the parser's best-effort
+// component plus a light scan of the text around the cursor, so they keep
+// working while the file is half-typed.
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// `bind:` properties the runtime handles specially, per element.
+const BIND_PROPERTIES: &[(&str, &[&str])] = &[
+    ("input", &["value", "checked", "group"]),
+    ("select", &["value", "selected"]),
+    ("textarea", &["value"]),
+    ("option", &["selected"]),
+];
+
+struct Document {
+    path: PathBuf,
+    text: String,
+    /// Best-effort parse of `text`.
+    component: ComponentFile,
+}
+
+struct Server {
+    cache: SourceCache,
+    documents: HashMap<String, Document>,
+}
+
+pub fn run() -> anyhow::Result<()> {
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut server = Server {
+        cache: SourceCache::new(),
+        documents: HashMap::new(),
+    };
+
+    while let Some(message) = read_message(&mut stdin)? {
+        if message.get("method").and_then(Value::as_str) == Some("exit") {
+            break;
+        }
+        for out in server.handle(message) {
+            write_message(&mut stdout, &out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one `Content-Length` framed message; `None` at end of input.
+fn read_message(input: &mut impl BufRead) -> anyhow::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or_else(|| anyhow::anyhow!("message without Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+impl Server {
+    /// Messages to send in reply to `message`.
+    fn handle(&mut self, message: Value) -> Vec<Value> {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to a request of ours; we send none.
+            return Vec::new();
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, &params).into_iter().collect();
+        };
+        let response = match self.request(method, &params) {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": METHOD_NOT_FOUND, "message": format!("unknown method '{}'", method) },
+            }),
+        };
+        vec![response]
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Option<Value> {
+        Some(match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": ["<", ":", "\""] },
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                },
+                "serverInfo": { "name": "luminc", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/completion" => self.completion(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            _ => return None,
+        })
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str()?.to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str()?.to_string();
+                self.update(&uri, text);
+                Some(self.publish_diagnostics(&uri))
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change holds the whole document.
+                let text = params["contentChanges"].as_array()?.last()?["text"].as_str()?.to_string();
+                self.update(&uri, text);
+                Some(self.publish_diagnostics(&uri))
+            }
+            "textDocument/didClose" => {
+                let doc = self.documents.remove(&uri)?;
+                self.cache.remove_source(&doc.path);
+                Some(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) {
+        let path = normalize_path(&uri_to_path(uri));
+        self.cache.set_source(&path, text.clone());
+        let (component, _) = self.cache.parse_recovering(&path, &text);
+        self.documents.insert(uri.to_string(), Document { path, text, component });
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Value {
+        let doc = &self.documents[uri];
+        let diagnostics: Vec<Value> = lumixjs::validate_source(&doc.path, &doc.text, &mut self.cache)
+            .iter()
+            .map(|d| lsp_diagnostic(&doc.text, d.range.start, d.range.end, &d.severity, &d.message))
+            .collect();
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn completion(&mut self, params: &Value) -> Value {
+        let Some((doc, offset)) = locate(&self.documents, params) else {
+            return json!([]);
+        };
+        let text = &doc.text[..offset];
+        let word_start = text
+            .rfind(|c: char| !is_ident_char(c))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+        // `<Ca|`: imported components.
+        if text[..word_start].ends_with('<') {
+            let items: Vec<Value> = default_imports(&doc.component)
+                .map(|(name, source)| json!({ "label": name, "kind": 7, "detail": source }))
+                .collect();
+            return json!(items);
+        }
+
+        let Some((tag_start, tag)) = open_tag_before(&doc.text, offset) else {
+            return json!([]);
+        };
+
+        // `<input bind:|`: properties two-way binding supports, and `this`.
+        // On `<Card bind:|`, the props `Card` declares with `model()`.
+        if text[..word_start].ends_with("bind:") {
+            if tag.starts_with(|c: char| c.is_ascii_uppercase()) {
+                let Some(child) = load_component(&mut self.cache, &doc.path, &doc.component, tag) else {
+                    return json!([{ "label": "this", "kind": 10 }]);
+                };
+                let props = child.script.as_ref().map(|s| s.props.as_slice()).unwrap_or_default();
+                let items: Vec<Value> = props
+                    .iter()
+                    .filter(|prop| prop.kind == PropKind::Model)
+                    .map(|prop| json!({ "label": prop.name, "kind": 10, "detail": "bindable prop" }))
+                    .chain(std::iter::once(json!({ "label": "this", "kind": 10 })))
+                    .collect();
+                return json!(items);
+            }
+            let properties = BIND_PROPERTIES
+                .iter()
+                .find(|(name, _)| *name == tag)
+                .map(|(_, properties)| *properties)
+                .unwrap_or(&["value"]);
+            let items: Vec<Value> = properties
+                .iter()
+                .chain(&["this"])
+                .map(|p| json!({ "label": p, "kind": 10 }))
+                .collect();
+            return json!(items);
+        }
+
+        // `<h1 slot="|"` inside `<Card>`: the slots `Card` renders.
+        if text[..word_start].ends_with("slot=\"") {
+            let Some(parent) = enclosing_component(&doc.text[..tag_start]) else {
+                return json!([]);
+            };
+            let Some(child) = load_component(&mut self.cache, &doc.path, &doc.component, parent) else {
+                return json!([]);
+            };
+            let items: Vec<Value> = child
+                .defined_slots
+                .iter()
+                .filter(|slot| *slot != "children")
+                .map(|slot| json!({ "label": slot, "kind": 12 }))
+                .collect();
+            return json!(items);
+        }
+
+        json!([])
+    }
+
+    fn definition(&mut self, params: &Value) -> Value {
+        let Some((doc, offset)) = locate(&self.documents, params) else {
+            return Value::Null;
+        };
+        let Some((_, tag)) = component_tag_at(&doc.text, offset) else {
+            return Value::Null;
+        };
+        match resolve_import(&doc.path, &doc.component, tag) {
+            Some(path) if path.exists() || self.documents.values().any(|d| d.path == path) => json!({
+                "uri": path_to_uri(&path),
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+            }),
+            _ => Value::Null,
+        }
+    }
+
+    fn hover(&mut self, params: &Value) -> Value {
+        let Some((doc, offset)) = locate(&self.documents, params) else {
+            return Value::Null;
+        };
+        let Some(((start, end), tag)) = component_tag_at(&doc.text, offset) else {
+            return Value::Null;
+        };
+        let component = &doc.component;
+        let Some((_, source)) = default_imports(component).find(|(name, _)| *name == tag) else {
+            return Value::Null;
+        };
+        let Some(child) = load_component(&mut self.cache, &doc.path, component, tag) else {
+            return Value::Null;
+        };
+
+        let mut markdown = format!("**{}** from `{}`\n", tag, source);
+        let props = child.script.as_ref().map(|s| s.props.as_slice()).unwrap_or_default();
+        if props.is_empty() {
+            markdown.push_str("\nNo props.\n");
+        } else {
+            markdown.push_str("\nProps:\n");
+            for prop in props {
+                markdown.push_str(&format!("- `{}`", prop.name));
+                if let Some(default) = &prop.original_default_value {
+                    markdown.push_str(&format!(" = `{}`", default));
+                }
+                match prop.kind {
+                    PropKind::Signal => markdown.push_str(" (signal)"),
+                    PropKind::Model => markdown.push_str(" (bindable)"),
+                    PropKind::Prop => {}
+                }
+                markdown.push('\n');
+            }
+        }
+        if !child.defined_slots.is_empty() {
+            let slots: Vec<String> = child.defined_slots.iter().map(|s| format!("`{}`", s)).collect();
+            markdown.push_str(&format!("\nSlots: {}\n", slots.join(", ")));
+        }
+
+        let (start_line, start_char) = position_at(&doc.text, start);
+        let (end_line, end_char) = position_at(&doc.text, end);
+        json!({
+            "contents": { "kind": "markdown", "value": markdown },
+            "range": {
+                "start": { "line": start_line, "character": start_char },
+                "end": { "line": end_line, "character": end_char },
+            },
+        })
+    }
+}
+
+/// The open document and byte offset a position request points at.
+fn locate<'a>(documents: &'a HashMap<String, Document>, params: &Value) -> Option<(&'a Document, usize)> {
+    let doc = documents.get(params["textDocument"]["uri"].as_str()?)?;
+    let line = params["position"]["line"].as_u64()? as usize;
+    let character = params["position"]["character"].as_u64()? as usize;
+    Some((doc, offset_at(&doc.text, line, character)))
+}
+
+/// The component `component` (at `path`) imports as `tag`, read from its
+/// open document or from disk.
+fn load_component(cache: &mut SourceCache, path: &Path, component: &ComponentFile, tag: &str) -> Option<ComponentFile> {
+    let child_path = resolve_import(path, component, tag)?;
+    let source = cache.read(&child_path).ok()?;
+    Some(cache.parse_recovering(&child_path, &source).0)
+}
+
+fn default_imports(component: &ComponentFile) -> impl Iterator<Item = (&str, &str)> {
+    component.imports.iter().flat_map(|imp| {
+        imp.specifiers.iter().filter_map(move |s| match s {
+            ImportSpecifier::Default(name) => Some((name.as_str(), imp.source.as_str())),
+            _ => None,
+        })
+    })
+}
+
+/// Path of the `.lumix` file imported as `tag` in the `---` block.
+fn resolve_import(path: &Path, component: &ComponentFile, tag: &str) -> Option<PathBuf> {
+    let (_, source) = default_imports(component).find(|(name, _)| *name == tag)?;
+    if !source.ends_with(".lumix") {
+        return None;
+    }
+    Some(normalize_path(&path.parent().unwrap_or(Path::new(".")).join(source)))
+}
+
+fn lsp_diagnostic(text: &str, start: usize, end: usize, severity: &DiagnosticSeverity, message: &str) -> Value {
+    let (start_line, start_char) = position_at(text, start);
+    let (end_line, end_char) = position_at(text, end);
+    json!({
+        "range": {
+            "start": { "line": start_line, "character": start_char },
+            "end": { "line": end_line, "character": end_char },
+        },
+        "severity": match severity {
+            DiagnosticSeverity::Error => 1,
+            DiagnosticSeverity::Warning => 2,
+        },
+        "source": "lumix",
+        "message": message,
+    })
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Tag name under `offset` in `<Name` or `</Name`, if it names a component.
+fn component_tag_at(text: &str, offset: usize) -> Option<((usize, usize), &str)> {
+    let start = text[..offset].rfind(|c: char| !is_ident_char(c)).map(|i| i + 1).unwrap_or(0);
+    let end = text[offset..].find(|c: char| !is_ident_char(c)).map(|i| offset + i).unwrap_or(text.len());
+    let before = &text[..start];
+    if start == end || !(before.ends_with('<') || before.ends_with("</")) {
+        return None;
+    }
+    let name = &text[start..end];
+    name.starts_with(|c: char| c.is_ascii_uppercase()).then_some(((start, end), name))
+}
+
+/// End of the start tag opened at `start` (the `>`), skipping quoted values
+/// and `{...}` expressions.
+fn tag_end(text: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (i, c) in text[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if depth == 0 => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => depth = depth.saturating_sub(1),
+            (None, '>') if depth == 0 => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The start tag the cursor is in: position of its `<` and its name.
+fn open_tag_before(text: &str, offset: usize) -> Option<(usize, &str)> {
+    let start = text[..offset].rfind('<')?;
+    if tag_end(&text[..offset], start).is_some() {
+        return None;
+    }
+    let name_end = text[start + 1..]
+        .find(|c: char| !(is_ident_char(c) || c == '-'))
+        .map(|i| start + 1 + i)
+        .unwrap_or(text.len());
+    let name = &text[start + 1..name_end];
+    name.starts_with(|c: char| c.is_ascii_alphabetic()).then_some((start, name))
+}
+
+/// Innermost component element still open at the end of `text`.
+fn enclosing_component(text: &str) -> Option<&str> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while let Some(found) = text[i..].find('<') {
+        let start = i + found;
+        let rest = &text[start + 1..];
+        if rest.starts_with("!--") {
+            i = text[start..].find("-->").map(|e| start + e + 3).unwrap_or(text.len());
+            continue;
+        }
+        let closing = rest.starts_with('/');
+        let name_start = start + 1 + usize::from(closing);
+        let name_len = text[name_start..]
+            .find(|c: char| !(is_ident_char(c) || c == '-'))
+            .unwrap_or(text.len() - name_start);
+        let name = &text[name_start..name_start + name_len];
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            i = start + 1;
+            continue;
+        }
+        let Some(end) = tag_end(text, start) else {
+            break;
+        };
+        if closing {
+            if let Some(pos) = stack.iter().rposition(|open| *open == name) {
+                stack.truncate(pos);
+            }
+        } else if !text[..end].ends_with('/') && !is_void_element(name) {
+            stack.push(name);
+        }
+        i = end + 1;
+    }
+    stack.into_iter().rev().find(|name| name.starts_with(|c: char| c.is_ascii_uppercase()))
+}
+
+/// Byte offset of an LSP position (UTF-16 character offsets).
+fn offset_at(text: &str, line: usize, character: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// LSP position (line, UTF-16 character) of a byte offset.
+fn position_at(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line, before[line_start..].encode_utf16().count())
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // `%XX` with two ASCII hex digits; anything else keeps the `%`.
+        if bytes[i] == b'%' && i + 2 < bytes.len() && bytes[i + 1..i + 3].iter().all(u8::is_ascii_hexdigit) {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
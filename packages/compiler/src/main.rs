@@ -1,8 +1,16 @@
-use std::path::PathBuf;
+mod lsp;
+mod serve;
+
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
-use lumixjs::diagnostic::DiagnosticSeverity;
+use lumixjs::diagnostic::{has_errors, Diagnostic, DiagnosticSeverity};
 use lumixjs::error::CompileError;
+use lumixjs::ast::RenderMode;
+use lumixjs::cache::SourceCache;
+use lumixjs::codegen::{CodegenOptions, CodegenTarget, CommentOutput, CssOutput};
+use lumixjs::islands::StaticBuild;
+use lumixjs::sourcemap::SourceMap;
 use owo_colors::OwoColorize;
 
 /// lumixjs compiler CLI
@@ -39,7 +47,34 @@ enum Commands {
         /// Do not generate an index.html file
         #[arg(long)]
         no_html: bool,
+
+        /// Emit a Source Map v3 mapping the output back to the .lumix sources
+        #[arg(long, default_value = "none")]
+        source_map: SourceMapMode,
+
+        /// How component styles are delivered
+        #[arg(long, default_value = "injected")]
+        css: CssMode,
+
+        /// What the compiled components render to
+        #[arg(long, default_value = "dom")]
+        target: Target,
+
+        /// Whether template comments are kept in the output
+        #[arg(long, default_value = "strip")]
+        comments: CommentMode,
+
+        /// Prerender a "use static" page as islands: a server module plus one
+        /// hydration entry per interactive subtree
+        #[arg(long)]
+        islands: bool,
     },
+
+    /// Answer compile requests over stdin/stdout (JSON-RPC, one message per line)
+    Serve,
+
+    /// Run the language server for .lumix files over stdin/stdout
+    Lsp,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -48,6 +83,74 @@ enum OutputFormat {
     Json,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SourceMapMode {
+    /// No source map
+    None,
+    /// Write `<output>.js.map` beside the output
+    File,
+    /// Embed the map as a data URL in the output
+    Inline,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CssMode {
+    /// Components inject their <style> into document.head at runtime
+    Injected,
+    /// Write a separate stylesheet and skip runtime style injection
+    External,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    /// DOM nodes, for the browser
+    Dom,
+    /// HTML strings, for server-side rendering
+    Ssr,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CommentMode {
+    /// Drop `<!-- -->` comments (conditional comments are kept)
+    Strip,
+    /// Render comments into the output
+    Keep,
+}
+
+struct BuildOptions {
+    format: OutputFormat,
+    no_emit: bool,
+    bundle: bool,
+    no_html: bool,
+    source_map: SourceMapMode,
+    css: CssMode,
+    target: Target,
+    comments: CommentMode,
+    islands: bool,
+}
+
+struct BuildOutput {
+    js: String,
+    map: Option<SourceMap>,
+    css: Option<String>,
+    /// Warnings; a build with errors fails instead.
+    diagnostics: Vec<Diagnostic>,
+    /// Island manifest and entries, for `"use static"` pages.
+    static_build: Option<StaticBuild>,
+}
+
+/// What one compile produced, before anything is written.
+struct Compiled {
+    js: String,
+    map: SourceMap,
+    css: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+    /// Islands of a `"use static"` page; its `js`, `map` and `css` are moved
+    /// into the fields above.
+    static_build: Option<StaticBuild>,
+    bundled: bool,
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -59,29 +162,29 @@ fn main() {
             no_emit,
             no_bundle,
             no_html,
+            source_map,
+            css,
+            target,
+            comments,
+            islands,
         } => {
-            let bundle = !no_bundle;
-            match run_build(
-                input.clone(),
-                out.clone(),
-                format.clone(),
+            let options = BuildOptions {
+                format: format.clone(),
                 no_emit,
-                bundle,
+                bundle: !no_bundle,
                 no_html,
-            ) {
-                Ok(js) => {
+                source_map,
+                css,
+                target,
+                comments,
+                islands,
+            };
+            match run_build(input.clone(), out.clone(), options) {
+                Ok(BuildOutput { js, map, css, diagnostics, static_build }) => {
                 if matches!(format, OutputFormat::Json) {
                     let source = std::fs::read_to_string(&input).unwrap_or_default();
-                    let component = lumixjs::parser::parse_component(&source).unwrap();
-                    let component_name = input.file_stem().and_then(|s| s.to_str()).unwrap_or("Component");
-                    let ts = lumixjs::ts_codegen::generate_ts(&component, component_name);
-
-                    let payload = serde_json::json!({
-                        "file": input.display().to_string(),
-                        "js": js,
-                        "ts": ts,
-                        "diagnostics": [],
-                    });
+                    let payload =
+                        success_payload(&input, &source, &js, &css, map.as_ref(), static_build.as_ref(), &diagnostics);
                     println!("{}", serde_json::to_string_pretty(&payload).unwrap());
                 }
                 }
@@ -92,39 +195,8 @@ fn main() {
 
                     match format {
                         OutputFormat::Json => {
-                            let mut payload = serde_json::json!({
-                                "file": input.display().to_string(),
-                                "error": err.to_string(),
-                            });
-
-                            if let Some(ce) = err.downcast_ref::<CompileError>() {
-                                let range = match ce {
-                                    CompileError::Syntax { range, .. } => range,
-                                    CompileError::InvalidStructure { range, .. } => range,
-                                    CompileError::Template { range, .. } => range,
-                                };
-
-                                if let Some(r) = range {
-                                    if let Ok(source) = std::fs::read_to_string(&input) {
-                                        let starts =
-                                            lumixjs::diagnostic::compute_line_starts(&source);
-                                        let (start_lc, end_lc) =
-                                            lumixjs::diagnostic::range_to_line_cols(
-                                                &starts, r.start, r.end,
-                                            );
-
-                                        payload = serde_json::json!({
-                                           "file": input.display().to_string(),
-                                           "error": err.to_string(),
-                                           "line": start_lc.line,
-                                           "column": start_lc.col,
-                                           "endLine": end_lc.line,
-                                           "endColumn": end_lc.col
-                                        });
-                                    }
-                                }
-                            }
-
+                            let source = std::fs::read_to_string(&input).ok();
+                            let payload = error_payload(&input, source.as_deref(), &err);
                             println!("{}", serde_json::to_string_pretty(&payload).unwrap());
                         }
                         OutputFormat::Pretty => {
@@ -135,86 +207,291 @@ fn main() {
                 }
             }
         }
+        Commands::Serve => {
+            if let Err(err) = serve::run() {
+                eprintln!("error: {:#}", err);
+                std::process::exit(1);
+            }
+        }
+        Commands::Lsp => {
+            if let Err(err) = lsp::run() {
+                eprintln!("error: {:#}", err);
+                std::process::exit(1);
+            }
+        }
     }
 }
 
-fn run_build(
-    input: PathBuf,
-    out_dir: PathBuf,
-    format: OutputFormat,
-    no_emit: bool,
+fn codegen_options(css: CssMode, target: Target, comments: CommentMode) -> CodegenOptions {
+    CodegenOptions {
+        css: match css {
+            CssMode::Injected => CssOutput::Injected,
+            CssMode::External => CssOutput::External,
+        },
+        target: match target {
+            Target::Dom => CodegenTarget::Dom,
+            Target::Ssr => CodegenTarget::Ssr,
+        },
+        comments: match comments {
+            CommentMode::Strip => CommentOutput::Strip,
+            CommentMode::Keep => CommentOutput::Keep,
+        },
+    }
+}
+
+/// Compiles `input`, as a bundle when `bundle` is set. With `islands`, a
+/// `"use static"` page is prerendered instead: the page module plus one entry
+/// per island, whatever the bundling and target flags say. Files are read
+/// through `cache`, so in-memory sources take precedence over the disk.
+fn compile_input(
+    input: &Path,
     bundle: bool,
-    no_html: bool,
-) -> anyhow::Result<String> {
-    let (js, diags) = if bundle {
-        let res = lumixjs::bundler::bundle_entry(&input)?;
-        (res.js, res.diagnostics)
+    islands: bool,
+    options: &CodegenOptions,
+    cache: &mut SourceCache,
+) -> anyhow::Result<Compiled> {
+    let source = cache.read(input)?;
+    let is_static = islands
+        && match cache.parse(input, &source) {
+            Ok(component) => component.render_mode == RenderMode::Static,
+            Err(_) => false,
+        };
+
+    if is_static {
+        let mut build = lumixjs::compile_static_source(input, &source, cache)?;
+        return Ok(Compiled {
+            js: std::mem::take(&mut build.js),
+            map: std::mem::replace(&mut build.map, SourceMap::new(String::new())),
+            css: build.css.take(),
+            diagnostics: std::mem::take(&mut build.diagnostics),
+            static_build: Some(build),
+            bundled: false,
+        });
+    }
+
+    if bundle {
+        let res = lumixjs::bundler::bundle_entry_cached(input, options, cache)?;
+        Ok(Compiled {
+            js: res.js,
+            map: res.map,
+            css: (!res.css.is_empty()).then_some(res.css),
+            diagnostics: res.diagnostics,
+            static_build: None,
+            bundled: true,
+        })
     } else {
-        lumixjs::compile_file_with_diagnostics(&input)?
-    };
-    if !diags.is_empty() {
+        let res = lumixjs::compile_source_with_options(input, &source, options, cache)?;
+        Ok(Compiled {
+            js: res.js,
+            map: res.map,
+            css: res.css,
+            diagnostics: res.diagnostics,
+            static_build: None,
+            bundled: false,
+        })
+    }
+}
+
+/// Name of the emitted JS file: `bundle.js`, or `<name>.js` beside a module.
+fn output_file_name(input: &Path, bundled: bool) -> anyhow::Result<String> {
+    let file_name = input
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid input file name"))?;
+
+    Ok(if bundled {
+        "bundle.js".to_string()
+    } else if let Some(stripped) = file_name.strip_suffix(".lumix") {
+        format!("{}.js", stripped)
+    } else {
+        format!("{}.js", file_name)
+    })
+}
+
+/// Generated TypeScript (for type checking) and render mode of `source`,
+/// from a best-effort parse when the file has syntax errors.
+fn ts_and_render_mode(input: &Path, source: &str) -> (String, &'static str) {
+    let (component, _) = lumixjs::parser::parse_component_recovering(source);
+    let component_name = input.file_stem().and_then(|s| s.to_str()).unwrap_or("Component");
+    (
+        lumixjs::ts_codegen::generate_ts(&component, component_name),
+        component.render_mode.as_str(),
+    )
+}
+
+/// `--format json` output of a successful build.
+fn success_payload(
+    input: &Path,
+    source: &str,
+    js: &str,
+    css: &Option<String>,
+    map: Option<&SourceMap>,
+    static_build: Option<&StaticBuild>,
+    warnings: &[Diagnostic],
+) -> serde_json::Value {
+    let (ts, render_mode) = ts_and_render_mode(input, source);
+    let mut payload = serde_json::json!({
+        "file": input.display().to_string(),
+        "js": js,
+        "ts": ts,
+        "css": css,
+        "renderMode": render_mode,
+        "diagnostics": warnings,
+    });
+    if let Some(map) = map {
+        payload["map"] = map.to_json();
+    }
+    if let Some(build) = static_build {
+        let file_name = input.file_name().and_then(|n| n.to_str()).unwrap_or("Component.lumix");
+        payload["islands"] = build.manifest(file_name);
+        payload["islandEntries"] = build
+            .client_modules()
+            .map(|(entry, js)| (entry.to_string(), serde_json::Value::String(js.to_string())))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+    payload
+}
+
+fn print_diagnostics(input: &Path, diags: &[Diagnostic]) {
+    for d in diags {
+        let severity = match d.severity {
+            DiagnosticSeverity::Error => "error".red().bold().to_string(),
+            DiagnosticSeverity::Warning => "warning".yellow().bold().to_string(),
+        };
+        let loc = format!("{}:{}:{}", input.display(), d.start.line, d.start.col);
+        eprintln!("{}: {}: {}", loc.cyan(), severity, d.message);
+    }
+}
+
+/// `--format json` output of a build that reported errors.
+fn diagnostics_payload(input: &Path, source: &str, diags: &[Diagnostic]) -> serde_json::Value {
+    let (ts, render_mode) = ts_and_render_mode(input, source);
+    serde_json::json!({
+        "file": input.display().to_string(),
+        "ts": ts,
+        "renderMode": render_mode,
+        "diagnostics": diags,
+    })
+}
+
+/// `--format json` output of a build that failed outright.
+fn error_payload(input: &Path, source: Option<&str>, err: &anyhow::Error) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "file": input.display().to_string(),
+        "error": err.to_string(),
+    });
+
+    if let Some(ce) = err.downcast_ref::<CompileError>() {
+        if let (Some(r), Some(source)) = (ce.range(), source) {
+            let starts = lumixjs::diagnostic::compute_line_starts(source);
+            let (start_lc, end_lc) = lumixjs::diagnostic::range_to_line_cols(&starts, r.start, r.end);
+
+            payload = serde_json::json!({
+               "file": input.display().to_string(),
+               "error": err.to_string(),
+               "line": start_lc.line,
+               "column": start_lc.col,
+               "endLine": end_lc.line,
+               "endColumn": end_lc.col
+            });
+        }
+    }
+    payload
+}
+
+fn run_build(input: PathBuf, out_dir: PathBuf, options: BuildOptions) -> anyhow::Result<BuildOutput> {
+    let BuildOptions {
+        format,
+        no_emit,
+        bundle,
+        no_html,
+        source_map,
+        css: css_mode,
+        target,
+        comments,
+        islands,
+    } = options;
+
+    let codegen_options = codegen_options(css_mode, target, comments);
+    let Compiled {
+        js,
+        mut map,
+        css,
+        diagnostics: diags,
+        static_build,
+        bundled: bundle,
+    } = compile_input(&input, bundle, islands, &codegen_options, &mut SourceCache::new())?;
+    if has_errors(&diags) {
         match format {
             OutputFormat::Json => {
                 let source = std::fs::read_to_string(&input).unwrap_or_default();
-                let component_res = lumixjs::parser::parse_component(&source);
-                let ts = if let Ok(component) = component_res {
-                    let component_name = input.file_stem().and_then(|s| s.to_str()).unwrap_or("Component");
-                    lumixjs::ts_codegen::generate_ts(&component, component_name)
-                } else {
-                    String::new()
-                };
-
-                let payload = serde_json::json!({
-                    "file": input.display().to_string(),
-                    "ts": ts,
-                    "diagnostics": diags,
-                });
+                let payload = diagnostics_payload(&input, &source, &diags);
                 println!("{}", serde_json::to_string_pretty(&payload)?);
             }
-            OutputFormat::Pretty => {
-                for d in diags {
-                    let (sev, sev_colored) = match d.severity {
-                        DiagnosticSeverity::Error => ("error", "error".red().bold().to_string()),
-                        DiagnosticSeverity::Warning => ("warning", "warning".yellow().bold().to_string()),
-                    };
-                    let loc = format!("{}:{}:{}", input.display(), d.start.line, d.start.col);
-                    eprintln!(
-                        "{}: {}: {}",
-                        loc.cyan(),
-                        sev_colored,
-                        d.message
-                    );
-                    let _ = sev;
-                }
-            }
+            OutputFormat::Pretty => print_diagnostics(&input, &diags),
         }
         return Err(anyhow::anyhow!("build failed"));
     }
+    // Only warnings: reported, and the build goes on.
+    if matches!(format, OutputFormat::Pretty) {
+        print_diagnostics(&input, &diags);
+    }
+
+    let file_name = input
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid input file name"))?;
+
+    let out_file_name = output_file_name(&input, bundle)?;
+    map.set_file(out_file_name.clone());
+
+    let js = if source_map == SourceMapMode::Inline {
+        format!("{}\n//# sourceMappingURL={}\n", js.trim_end(), map.to_data_url())
+    } else {
+        js
+    };
+    let map = (source_map != SourceMapMode::None).then_some(map);
 
     if no_emit {
         if matches!(format, OutputFormat::Pretty) {
             println!("{}", "ok (no-emit)".green().bold());
         }
-        return Ok(js);
+        return Ok(BuildOutput { js, map, css, diagnostics: diags, static_build });
     }
 
     std::fs::create_dir_all(&out_dir)?;
 
-    let file_name = input
-        .file_name()
-        .and_then(|n| n.to_str())
-        .ok_or_else(|| anyhow::anyhow!("invalid input file name"))?;
-
-    let out_file_name = if bundle {
-        "bundle.js".to_string()
-    } else if let Some(stripped) = file_name.strip_suffix(".lumin") {
-        format!("{}.js", stripped)
+    let out_path = out_dir.join(&out_file_name);
+    if let (SourceMapMode::File, Some(map)) = (source_map, map.as_ref()) {
+        let map_file_name = format!("{}.map", out_file_name);
+        std::fs::write(out_dir.join(&map_file_name), map.to_json_string())?;
+        std::fs::write(
+            &out_path,
+            format!("{}\n//# sourceMappingURL={}\n", js.trim_end(), map_file_name),
+        )?;
     } else {
-        format!("{}.js", file_name)
+        std::fs::write(&out_path, &js)?;
+    }
+
+    // Standalone stylesheet next to the JS (bundle.css in bundle mode)
+    let css_file_name = match (&css, css_mode) {
+        (Some(css), CssMode::External) => {
+            let name = out_file_name.trim_end_matches(".js").to_string() + ".css";
+            std::fs::write(out_dir.join(&name), css)?;
+            Some(name)
+        }
+        _ => None,
     };
 
-    let out_path = out_dir.join(out_file_name);
-    std::fs::write(&out_path, &js)?;
+    if let Some(build) = &static_build {
+        for (entry, js) in build.client_modules() {
+            std::fs::write(out_dir.join(entry), js)?;
+        }
+        let manifest = build.manifest(file_name);
+        let manifest_name = out_file_name.trim_end_matches(".js").to_string() + ".islands.json";
+        std::fs::write(out_dir.join(manifest_name), serde_json::to_string_pretty(&manifest)?)?;
+    }
 
     if !bundle {
         // Copy runtime.js if it exists in the project directory (module mode)
@@ -225,8 +502,17 @@ fn run_build(
         }
     }
 
-    if no_html {
-        return Ok(js);
+    // Server modules export `render`, not `hydrate`, so there is nothing to wire up.
+    if no_html || target == Target::Ssr || static_build.is_some() {
+        if matches!(format, OutputFormat::Pretty) {
+            println!(
+                "{} {} -> {}",
+                "compiled".green().bold(),
+                input.display(),
+                out_path.display()
+            );
+        }
+        return Ok(BuildOutput { js, map, css, diagnostics: diags, static_build });
     }
 
     // Generate a simple index.html in the output directory that wires up hydrate
@@ -235,33 +521,12 @@ fn run_build(
         .and_then(|n| n.to_str())
         .ok_or_else(|| anyhow::anyhow!("invalid output file name"))?;
 
-    let index_html = if bundle {
-        format!(
-            r#"<!doctype html>
-<html lang="en">
-  <head>
-    <meta charset="utf-8" />
-    <title>lumixjs App</title>
-  </head>
-  <body>
-    <div id="app"></div>
-
-    <script type="module">
-      import {{ hydrate }} from './{0}';
-      hydrate(document.getElementById('app'));
-    </script>
-  </body>
-</html>
-"#,
-            module_file
-        )
-    } else {
-        format!(
-            r#"<!doctype html>
+    let index_html = format!(
+        r#"<!doctype html>
 <html lang="en">
   <head>
     <meta charset="utf-8" />
-    <title>lumixjs App</title>
+    <title>lumixjs App</title>{1}
   </head>
   <body>
     <div id="app"></div>
@@ -273,9 +538,11 @@ fn run_build(
   </body>
 </html>
 "#,
-            module_file
-        )
-    };
+        module_file,
+        css_file_name
+            .map(|name| format!("\n    <link rel=\"stylesheet\" href=\"./{}\" />", name))
+            .unwrap_or_default()
+    );
 
     let index_path = out_dir.join("index.html");
     std::fs::write(index_path, index_html)?;
@@ -289,5 +556,5 @@ fn run_build(
         );
     }
 
-    Ok(js)
+    Ok(BuildOutput { js, map, css, diagnostics: diags, static_build })
 }
@@ -1,17 +1,42 @@
 use crate::ast::*;
 use crate::error::CompileError;
-use crate::diagnostic::SourceRange as DiagnosticSourceRange;
+use crate::html;
+use crate::diagnostic::{compute_line_starts, Diagnostic, SourceRange as DiagnosticSourceRange};
 
-use swc_common::{FileName, SourceMap, DUMMY_SP, Spanned};
+use swc_common::{sync::Lrc, FileName, SourceMap, DUMMY_SP, Spanned};
 use swc_ecma_ast::{ModuleDecl, ModuleItem, Decl, Pat, Callee};
 use swc_ecma_parser::{Parser, StringInput, Syntax, TsConfig};
-use crate::transpiler::{transpile_ts_module, emit_module_to_string};
+use crate::transpiler::{transpile_ts_module, emit_module_to_string, emit_module_with_mappings};
+use crate::codegen::js_string;
 
 /// Robust parser for LuminJS components:
 /// - Detects an optional `--- ... ---` import block at the beginning.
 /// - Detects `<script>` and `<style>` blocks in any order.
 /// - Parses the template as a reactive DOM.
+///
+/// Fails with the first syntax error; see `parse_component_recovering` for
+/// all of them.
 pub fn parse_component(source: &str) -> Result<ComponentFile, CompileError> {
+    let (component, errors) = parse_component_with_errors(source);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(component),
+    }
+}
+
+/// Error-recovering parse: a best-effort component, even for a partly broken
+/// file, plus every syntax error found in it.
+pub fn parse_component_recovering(source: &str) -> (ComponentFile, Vec<Diagnostic>) {
+    let (component, errors) = parse_component_with_errors(source);
+    let line_starts = compute_line_starts(source);
+    let diagnostics = errors.iter().map(|err| err.to_diagnostic(&line_starts)).collect();
+    (component, diagnostics)
+}
+
+/// The parser proper. Each syntax error is recorded and parsing resumes at
+/// the next point that makes sense (the end of the attribute, tag, line or
+/// block), so one mistake doesn't hide the rest of the file.
+pub(crate) fn parse_component_with_errors(source: &str) -> (ComponentFile, Vec<CompileError>) {
     let mut parser = MarkupParser::new(source, 0);
     let mut component = ComponentFile {
         imports: Vec::new(),
@@ -20,6 +45,7 @@ pub fn parse_component(source: &str) -> Result<ComponentFile, CompileError> {
         template: Vec::new(),
         defined_slots: Vec::new(),
         head: None,
+        render_mode: RenderMode::default(),
     };
 
     parser.skip_ws();
@@ -31,12 +57,12 @@ pub fn parse_component(source: &str) -> Result<ComponentFile, CompileError> {
         let end_idx = parser.input[start..].find("---");
         if let Some(end) = end_idx {
             let imports_block = &parser.input[start..start + end];
-            component.imports = parse_imports_block(imports_block)?;
+            component.imports = parse_imports_block(imports_block, start, &mut parser.errors);
             parser.pos = start + end + 3;
         } else {
-            return Err(CompileError::Syntax {
+            parser.errors.push(CompileError::Syntax {
                 message: "imports block '---' without closing '---'".into(),
-                range: Some(DiagnosticSourceRange { start: 0, end: 3 }),
+                range: Some(DiagnosticSourceRange { start: start - 3, end: start }),
             });
         }
     }
@@ -49,66 +75,114 @@ pub fn parse_component(source: &str) -> Result<ComponentFile, CompileError> {
         }
 
         if parser.starts_with("<script>") {
+            let script_start = parser.pos;
+            parser.pos += 8; // skip <script>
+            let code = parser.parse_script_or_style_block("</script>");
+
             if component.script.is_some() {
-                return Err(CompileError::Template {
+                parser.errors.push(CompileError::Template {
                     message: "only one <script> block is allowed".into(),
-                    range: None,
+                    range: Some(DiagnosticSourceRange {
+                        start: parser.base_offset + script_start,
+                        end: parser.base_offset + script_start + 8,
+                    }),
                 });
+                continue;
             }
-            let script_start = parser.pos;
-            parser.pos += 8; // skip <script>
-            
-            let (code, end_pos) = parser.parse_script_or_style_block("</script>")?;
             let abs_start = parser.base_offset + script_start + 8;
-            
-            let (cleaned_code, props, imports, head_metadata) = parse_script_block_contents(&code)?;
-
-            component.script = Some(ScriptBlock {
-                code: cleaned_code,
-                original_code: code.to_string(),
-                props,
-                imports,
-                span: Some(SourceRange {
-                    start: abs_start,
-                    end: abs_start + code.len(),
-                }),
+            let span = Some(SourceRange {
+                start: abs_start,
+                end: abs_start + code.len(),
             });
-            
-            // Store head metadata if found
-            if let Some(head) = head_metadata {
-                component.head = Some(head);
+
+            match parse_script_block_contents(&code, abs_start) {
+                Ok((cleaned_code, mappings, props, imports, head_metadata, directives)) => {
+                    let directives: Vec<RenderDirective> = directives
+                        .into_iter()
+                        .map(|d| RenderDirective {
+                            span: SourceRange {
+                                start: abs_start + d.span.start,
+                                end: abs_start + d.span.end,
+                            },
+                            ..d
+                        })
+                        .collect();
+                    if let Some(d) = directives.iter().find(|d| d.in_prologue) {
+                        component.render_mode = d.mode;
+                    }
+
+                    component.script = Some(ScriptBlock {
+                        code: cleaned_code,
+                        original_code: code,
+                        props,
+                        imports,
+                        span,
+                        mappings,
+                        directives,
+                    });
+
+                    // Store head metadata if found
+                    if let Some(head) = head_metadata {
+                        component.head = Some(head);
+                    }
+                }
+                Err(err) => {
+                    parser.errors.push(err);
+                    // Keep the source so the script can still be type-checked.
+                    component.script = Some(ScriptBlock {
+                        code: code.clone(),
+                        original_code: code,
+                        props: Vec::new(),
+                        imports: Vec::new(),
+                        span,
+                        mappings: Vec::new(),
+                        directives: Vec::new(),
+                    });
+                }
             }
-            
-            parser.pos = end_pos + 9; // skip </script>
             continue;
         }
 
-        if parser.starts_with("<style>") {
+        if parser.starts_with_style_open_tag() {
+            let tag_start = parser.pos;
+            parser.pos += 6; // skip <style
+            let attributes = parser.parse_attributes_with_ranges();
+            parser.expect_recovering(">");
+            let global = match style_block_is_global(&attributes, source) {
+                Ok(global) => global,
+                Err(err) => {
+                    parser.errors.push(err);
+                    false
+                }
+            };
+            let style_start = parser.pos;
+            let code = parser.parse_script_or_style_block("</style>");
+
             if component.style.is_some() {
-                return Err(CompileError::Template {
+                parser.errors.push(CompileError::Template {
                     message: "only one <style> block is allowed".into(),
-                    range: None,
+                    range: Some(DiagnosticSourceRange {
+                        start: parser.base_offset + tag_start,
+                        end: parser.base_offset + tag_start + 6,
+                    }),
                 });
+                continue;
             }
-            let style_start = parser.pos;
-            parser.pos += 7; // skip <style>
-            
-            let (code, end_pos) = parser.parse_script_or_style_block("</style>")?;
-            let abs_start = parser.base_offset + style_start + 7;
-            
+            let abs_start = parser.base_offset + style_start;
+
             component.style = Some(StyleBlock {
-                code: code.to_string(),
                 span: Some(SourceRange {
                     start: abs_start,
                     end: abs_start + code.len(),
                 }),
+                code,
+                global,
             });
-            parser.pos = end_pos + 8; // skip </style>
             continue;
         }
 
         // Must be template
-        let nodes = parser.parse_nodes(None, None)?;
+        let nodes = parser.parse_nodes(None, None);
         if nodes.is_empty() && !parser.is_eof() {
              // If we didn't get any nodes but aren't at EOF, something is wrong
              // (e.g. we're stuck at a tag we didn't handle)
@@ -117,9 +191,326 @@ pub fn parse_component(source: &str) -> Result<ComponentFile, CompileError> {
         component.template.extend(nodes);
     }
 
+    html::normalize_whitespace(&mut component.template, false);
     component.defined_slots = collect_slots(&component.template);
 
-    Ok(component)
+    (component, parser.errors)
+}
+
+struct ForHeader {
+    binding: String,
+    iterable: JsExpr,
+    index: Option<String>,
+    key_expr: Option<String>,
+}
+
+/// The inside of `@{for (...)}`: `binding of iterable`, parsed with swc like
+/// a JS `for...of` head, then optional `; index=name` and `; key=expr`
+/// clauses. `offset` is where `header` starts in the source.
+fn parse_for_header(header: &str, offset: usize) -> Result<ForHeader, Vec<CompileError>> {
+    let mut errors = Vec::new();
+    let mut clauses = split_top_level(header, ';').into_iter();
+    let (head_start, head) = clauses.next().unwrap_or((0, header));
+
+    let mut index = None;
+    let mut key_expr = None;
+    for (clause_start, clause) in clauses {
+        let trimmed = clause.trim();
+        let range = Some(DiagnosticSourceRange {
+            start: offset + clause_start + (clause.len() - clause.trim_start().len()),
+            end: offset + clause_start + clause.trim_end().len(),
+        });
+        match trimmed.split_once('=').map(|(name, value)| (name.trim(), value.trim())) {
+            Some(("key", key)) if !key.is_empty() => key_expr = Some(key.to_string()),
+            Some(("index", name)) if is_js_identifier(name) => index = Some(name.to_string()),
+            Some(("index", name)) => errors.push(CompileError::Template {
+                message: format!("'index=' needs a variable name, not '{name}'"),
+                range,
+            }),
+            _ => errors.push(CompileError::Template {
+                message: format!("unknown clause '{trimmed}' in @{{for}}; expected 'index=name' or 'key=expr'"),
+                range,
+            }),
+        }
+    }
+
+    match parse_for_of(head, offset + head_start) {
+        Ok((binding, iterable)) if errors.is_empty() => Ok(ForHeader {
+            binding,
+            iterable,
+            index,
+            key_expr,
+        }),
+        Ok(_) => Err(errors),
+        Err(err) => {
+            errors.insert(0, err);
+            Err(errors)
+        }
+    }
+}
+
+/// `item of items` (optionally after `let`/`const`), where `item` may be a
+/// destructuring pattern: the pattern as written and the iterable.
+fn parse_for_of(head: &str, offset: usize) -> Result<(String, JsExpr), CompileError> {
+    const PREFIX: &str = "for (";
+    let source = format!("{PREFIX}{head}) {{}}");
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("for.ts".into()), source);
+    let base = fm.start_pos.0 as usize + PREFIX.len();
+    // Source range of an swc span, clamped to the header.
+    let range = |span: swc_common::Span| {
+        let start = (span.lo.0 as usize).saturating_sub(base).min(head.len());
+        let end = (span.hi.0 as usize).saturating_sub(base).clamp(start, head.len());
+        (start, end)
+    };
+    let error = |message: String, (start, end): (usize, usize)| CompileError::Template {
+        message,
+        range: Some(DiagnosticSourceRange {
+            start: offset + start,
+            end: offset + end,
+        }),
+    };
+
+    let syntax = Syntax::Typescript(TsConfig::default());
+    let mut parser = Parser::new(syntax, StringInput::from(&*fm), None);
+    let stmt = parser
+        .parse_module()
+        .map_err(|e| error(format!("invalid @{{for}} header: {}", e.kind().msg()), range(e.span())))?
+        .body
+        .into_iter()
+        .next();
+    let for_of = match stmt {
+        Some(ModuleItem::Stmt(swc_ecma_ast::Stmt::ForOf(for_of))) if !for_of.is_await => for_of,
+        _ => {
+            return Err(error(
+                "expected 'item of list' in @{for}, e.g. @{for (const item of items)}".into(),
+                (0, head.len()),
+            ))
+        }
+    };
+
+    let pattern = match &for_of.left {
+        swc_ecma_ast::ForHead::VarDecl(decl) if decl.decls.len() == 1 => decl.decls[0].name.span(),
+        swc_ecma_ast::ForHead::Pat(pat) => pat.span(),
+        left => {
+            return Err(error(
+                "expected one loop variable or destructuring pattern in @{for}".into(),
+                range(left.span()),
+            ))
+        }
+    };
+    let (start, end) = range(pattern);
+    let binding = head[start..end].to_string();
+    let (start, end) = range(for_of.right.span());
+    let iterable = JsExpr {
+        code: head[start..end].to_string(),
+        span: Some(SourceRange {
+            start: offset + start,
+            end: offset + end,
+        }),
+    };
+    Ok((binding, iterable))
+}
+
+/// Names bound by a binding pattern.
+fn pattern_names(pat: &Pat, out: &mut Vec<String>) {
+    match pat {
+        Pat::Ident(ident) => out.push(ident.id.sym.to_string()),
+        Pat::Array(array) => {
+            for elem in array.elems.iter().flatten() {
+                pattern_names(elem, out);
+            }
+        }
+        Pat::Object(object) => {
+            for prop in &object.props {
+                match prop {
+                    swc_ecma_ast::ObjectPatProp::KeyValue(kv) => pattern_names(&kv.value, out),
+                    swc_ecma_ast::ObjectPatProp::Assign(assign) => out.push(assign.key.sym.to_string()),
+                    swc_ecma_ast::ObjectPatProp::Rest(rest) => pattern_names(&rest.arg, out),
+                }
+            }
+        }
+        Pat::Rest(rest) => pattern_names(&rest.arg, out),
+        Pat::Assign(assign) => pattern_names(&assign.left, out),
+        Pat::Invalid(_) | Pat::Expr(_) => {}
+    }
+}
+
+/// Checks that `code` is a variable name or destructuring pattern, as in
+/// `then (value)`.
+fn parse_binding_pattern(code: &str) -> Result<(), String> {
+    let invalid = || format!("expected a variable name or destructuring pattern, found '{code}'");
+    if code.is_empty() {
+        return Err(invalid());
+    }
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("binding.ts".into()), format!("({code}) => {{}}"));
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    match parser.parse_expr() {
+        Ok(expr) if parser.take_errors().is_empty() => match *expr {
+            swc_ecma_ast::Expr::Arrow(arrow) if arrow.params.len() == 1 => Ok(()),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
+
+/// Checks a snippet's parameter list, parsed as that of an arrow function.
+fn parse_snippet_params(code: &str) -> Result<(), String> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("params.ts".into()), format!("({code}) => {{}}"));
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    match parser.parse_expr() {
+        Ok(expr) if parser.take_errors().is_empty() && matches!(*expr, swc_ecma_ast::Expr::Arrow(_)) => Ok(()),
+        _ => Err(format!("invalid snippet parameters '{code}'")),
+    }
+}
+
+/// `params` with `: any` added to each parameter that has no type, so that
+/// strict type checking accepts snippets written without types.
+pub(crate) fn annotate_snippet_params(params: &str) -> String {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Custom("params.ts".into()), format!("({params}) => {{}}"));
+    let base = fm.start_pos.0 as usize + 1;
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+    let arrow = match parser.parse_expr().map(|expr| *expr) {
+        Ok(swc_ecma_ast::Expr::Arrow(arrow)) => arrow,
+        _ => return params.to_string(),
+    };
+    let mut out = params.to_string();
+    for pat in arrow.params.iter().rev() {
+        let annotation = match pat {
+            Pat::Ident(ident) if ident.type_ann.is_none() => ": any",
+            Pat::Object(object) if object.type_ann.is_none() => ": any",
+            Pat::Array(array) if array.type_ann.is_none() => ": any",
+            Pat::Rest(rest) if rest.type_ann.is_none() => ": any[]",
+            // Typed from the default value or already annotated.
+            _ => continue,
+        };
+        out.insert_str(pat.span().hi.0 as usize - base, annotation);
+    }
+    out
+}
+
+/// Splits `s` at each `sep` outside brackets, braces, parentheses and string
+/// literals, with the byte offset of each part.
+fn split_top_level(s: &str, sep: char) -> Vec<(usize, &str)> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push((start, &s[start..i]));
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push((start, &s[start..]));
+    parts
+}
+
+fn is_js_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// `name={expr}`, classified by its name.
+fn expression_attribute(name: String, expr: JsExpr, modifiers: Vec<EventModifier>) -> AttributeNode {
+    if let Some(property) = name.strip_prefix("bind:") {
+        AttributeNode::Bind {
+            property: property.to_string(),
+            expr,
+        }
+    } else if let Some(class) = name.strip_prefix("class:") {
+        AttributeNode::Class {
+            name: class.to_string(),
+            expr,
+        }
+    } else if let Some(property) = name.strip_prefix("style:") {
+        AttributeNode::Style {
+            property: property.to_string(),
+            expr,
+        }
+    } else if name.starts_with("on") {
+        AttributeNode::EventHandler { name, expr, modifiers }
+    } else {
+        AttributeNode::Dynamic { name, expr }
+    }
+}
+
+/// `onClick|preventDefault|once` into the name and its validated modifiers.
+fn split_event_modifiers(name: &str) -> Result<(String, Vec<EventModifier>), String> {
+    let mut parts = name.split('|');
+    let base = parts.next().unwrap_or_default().to_string();
+    let mut modifiers: Vec<EventModifier> = Vec::new();
+    for part in parts {
+        if !base.starts_with("on") {
+            return Err(format!("modifiers are only allowed on event handlers, not '{base}'"));
+        }
+        let Some(modifier) = EventModifier::from_name(part) else {
+            let known: Vec<&str> = EventModifier::ALL.iter().map(|m| m.name()).collect();
+            return Err(format!("unknown event modifier '{part}'; expected one of {}", known.join(", ")));
+        };
+        if modifiers.contains(&modifier) {
+            return Err(format!("duplicate event modifier '{part}'"));
+        }
+        modifiers.push(modifier);
+    }
+    if modifiers.contains(&EventModifier::Passive) && modifiers.contains(&EventModifier::PreventDefault) {
+        return Err("'passive' and 'preventDefault' cannot be combined: passive listeners cannot cancel the event".into());
+    }
+    Ok((base, modifiers))
+}
+
+/// Only a bare `global` attribute (opting out of scoped CSS) is allowed on `<style>`.
+fn style_block_is_global(
+    attributes: &[(AttributeNode, DiagnosticSourceRange)],
+    source: &str,
+) -> Result<bool, CompileError> {
+    let mut global = false;
+    for (attr, range) in attributes {
+        let message = match attr {
+            AttributeNode::Static { name, .. } if name == "global" => {
+                if source.get(range.start..range.end) == Some("global") {
+                    global = true;
+                    continue;
+                }
+                "'global' on <style> takes no value".to_string()
+            }
+            AttributeNode::Static { name, .. }
+            | AttributeNode::Dynamic { name, .. }
+            | AttributeNode::EventHandler { name, .. } => {
+                format!("unsupported attribute '{name}' on <style>; only 'global' is allowed")
+            }
+            AttributeNode::Bind { property, .. } => format!("unsupported attribute 'bind:{property}' on <style>"),
+            AttributeNode::Spread { .. } => "unsupported attribute spread on <style>".into(),
+            AttributeNode::Class { name, .. } => format!("unsupported attribute 'class:{name}' on <style>"),
+            AttributeNode::Style { property, .. } => format!("unsupported attribute 'style:{property}' on <style>"),
+            AttributeNode::Action { action, .. } => format!("unsupported attribute 'use:{}' on <style>", action.code),
+        };
+        return Err(CompileError::Template {
+            message,
+            range: Some(range.clone()),
+        });
+    }
+    Ok(global)
 }
 
 fn collect_slots(nodes: &[TemplateNode]) -> Vec<String> {
@@ -152,64 +543,124 @@ fn collect_slots(nodes: &[TemplateNode]) -> Vec<String> {
                 ControlFlowBlock::For { body, .. } => {
                     slots.extend(collect_slots(body));
                 }
+                ControlFlowBlock::Await {
+                    pending,
+                    then_branch,
+                    catch_branch,
+                    ..
+                } => {
+                    slots.extend(collect_slots(pending));
+                    slots.extend(collect_slots(then_branch));
+                    if let Some(branch) = catch_branch {
+                        slots.extend(collect_slots(branch));
+                    }
+                }
+                ControlFlowBlock::Switch { cases, default, .. } => {
+                    for (_, branch) in cases {
+                        slots.extend(collect_slots(branch));
+                    }
+                    if let Some(branch) = default {
+                        slots.extend(collect_slots(branch));
+                    }
+                }
             },
+            TemplateNode::Snippet(snippet) => slots.extend(collect_slots(&snippet.body)),
+            // `{@render props.slots.row(item)}` renders the `row` slot.
+            TemplateNode::Render(tag) => {
+                let callee: String = tag.snippet.code.split_whitespace().collect();
+                let name = match callee.as_str() {
+                    "props.children" => Some("children"),
+                    _ => callee.strip_prefix("props.slots.").or_else(|| callee.strip_prefix("props.slots?.")),
+                };
+                if let Some(name) = name.filter(|n| n.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$')) {
+                    slots.push(name.to_string());
+                }
+            }
             _ => {}
         }
     }
     slots
 }
 
-fn parse_imports_block(block: &str) -> Result<Vec<ComponentImport>, CompileError> {
+/// Imports of the `---` block starting at `offset`. A bad line is recorded
+/// in `errors` and skipped.
+fn parse_imports_block(block: &str, offset: usize, errors: &mut Vec<CompileError>) -> Vec<ComponentImport> {
     let mut imports: Vec<ComponentImport> = Vec::new();
 
-    for line in block.lines() {
-        let line: &str = line.trim();
+    let mut line_start = offset;
+    for raw_line in block.split_inclusive('\n') {
+        let line: &str = raw_line.trim();
+        let leading_ws = raw_line.len() - raw_line.trim_start().len();
+        let range = Some(DiagnosticSourceRange {
+            start: line_start + leading_ws,
+            end: line_start + leading_ws + line.len(),
+        });
+        line_start += raw_line.len();
         if line.is_empty() {
             continue;
         }
-        if !line.starts_with("import ") {
-            return Err(CompileError::Syntax {
-                message: format!("invalid import line in imports block: {line}"),
-                range: None, // Simplified for now
-            });
-        }
-        // Parse `import ... from "./X.lumin"`
-        let from_idx: usize = line.find("from ").ok_or_else(|| {
-            CompileError::Syntax {
-                message: format!("import without 'from' in imports block: {line}"),
-                range: None,
-            }
-        })?;
-        let spec_part: &str = line["import ".len()..from_idx].trim();
-        let source_part: &str = line[from_idx + 5..].trim().trim_end_matches(';');
-        let source: String = source_part
-            .trim()
-            .trim_matches(|c| c == '"' || c == '\'')
-            .to_string();
-
-        if !source.ends_with(".lumix") {
-            return Err(CompileError::InvalidStructure {
-                message: format!("only .lumix component imports are allowed in the --- block: {source}"),
-                range: None,
-            });
+        match parse_import_line(line) {
+            Ok(import) => imports.push(import),
+            Err(err) => errors.push(err.with_range(range)),
         }
+    }
 
-        let specifiers = parse_import_specifiers(spec_part)?;
-        if specifiers.is_empty() {
-            return Err(CompileError::Syntax {
-                message: format!("import missing specifiers: {line}"),
-                range: None,
-            });
+    imports
+}
+
+fn parse_import_line(line: &str) -> Result<ComponentImport, CompileError> {
+    if !line.starts_with("import ") {
+        return Err(CompileError::Syntax {
+            message: format!("invalid import line in imports block: {line}"),
+            range: None,
+        });
+    }
+    // Parse `import ... from "./X.lumin"`
+    let from_idx: usize = line.find("from ").ok_or_else(|| {
+        CompileError::Syntax {
+            message: format!("import without 'from' in imports block: {line}"),
+            range: None,
         }
+    })?;
+    let spec_part: &str = line["import ".len()..from_idx].trim();
+    let source_part: &str = line[from_idx + 5..].trim().trim_end_matches(';');
+    let source: String = source_part
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .to_string();
+
+    if !source.ends_with(".lumix") {
+        return Err(CompileError::InvalidStructure {
+            message: format!("only .lumix component imports are allowed in the --- block: {source}"),
+            range: None,
+        });
+    }
 
-        imports.push(ComponentImport { specifiers, source });
+    let specifiers = parse_import_specifiers(spec_part)?;
+    if specifiers.is_empty() {
+        return Err(CompileError::Syntax {
+            message: format!("import missing specifiers: {line}"),
+            range: None,
+        });
     }
 
-    Ok(imports)
+    Ok(ComponentImport { specifiers, source })
 }
 
-fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<ScriptImport>, Option<HeadMetadata>), CompileError> {
-    let cm: SourceMap = Default::default();
+/// Cleaned script code (with its mappings back to the original), detected
+/// props, hoisted imports and `export const head`.
+type ScriptBlockContents = (
+    String,
+    Vec<CodeMapping>,
+    Vec<Prop>,
+    Vec<ScriptImport>,
+    Option<HeadMetadata>,
+    Vec<RenderDirective>,
+);
+
+/// `offset` is where `code` starts in the component, for error ranges.
+fn parse_script_block_contents(code: &str, offset: usize) -> Result<ScriptBlockContents, CompileError> {
+    let cm: Lrc<SourceMap> = Default::default();
     let fm = cm.new_source_file(FileName::Custom("script.ts".into()), code.to_string());
     
     let syntax = Syntax::Typescript(TsConfig {
@@ -220,13 +671,19 @@ fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<Scr
     let module = match parser.parse_module() {
         Ok(m) => m,
         Err(e) => {
+             let span = e.span();
              return Err(CompileError::Template {
                  message: format!("JS parse error in <script>: {}", e.kind().msg()),
-                 range: None,
+                 range: Some(DiagnosticSourceRange {
+                     start: offset + (span.lo.0 - fm.start_pos.0) as usize,
+                     end: offset + (span.hi.0 - fm.start_pos.0) as usize,
+                 }),
              });
         }
     };
 
+    let directives = collect_render_directives(&module, &fm);
+
     // Transpile the WHOLE module once to get type stripping and resolver context right.
     let transpiled_module = transpile_ts_module(module);
 
@@ -297,11 +754,11 @@ fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<Scr
                         if let swc_ecma_ast::Expr::Call(call) = &**init {
                             if let Callee::Expr(callee_expr) = &call.callee {
                                 if let swc_ecma_ast::Expr::Ident(id) = &**callee_expr {
-                                    if id.sym.as_ref() == "prop" {
+                                    if matches!(id.sym.as_ref(), "prop" | "model") {
                                         is_prop_decl = true;
                                         // Handle props/signals
                                         if let Pat::Ident(binding) = &decl.name {
-                                            let original_default_value = call.args.get(0).map(|arg| {
+                                            let original_default_value = call.args.first().map(|arg| {
                                                 let start = (arg.span().lo.0 - fm.start_pos.0) as usize;
                                                 let end = (arg.span().hi.0 - fm.start_pos.0) as usize;
                                                 if start < end && end <= code.len() {
@@ -313,7 +770,7 @@ fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<Scr
 
                                             props.push(Prop {
                                                 name: binding.id.sym.to_string(),
-                                                default_value: call.args.get(0).map(|arg| {
+                                                default_value: call.args.first().map(|arg| {
                                                     let temp_mod = swc_ecma_ast::Module {
                                                         span: DUMMY_SP,
                                                         body: vec![ModuleItem::Stmt(swc_ecma_ast::Stmt::Expr(swc_ecma_ast::ExprStmt {
@@ -325,7 +782,7 @@ fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<Scr
                                                     emit_module_to_string(&temp_mod).trim_end_matches(';').trim().to_string()
                                                 }),
                                                 original_default_value,
-                                                kind: if id.sym.as_ref() == "prop" { PropKind::Prop } else { PropKind::Signal },
+                                                kind: if id.sym.as_ref() == "model" { PropKind::Model } else { PropKind::Prop },
                                             });
                                         }
                                     }
@@ -349,9 +806,49 @@ fn parse_script_block_contents(code: &str) -> Result<(String, Vec<Prop>, Vec<Scr
         body: other_items,
         shebang: None,
     };
-    let cleaned_code = emit_module_to_string(&cleaned_mod);
+    let (cleaned_code, raw_mappings) = emit_module_with_mappings(&cleaned_mod, cm.clone());
+    let mappings = raw_mappings
+        .into_iter()
+        .filter(|(_, pos)| pos.0 >= fm.start_pos.0 && pos.0 <= fm.end_pos.0)
+        .map(|(generated, pos)| CodeMapping {
+            generated,
+            original: (pos.0 - fm.start_pos.0) as usize,
+        })
+        .collect();
+
+    Ok((cleaned_code, mappings, props, imports, head_metadata, directives))
+}
 
-    Ok((cleaned_code, props, imports, head_metadata))
+/// Top-level render directives, with spans relative to the script. Only the
+/// leading string statements (the directive prologue) count; later ones are
+/// kept so they can be reported as misplaced.
+fn collect_render_directives(module: &swc_ecma_ast::Module, fm: &swc_common::SourceFile) -> Vec<RenderDirective> {
+    let mut directives = Vec::new();
+    let mut in_prologue = true;
+    for item in &module.body {
+        let literal = match item {
+            ModuleItem::Stmt(swc_ecma_ast::Stmt::Expr(stmt)) => match &*stmt.expr {
+                swc_ecma_ast::Expr::Lit(swc_ecma_ast::Lit::Str(s)) => Some(s),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some(literal) = literal else {
+            in_prologue = false;
+            continue;
+        };
+        if let Some(mode) = RenderMode::from_directive(&literal.value) {
+            directives.push(RenderDirective {
+                mode,
+                span: SourceRange {
+                    start: (literal.span.lo.0 - fm.start_pos.0) as usize,
+                    end: (literal.span.hi.0 - fm.start_pos.0) as usize,
+                },
+                in_prologue,
+            });
+        }
+    }
+    directives
 }
 
 // Extract head metadata from an object literal expression
@@ -383,28 +880,26 @@ fn extract_head_metadata(expr: &swc_ecma_ast::Expr, _code: &str, _fm: &swc_commo
                         "meta" | "link" | "script" => {
                             if let Expr::Array(arr) = &**value {
                                 let mut items = Vec::new();
-                                for elem in &arr.elems {
-                                    if let Some(elem_expr) = elem {
-                                        if let Expr::Object(obj) = &*elem_expr.expr {
-                                            let mut map = HashMap::new();
-                                            for obj_prop in &obj.props {
-                                                if let PropOrSpread::Prop(p) = obj_prop {
-                                                    if let SwcProp::KeyValue(KeyValueProp { key: k, value: v, .. }) = &**p {
-                                                        let k_name = match k {
-                                                            swc_ecma_ast::PropName::Ident(id) => id.sym.to_string(),
-                                                            swc_ecma_ast::PropName::Str(s) => s.value.to_string(),
-                                                            _ => continue,
-                                                        };
-                                                        if let Expr::Lit(Lit::Str(s)) = &**v {
-                                                            map.insert(k_name, s.value.to_string());
-                                                        } else if let Expr::Lit(Lit::Bool(b)) = &**v {
-                                                            map.insert(k_name, b.value.to_string());
-                                                        }
+                                for elem_expr in arr.elems.iter().flatten() {
+                                    if let Expr::Object(obj) = &*elem_expr.expr {
+                                        let mut map = HashMap::new();
+                                        for obj_prop in &obj.props {
+                                            if let PropOrSpread::Prop(p) = obj_prop {
+                                                if let SwcProp::KeyValue(KeyValueProp { key: k, value: v, .. }) = &**p {
+                                                    let k_name = match k {
+                                                        swc_ecma_ast::PropName::Ident(id) => id.sym.to_string(),
+                                                        swc_ecma_ast::PropName::Str(s) => s.value.to_string(),
+                                                        _ => continue,
+                                                    };
+                                                    if let Expr::Lit(Lit::Str(s)) = &**v {
+                                                        map.insert(k_name, s.value.to_string());
+                                                    } else if let Expr::Lit(Lit::Bool(b)) = &**v {
+                                                        map.insert(k_name, b.value.to_string());
                                                     }
                                                 }
                                             }
-                                            items.push(map);
                                         }
+                                        items.push(map);
                                     }
                                 }
                                 match key_name.as_str() {
@@ -479,6 +974,13 @@ struct MarkupParser<'a> {
     input: &'a str,
     pos: usize,
     base_offset: usize,
+    /// Syntax errors recovered from so far, in the order they were found.
+    errors: Vec<CompileError>,
+    /// Elements currently being parsed, outermost first.
+    open_tags: Vec<String>,
+    /// The next `parse_nodes` call parses a control-flow branch, where
+    /// `{@const}` tags are allowed.
+    branch_body: bool,
 }
 
 impl<'a> MarkupParser<'a> {
@@ -487,6 +989,26 @@ impl<'a> MarkupParser<'a> {
             input,
             pos: 0,
             base_offset,
+            errors: Vec::new(),
+            open_tags: Vec::new(),
+            branch_body: false,
+        }
+    }
+
+    /// Records `err` from a construct that started at `start` and makes sure
+    /// parsing moves on past it.
+    fn recover(&mut self, err: CompileError, start: usize) {
+        self.errors.push(err);
+        if self.pos <= start {
+            self.pos = start;
+            self.consume_char();
+        }
+    }
+
+    /// `expect`, recording the error and carrying on when `s` is missing.
+    fn expect_recovering(&mut self, s: &str) {
+        if let Err(err) = self.expect(s) {
+            self.errors.push(err);
         }
     }
 
@@ -522,6 +1044,25 @@ impl<'a> MarkupParser<'a> {
         self.remaining().starts_with(s)
     }
 
+    /// A `<` that opens a tag, a closing tag or a `<!` construct. Anything
+    /// else, like the `<` in `a < b`, is text.
+    /// `keyword` followed by something other than an identifier character.
+    fn starts_with_keyword(&self, keyword: &str) -> bool {
+        self.starts_with(keyword)
+            && !matches!(self.remaining()[keyword.len()..].chars().next(), Some(c) if c.is_alphanumeric() || c == '_' || c == '$')
+    }
+
+    fn starts_with_tag(&self) -> bool {
+        self.starts_with("<")
+            && matches!(self.remaining()[1..].chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '/' || c == '!')
+    }
+
+    /// `<style>` or `<style ...attributes>`, but not e.g. `<styled-box>`.
+    fn starts_with_style_open_tag(&self) -> bool {
+        self.starts_with("<style")
+            && matches!(self.remaining()[6..].chars().next(), Some(c) if c == '>' || c.is_whitespace())
+    }
+
     fn expect(&mut self, s: &str) -> Result<(), CompileError> {
         if self.starts_with(s) {
             self.pos += s.len();
@@ -538,8 +1079,9 @@ impl<'a> MarkupParser<'a> {
     }
 
     /// Robustly extracts content between <script>...</script> or <style>...</style>
-    /// by skipping strings and comments to find the closing tag.
-    fn parse_script_or_style_block(&mut self, terminator: &str) -> Result<(String, usize), CompileError> {
+    /// by skipping strings and comments to find the closing tag, and moves
+    /// past it. An unclosed block runs to the end of the input.
+    fn parse_script_or_style_block(&mut self, terminator: &str) -> String {
         let start = self.pos;
         let mut in_single = false;
         let mut in_double = false;
@@ -549,11 +1091,16 @@ impl<'a> MarkupParser<'a> {
         let mut escaped = false;
 
         while !self.is_eof() {
-            if !in_single && !in_double && !in_backtick && !in_line_comment && !in_block_comment {
-                if self.starts_with(terminator) {
-                    let code = self.input[start..self.pos].to_string();
-                    return Ok((code, self.pos));
-                }
+            if !in_single
+                && !in_double
+                && !in_backtick
+                && !in_line_comment
+                && !in_block_comment
+                && self.starts_with(terminator)
+            {
+                let code = self.input[start..self.pos].to_string();
+                self.pos += terminator.len();
+                return code;
             }
 
             let c = self.consume_char().unwrap();
@@ -564,10 +1111,8 @@ impl<'a> MarkupParser<'a> {
             }
 
             match c {
-                '\\' => {
-                    if in_single || in_double || in_backtick {
-                        escaped = true;
-                    }
+                '\\' if in_single || in_double || in_backtick => {
+                    escaped = true;
                 }
                 '\'' if !in_double && !in_backtick && !in_line_comment && !in_block_comment => {
                     in_single = !in_single;
@@ -578,121 +1123,188 @@ impl<'a> MarkupParser<'a> {
                 '`' if !in_single && !in_double && !in_line_comment && !in_block_comment => {
                     in_backtick = !in_backtick;
                 }
-                '/' if !in_single && !in_double && !in_backtick => {
-                    if !in_line_comment && !in_block_comment {
-                        if self.starts_with("/") {
-                            self.consume_char();
-                            in_line_comment = true;
-                        } else if self.starts_with("*") {
-                            self.consume_char();
-                            in_block_comment = true;
-                        }
+                '/' if !in_single
+                    && !in_double
+                    && !in_backtick
+                    && !in_line_comment
+                    && !in_block_comment =>
+                {
+                    if self.starts_with("/") {
+                        self.consume_char();
+                        in_line_comment = true;
+                    } else if self.starts_with("*") {
+                        self.consume_char();
+                        in_block_comment = true;
                     }
                 }
                 '\n' if in_line_comment => {
                     in_line_comment = false;
                 }
-                '*' if in_block_comment => {
-                    if self.starts_with("/") {
-                        self.consume_char();
-                        in_block_comment = false;
-                    }
+                '*' if in_block_comment && self.starts_with("/") => {
+                    self.consume_char();
+                    in_block_comment = false;
                 }
                 _ => {}
             }
         }
 
-        Err(CompileError::Template {
+        self.errors.push(CompileError::Template {
             message: format!("unclosed block; expected {terminator}"),
             range: Some(DiagnosticSourceRange {
-                start: self.base_offset + start - 8, // heuristic
+                start: self.base_offset + start.saturating_sub(8), // heuristic
                 end: self.base_offset + self.pos,
             }),
-        })
+        });
+        self.input[start..].to_string()
     }
 
-    fn parse_nodes(
-        &mut self,
-        closing_tag: Option<&str>,
-        terminator: Option<&str>,
-    ) -> Result<Vec<TemplateNode>, CompileError> {
+    /// Nodes up to `closing_tag` (consumed) or `terminator` (not consumed).
+    /// Never fails: broken nodes are recorded in `errors` and skipped.
+    fn parse_nodes(&mut self, closing_tag: Option<&str>, terminator: Option<&str>) -> Vec<TemplateNode> {
         let mut nodes: Vec<TemplateNode> = Vec::new();
+        let is_branch = std::mem::take(&mut self.branch_body);
+        // Names declared by the `{@const}` tags of this branch.
+        let mut consts: Vec<String> = Vec::new();
+        // The template itself, a branch, or the content of a component.
+        let snippets_allowed = is_branch || closing_tag.map_or(terminator.is_none(), crate::codegen::is_component_tag);
 
         while !self.is_eof() {
             if let Some(term) = terminator {
                 if self.starts_with(term) {
-                    return Ok(nodes);
+                    return nodes;
                 }
             }
 
             // Top-level blocks should stop template parsing
-            if closing_tag.is_none() && terminator.is_none() {
-                if self.starts_with("<script>") || self.starts_with("<style>") || self.starts_with("---") {
-                    break;
-                }
+            if closing_tag.is_none()
+                && terminator.is_none()
+                && (self.starts_with("<script>") || self.starts_with_style_open_tag() || self.starts_with("---"))
+            {
+                break;
             }
 
             if self.starts_with("</") {
-                let _saved = self.pos;
+                let tag_start = self.pos;
                 self.pos += 2;
-                let name = self.parse_tag_name()?;
+                let name = match self.parse_tag_name() {
+                    Ok(name) => name,
+                    Err(err) => {
+                        self.errors.push(err);
+                        continue;
+                    }
+                };
                 self.skip_ws();
-                self.expect(">")?;
+                self.expect_recovering(">");
 
-                if let Some(expected) = closing_tag {
-                    if name != expected {
-                        return Err(CompileError::Template {
+                if closing_tag == Some(name.as_str()) {
+                    return nodes;
+                }
+                let range = Some(DiagnosticSourceRange {
+                    start: self.base_offset + tag_start,
+                    end: self.base_offset + self.pos,
+                });
+                match closing_tag {
+                    Some(expected) => {
+                        self.errors.push(CompileError::Template {
                             message: format!("mismatched closing tag </{name}>; expected </{expected}>"),
-                            range: Some(DiagnosticSourceRange {
-                                start: self.base_offset + self.pos - (name.len() + 3), // approx backtrace
-                                end: self.base_offset + self.pos,
-                            }),
+                            range,
                         });
+                        // `</div>` for an enclosing <div> closes this element too.
+                        if self.open_tags.contains(&name) {
+                            self.pos = tag_start;
+                            return nodes;
+                        }
                     }
-                    return Ok(nodes);
-                }
-
-                return Err(CompileError::Template {
-                    message: format!("unexpected closing tag </{name}>"),
-                    range: Some(DiagnosticSourceRange {
-                        start: self.base_offset + self.pos - (name.len() + 3),
-                        end: self.base_offset + self.pos,
+                    None => self.errors.push(CompileError::Template {
+                        message: format!("unexpected closing tag </{name}>"),
+                        range,
                     }),
-                });
-            }
-
-            if self.starts_with("<") {
-                let el = self.parse_element()?;
-                nodes.push(TemplateNode::Element(el));
+                }
                 continue;
             }
 
-            if self.starts_with("@{") {
-                let cf = self.parse_control_flow_block()?;
-                nodes.push(TemplateNode::ControlFlow(cf));
+            let start = self.pos;
+            if self.starts_with("{@const") {
+                match self.parse_const_tag() {
+                    Ok((tag, names)) => {
+                        let range = Some(DiagnosticSourceRange {
+                            start: self.base_offset + start,
+                            end: self.base_offset + self.pos,
+                        });
+                        // Whitespace before the tags is not content.
+                        while matches!(nodes.last(), Some(TemplateNode::Text(t)) if t.trim().is_empty()) {
+                            nodes.pop();
+                        }
+                        if !is_branch {
+                            self.errors.push(CompileError::Template {
+                                message: "{@const} is only allowed at the top of an @{if}, @{for}, @{await} or @{switch} branch".into(),
+                                range,
+                            });
+                        } else if !nodes.iter().all(|n| matches!(n, TemplateNode::Const(_))) {
+                            self.errors.push(CompileError::Template {
+                                message: "{@const} must come before the other content of its branch".into(),
+                                range,
+                            });
+                        } else if let Some(name) = names.iter().find(|name| consts.contains(name)) {
+                            self.errors.push(CompileError::Template {
+                                message: format!("'{name}' is already declared by a {{@const}} in this branch"),
+                                range,
+                            });
+                        }
+                        consts.extend(names);
+                        nodes.push(TemplateNode::Const(tag));
+                    }
+                    Err(err) => self.recover(err, start),
+                }
                 continue;
             }
-
-            if self.starts_with("{@slot") {
-                let slot = self.parse_slot_node()?;
-                nodes.push(TemplateNode::Slot(slot));
+            if self.starts_with_snippet() {
+                match self.parse_snippet() {
+                    Ok(snippet) => {
+                        if !snippets_allowed {
+                            self.errors.push(CompileError::Template {
+                                message: "@{snippet} can only be defined at the top level of the template, in a control-flow branch or inside a component".into(),
+                                range: Some(DiagnosticSourceRange {
+                                    start: self.base_offset + start,
+                                    end: self.base_offset + self.pos,
+                                }),
+                            });
+                        }
+                        nodes.push(TemplateNode::Snippet(snippet));
+                    }
+                    Err(err) => self.recover(err, start),
+                }
                 continue;
             }
-
-            if self.starts_with("{") {
-                let expr = self.parse_braced_js_expr()?;
-                nodes.push(TemplateNode::Expr(expr));
+            let node = if self.starts_with("<!") {
+                self.parse_markup_declaration()
+            } else if self.starts_with_tag() {
+                self.parse_element().map(TemplateNode::Element)
+            } else if self.starts_with("@{") {
+                self.parse_control_flow_block().map(TemplateNode::ControlFlow)
+            } else if self.starts_with("{@slot") {
+                self.parse_slot_node().map(TemplateNode::Slot)
+            } else if self.starts_with("{@html") {
+                self.parse_html_tag().map(TemplateNode::Html)
+            } else if self.starts_with("{@render") {
+                self.parse_render_tag().map(TemplateNode::Render)
+            } else if self.starts_with("{") {
+                self.parse_braced_js_expr().map(TemplateNode::Expr)
+            } else {
+                let text = self.parse_text(terminator);
+                if !text.is_empty() {
+                    nodes.push(TemplateNode::Text(html::decode_entities(&text).into_owned()));
+                }
                 continue;
-            }
-
-            let text = self.parse_text(terminator)?;
-            if !text.is_empty() {
-                nodes.push(TemplateNode::Text(text));
+            };
+            match node {
+                Ok(node) => nodes.push(node),
+                Err(err) => self.recover(err, start),
             }
         }
 
         if let Some(expected) = closing_tag {
-            return Err(CompileError::Template {
+            self.errors.push(CompileError::Template {
                 message: format!("unclosed tag <{expected}>"),
                 range: Some(DiagnosticSourceRange {
                     start: self.base_offset + self.pos, // EOF usually
@@ -701,12 +1313,45 @@ impl<'a> MarkupParser<'a> {
             });
         }
 
-        Ok(nodes)
+        nodes
+    }
+
+    /// `<!-- comment -->`, `<![CDATA[text]]>` (literal text) or another
+    /// `<!...>` construct such as a doctype.
+    fn parse_markup_declaration(&mut self) -> Result<TemplateNode, CompileError> {
+        let start = self.pos;
+        let (open, close) = if self.starts_with("<!--") {
+            ("<!--", "-->")
+        } else if self.starts_with("<![CDATA[") {
+            ("<![CDATA[", "]]>")
+        } else {
+            ("<!", ">")
+        };
+        let content_start = start + open.len();
+        let Some(len) = self.input[content_start..].find(close) else {
+            // Like HTML, an unterminated comment runs to the end of the input.
+            self.pos = self.input.len();
+            return Err(CompileError::Template {
+                message: format!("unterminated '{open}'; expected '{close}'"),
+                range: Some(DiagnosticSourceRange {
+                    start: self.base_offset + start,
+                    end: self.base_offset + content_start,
+                }),
+            });
+        };
+        let content = self.input[content_start..content_start + len].to_string();
+        self.pos = content_start + len + close.len();
+        Ok(match open {
+            "<!--" => TemplateNode::Comment(content),
+            "<![CDATA[" => TemplateNode::Text(content),
+            _ => TemplateNode::Declaration(content),
+        })
     }
-    fn parse_text(&mut self, terminator: Option<&str>) -> Result<String, CompileError> {
+
+    fn parse_text(&mut self, terminator: Option<&str>) -> String {
         let start = self.pos;
         while !self.is_eof() {
-            if self.starts_with("<") || self.starts_with("{") || self.starts_with("@{") {
+            if self.starts_with_tag() || self.starts_with("{") || self.starts_with("@{") {
                 break;
             }
             if let Some(term) = terminator {
@@ -716,7 +1361,7 @@ impl<'a> MarkupParser<'a> {
             }
             self.consume_char();
         }
-        Ok(self.input[start..self.pos].to_string())
+        self.input[start..self.pos].to_string()
     }
 
     fn parse_control_flow_block(&mut self) -> Result<ControlFlowBlock, CompileError> {
@@ -757,7 +1402,7 @@ impl<'a> MarkupParser<'a> {
             }
 
             self.skip_ws();
-            self.expect("}")?;
+            self.expect_recovering("}");
             
             Ok(ControlFlowBlock::If {
                 condition,
@@ -772,6 +1417,7 @@ impl<'a> MarkupParser<'a> {
             
             // For params: we just want the string inside (e.g. "let item of items")
             let start = self.pos;
+            let open_paren = self.pos - 1;
             let mut depth = 1;
             while !self.is_eof() && depth > 0 {
                 let c = self.consume_char().unwrap();
@@ -781,34 +1427,141 @@ impl<'a> MarkupParser<'a> {
             if depth != 0 {
                 return Err(CompileError::Template {
                     message: "unterminated '(' in for block".into(),
-                    range: None,
+                    range: Some(DiagnosticSourceRange {
+                        start: self.base_offset + open_paren,
+                        end: self.base_offset + open_paren + 1,
+                    }),
                 });
             }
-            let params_full = self.input[start..self.pos-1].trim();
-            let mut parts = params_full.split(';');
-            let params = parts.next().unwrap_or("").trim().to_string();
-            let mut key_expr = None;
-            for part in parts {
-                let p = part.trim();
-                if p.starts_with("key=") {
-                    key_expr = Some(p[4..].trim().to_string());
+            let header = match parse_for_header(&self.input[start..self.pos - 1], self.base_offset + start) {
+                Ok(header) => header,
+                Err(errors) => {
+                    self.errors.extend(errors);
+                    // Keep parsing the body; nothing is generated for a file with errors.
+                    ForHeader {
+                        binding: "_".into(),
+                        iterable: JsExpr { code: "[]".into(), span: None },
+                        index: None,
+                        key_expr: None,
+                    }
                 }
-            }
-            
+            };
+
             self.skip_ws();
             let body = self.parse_control_flow_branch()?;
             
             self.skip_ws();
-            self.expect("}")?;
+            self.expect_recovering("}");
 
             Ok(ControlFlowBlock::For {
-                params,
-                key_expr,
+                binding: header.binding,
+                iterable: header.iterable,
+                index: header.index,
+                key_expr: header.key_expr,
                 body,
             })
+        } else if self.starts_with_keyword("await") {
+            self.pos += 5;
+            self.skip_ws();
+            self.expect("(")?;
+            let promise = self.parse_paren_js_expr()?;
+            self.expect(")")?;
+            self.skip_ws();
+
+            let pending = if self.starts_with_keyword("then") || self.starts_with_keyword("catch") {
+                Vec::new()
+            } else {
+                self.parse_control_flow_branch()?
+            };
+
+            self.skip_ws();
+            let mut then_binding = None;
+            let mut then_branch = Vec::new();
+            if self.starts_with_keyword("then") {
+                self.pos += 4;
+                then_binding = self.parse_await_binding()?;
+                then_branch = self.parse_control_flow_branch()?;
+                self.skip_ws();
+            }
+            let mut catch_binding = None;
+            let mut catch_branch = None;
+            if self.starts_with_keyword("catch") {
+                self.pos += 5;
+                catch_binding = self.parse_await_binding()?;
+                catch_branch = Some(self.parse_control_flow_branch()?);
+                self.skip_ws();
+            }
+
+            self.expect_recovering("}");
+
+            Ok(ControlFlowBlock::Await {
+                promise,
+                pending,
+                then_binding,
+                then_branch,
+                catch_binding,
+                catch_branch,
+            })
+        } else if self.starts_with_keyword("switch") || self.starts_with_keyword("match") {
+            // `@{match}` is another spelling of `@{switch}`.
+            let keyword = if self.starts_with("switch") { "switch" } else { "match" };
+            self.pos += keyword.len();
+            self.skip_ws();
+            self.expect("(")?;
+            let discriminant = self.parse_paren_js_expr()?;
+            self.expect(")")?;
+            self.skip_ws();
+            self.expect("{")?;
+
+            let mut cases = Vec::new();
+            let mut default = None;
+            loop {
+                self.skip_ws();
+                if self.is_eof() || self.starts_with("}") {
+                    break;
+                }
+                let clause_start = self.pos;
+                if self.starts_with_keyword("case") {
+                    self.pos += 4;
+                    let value = self.parse_case_value();
+                    let body = self.parse_control_flow_branch()?;
+                    cases.push((value, body));
+                } else if self.starts_with_keyword("default") {
+                    self.pos += 7;
+                    let body = self.parse_control_flow_branch()?;
+                    if default.is_some() {
+                        self.errors.push(CompileError::Template {
+                            message: format!("@{{{keyword}}} can only have one 'default'"),
+                            range: Some(DiagnosticSourceRange {
+                                start: self.base_offset + clause_start,
+                                end: self.base_offset + clause_start + 7,
+                            }),
+                        });
+                    }
+                    default = Some(body);
+                } else {
+                    return Err(CompileError::Template {
+                        message: format!("expected 'case' or 'default' in @{{{keyword}}}"),
+                        range: Some(DiagnosticSourceRange {
+                            start: self.base_offset + self.pos,
+                            end: self.base_offset + self.pos + 1,
+                        }),
+                    });
+                }
+            }
+
+            self.expect_recovering("}");
+            self.skip_ws();
+            self.expect_recovering("}");
+
+            Ok(ControlFlowBlock::Switch {
+                discriminant,
+                cases,
+                default,
+            })
         } else {
             Err(CompileError::Template {
-                message: "expected 'if' or 'for' after '@{'".into(),
+                message: "expected 'if', 'for', 'await', 'switch', 'match' or 'snippet' after '@{'".into(),
                 range: Some(DiagnosticSourceRange {
                     start: self.base_offset + self.pos,
                     end: self.base_offset + self.pos + 1,
@@ -817,6 +1570,270 @@ impl<'a> MarkupParser<'a> {
         }
     }
 
+    /// Whether the input is at `@{snippet`, with optional whitespace after `@{`.
+    fn starts_with_snippet(&mut self) -> bool {
+        if !self.starts_with("@{") {
+            return false;
+        }
+        let start = self.pos;
+        self.pos += 2;
+        self.skip_ws();
+        let found = self.starts_with_keyword("snippet");
+        self.pos = start;
+        found
+    }
+
+    /// `@{snippet name(params) { ... }}`.
+    fn parse_snippet(&mut self) -> Result<SnippetNode, CompileError> {
+        self.expect("@{")?;
+        self.skip_ws();
+        self.pos += "snippet".len();
+        self.skip_ws();
+
+        let name_start = self.pos;
+        while matches!(self.peek_char(), Some(c) if c.is_alphanumeric() || c == '_' || c == '$') {
+            self.consume_char();
+        }
+        let name = self.input[name_start..self.pos].to_string();
+        let name_span = SourceRange {
+            start: self.base_offset + name_start,
+            end: self.base_offset + self.pos,
+        };
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(CompileError::Template {
+                message: "expected a snippet name, e.g. @{snippet row(item) { ... }}".into(),
+                range: Some(DiagnosticSourceRange {
+                    start: name_span.start,
+                    end: name_span.end.max(name_span.start + 1),
+                }),
+            });
+        }
+
+        self.skip_ws();
+        self.expect("(")?;
+        let params = self.parse_paren_js_expr()?;
+        self.expect(")")?;
+        if let Err(message) = parse_snippet_params(&params.code) {
+            let span = params.span.expect("parsed expressions have a span");
+            self.errors.push(CompileError::Template {
+                message,
+                range: Some(DiagnosticSourceRange {
+                    start: span.start,
+                    end: span.end,
+                }),
+            });
+        }
+
+        let body = self.parse_control_flow_branch()?;
+        self.skip_ws();
+        self.expect_recovering("}");
+
+        Ok(SnippetNode {
+            name,
+            name_span: Some(name_span),
+            params: params.code,
+            body,
+        })
+    }
+
+    /// `{@render snippet(args)}`.
+    fn parse_render_tag(&mut self) -> Result<RenderTag, CompileError> {
+        let tag = self.parse_braced_js_expr()?;
+        let span = tag.span.expect("parsed expressions have a span");
+        let invalid = || CompileError::Template {
+            message: "expected '{@render snippet(arguments)}'".into(),
+            range: Some(DiagnosticSourceRange {
+                start: span.start,
+                end: span.end,
+            }),
+        };
+        let rest = &tag.code["@render".len()..];
+        if !rest.starts_with(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let call = rest.trim_start();
+        let offset = span.start + tag.code.len() - call.len();
+
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Custom("render.ts".into()), call.to_string());
+        let base = fm.start_pos.0 as usize;
+        let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+        let expr = parser.parse_expr().map_err(|_| invalid())?;
+        if !parser.take_errors().is_empty() || expr.span().hi.0 as usize - base != call.len() {
+            return Err(invalid());
+        }
+        let swc_ecma_ast::Expr::Call(call_expr) = &*expr else {
+            return Err(invalid());
+        };
+        let Callee::Expr(callee) = &call_expr.callee else {
+            return Err(invalid());
+        };
+
+        let js_expr = |lo: swc_common::BytePos, hi: swc_common::BytePos| {
+            let (start, end) = (lo.0 as usize - base, hi.0 as usize - base);
+            JsExpr {
+                code: call[start..end].to_string(),
+                span: Some(SourceRange {
+                    start: offset + start,
+                    end: offset + end,
+                }),
+            }
+        };
+        let args = call_expr
+            .args
+            .iter()
+            .map(|arg| js_expr(arg.spread.map_or(arg.expr.span().lo, |s| s.lo), arg.expr.span().hi))
+            .collect();
+        Ok(RenderTag {
+            snippet: js_expr(callee.span().lo, callee.span().hi),
+            args,
+        })
+    }
+
+    /// `{@html expr}`; the expression without the keyword.
+    fn parse_html_tag(&mut self) -> Result<JsExpr, CompileError> {
+        let tag = self.parse_braced_js_expr()?;
+        let span = tag.span.expect("parsed expressions have a span");
+        let rest = &tag.code["@html".len()..];
+        let expr = rest.trim_start();
+        if !rest.starts_with(char::is_whitespace) || expr.is_empty() {
+            return Err(CompileError::Template {
+                message: "expected '{@html expression}'".into(),
+                range: Some(DiagnosticSourceRange {
+                    start: span.start,
+                    end: span.end,
+                }),
+            });
+        }
+        let start = span.start + tag.code.len() - expr.len();
+        Ok(JsExpr {
+            code: expr.to_string(),
+            span: Some(SourceRange {
+                start,
+                end: span.end,
+            }),
+        })
+    }
+
+    /// `{@const binding = expr}`, with the names it declares.
+    fn parse_const_tag(&mut self) -> Result<(ConstTag, Vec<String>), CompileError> {
+        let tag = self.parse_braced_js_expr()?;
+        let span = tag.span.expect("parsed expressions have a span");
+        // `tag.code` is `@const ...`; without the `@` it is a declaration.
+        let decl = &tag.code[1..];
+        let offset = span.start + 1;
+        let invalid = || CompileError::Template {
+            message: "expected '{@const name = value}'".into(),
+            range: Some(DiagnosticSourceRange {
+                start: span.start,
+                end: span.end,
+            }),
+        };
+        if !decl.starts_with("const") || !decl[5..].starts_with(char::is_whitespace) {
+            return Err(invalid());
+        }
+
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Custom("const.ts".into()), decl.to_string());
+        let base = fm.start_pos.0 as usize;
+        let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), StringInput::from(&*fm), None);
+        let module = parser.parse_module().map_err(|_| invalid())?;
+        if !parser.take_errors().is_empty() {
+            return Err(invalid());
+        }
+        let declarator = match module.body.as_slice() {
+            [ModuleItem::Stmt(swc_ecma_ast::Stmt::Decl(Decl::Var(var)))] if var.decls.len() == 1 => &var.decls[0],
+            _ => return Err(invalid()),
+        };
+        let Some(init) = &declarator.init else {
+            return Err(invalid());
+        };
+
+        let text = |span: swc_common::Span| (span.lo.0 as usize - base, span.hi.0 as usize - base);
+        let (start, end) = text(declarator.name.span());
+        let binding = decl[start..end].to_string();
+        let (start, end) = text(init.span());
+        let expr = JsExpr {
+            code: decl[start..end].to_string(),
+            span: Some(SourceRange {
+                start: offset + start,
+                end: offset + end,
+            }),
+        };
+        let mut names = Vec::new();
+        pattern_names(&declarator.name, &mut names);
+        Ok((ConstTag { binding, expr }, names))
+    }
+
+    /// The value after `case`, up to the `{` of its branch.
+    fn parse_case_value(&mut self) -> JsExpr {
+        let start = self.pos;
+        let mut depth = 0;
+        let mut quote: Option<char> = None;
+        while let Some(c) = self.peek_char() {
+            if let Some(q) = quote {
+                self.consume_char();
+                if c == '\\' {
+                    self.consume_char();
+                } else if c == q {
+                    quote = None;
+                }
+                continue;
+            }
+            match c {
+                '{' if depth == 0 => break,
+                '\'' | '"' | '`' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+            self.consume_char();
+        }
+
+        let code = self.input[start..self.pos].trim();
+        if code.is_empty() {
+            self.errors.push(CompileError::Template {
+                message: "expected a value after 'case'".into(),
+                range: Some(DiagnosticSourceRange {
+                    start: self.base_offset + start,
+                    end: self.base_offset + self.pos,
+                }),
+            });
+        }
+        let leading = self.input[start..self.pos].len() - self.input[start..self.pos].trim_start().len();
+        JsExpr {
+            code: code.to_string(),
+            span: Some(SourceRange {
+                start: self.base_offset + start + leading,
+                end: self.base_offset + start + leading + code.len(),
+            }),
+        }
+    }
+
+    /// The optional `(value)` after `then` or `catch`.
+    fn parse_await_binding(&mut self) -> Result<Option<String>, CompileError> {
+        self.skip_ws();
+        if !self.starts_with("(") {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let expr = self.parse_paren_js_expr()?;
+        self.expect(")")?;
+        self.skip_ws();
+        let span = expr.span.expect("parsed expressions have a span");
+        if let Err(err) = parse_binding_pattern(&expr.code) {
+            self.errors.push(CompileError::Template {
+                message: err,
+                range: Some(DiagnosticSourceRange {
+                    start: span.start,
+                    end: span.end,
+                }),
+            });
+            return Ok(None);
+        }
+        Ok(Some(expr.code))
+    }
+
     fn parse_slot_node(&mut self) -> Result<SlotNode, CompileError> {
         self.expect("{@slot")?;
         self.skip_ws();
@@ -832,10 +1849,10 @@ impl<'a> MarkupParser<'a> {
             self.pos += 2;
             self.skip_ws();
             // Terminate at '}'
-            fallback = self.parse_nodes(None, Some("}"))?;
+            fallback = self.parse_nodes(None, Some("}"));
         }
 
-        self.expect("}")?;
+        self.expect_recovering("}");
         Ok(SlotNode {
             name,
             fallback,
@@ -846,9 +1863,10 @@ impl<'a> MarkupParser<'a> {
         self.skip_ws();
         if self.starts_with("{") {
             self.pos += 1;
-            let nodes = self.parse_nodes(None, Some("}"))?;
+            self.branch_body = true;
+            let nodes = self.parse_nodes(None, Some("}"));
             self.skip_ws();
-            self.expect("}")?;
+            self.expect_recovering("}");
             Ok(nodes)
         } else {
             // Single node branch
@@ -860,7 +1878,7 @@ impl<'a> MarkupParser<'a> {
                 Ok(vec![TemplateNode::Expr(expr)])
             } else {
                 // Must be text
-                let text = self.parse_text(None)?;
+                let text = self.parse_text(None);
                 Ok(vec![TemplateNode::Text(text)])
             }
         }
@@ -907,7 +1925,23 @@ impl<'a> MarkupParser<'a> {
         }
 
         let (tag_name, tag_span) = self.parse_tag_name_with_span()?;
-        let attributes = self.parse_attributes()?;
+        let mut attributes = self.parse_attributes();
+        if crate::codegen::is_component_tag(&tag_name) {
+            // A component has no element of its own to run an action on.
+            attributes.retain(|attr| {
+                let AttributeNode::Action { action, .. } = attr else {
+                    return true;
+                };
+                self.errors.push(CompileError::Template {
+                    message: format!("'use:{}' is only allowed on elements, not on <{}>", action.code, tag_name),
+                    range: action.span.map(|s| DiagnosticSourceRange {
+                        start: s.start - "use:".len(),
+                        end: s.end,
+                    }),
+                });
+                false
+            });
+        }
 
         if self.starts_with("/>") {
             self.pos += 2;
@@ -920,8 +1954,44 @@ impl<'a> MarkupParser<'a> {
             });
         }
 
+        // Input ended inside the start tag (already reported).
+        if self.is_eof() {
+            return Ok(ElementNode {
+                tag_name,
+                tag_span,
+                attributes,
+                children: Vec::new(),
+                self_closing: false,
+            });
+        }
+
         self.expect(">")?;
-        let children = self.parse_nodes(Some(&tag_name), None)?;
+        if is_void_element(&tag_name) {
+            // `<input></input>` is accepted too.
+            let close = format!("</{tag_name}>");
+            if self.starts_with(&close) {
+                self.pos += close.len();
+            }
+            return Ok(ElementNode {
+                tag_name,
+                tag_span,
+                attributes,
+                children: Vec::new(),
+                self_closing: false,
+            });
+        }
+
+        let children = if is_raw_text_element(&tag_name) || is_escapable_raw_text_element(&tag_name) {
+            self.parse_raw_text(&tag_name)
+        } else {
+            if tag_name == "pre" {
+                self.skip_leading_newline();
+            }
+            self.open_tags.push(tag_name.clone());
+            let children = self.parse_nodes(Some(&tag_name), None);
+            self.open_tags.pop();
+            children
+        };
 
         Ok(ElementNode {
             tag_name,
@@ -932,6 +2002,73 @@ impl<'a> MarkupParser<'a> {
         })
     }
 
+    /// Content of a `<script>`/`<style>` (one text node) or `<textarea>`,
+    /// `<title>` (text and `{expr}`), up to and including `</tag>`.
+    fn parse_raw_text(&mut self, tag_name: &str) -> Vec<TemplateNode> {
+        let start = self.pos;
+        let close = format!("</{tag_name}");
+        let end = match self.remaining().find(&close) {
+            Some(len) => self.pos + len,
+            None => {
+                self.errors.push(CompileError::Template {
+                    message: format!("unclosed tag <{tag_name}>"),
+                    range: Some(DiagnosticSourceRange {
+                        start: self.base_offset + start,
+                        end: self.base_offset + self.input.len(),
+                    }),
+                });
+                self.input.len()
+            }
+        };
+
+        if tag_name == "textarea" {
+            self.skip_leading_newline();
+        }
+
+        let mut nodes = Vec::new();
+        if is_raw_text_element(tag_name) {
+            if end > start {
+                nodes.push(TemplateNode::Text(self.input[start..end].to_string()));
+            }
+        } else {
+            while self.pos < end {
+                let text_start = self.pos;
+                if self.starts_with("{") {
+                    match self.parse_braced_js_expr() {
+                        Ok(expr) => nodes.push(TemplateNode::Expr(expr)),
+                        Err(err) => self.recover(err, text_start),
+                    }
+                    // An expression may not run into the closing tag.
+                    if self.pos > end {
+                        break;
+                    }
+                    continue;
+                }
+                let text_end = self.remaining()[..end - self.pos].find('{').map_or(end, |i| self.pos + i);
+                let text = &self.input[text_start..text_end];
+                nodes.push(TemplateNode::Text(html::decode_entities(text).into_owned()));
+                self.pos = text_end;
+            }
+        }
+
+        if end < self.input.len() && self.pos <= end {
+            self.pos = end + close.len();
+            self.skip_ws();
+            self.expect_recovering(">");
+        }
+        nodes
+    }
+
+    /// Like in HTML, a newline right after `<pre>` or `<textarea>` is not
+    /// part of the content.
+    fn skip_leading_newline(&mut self) {
+        if self.starts_with("\r\n") {
+            self.pos += 2;
+        } else if self.starts_with("\n") {
+            self.pos += 1;
+        }
+    }
+
     fn parse_tag_name_with_span(&mut self) -> Result<(String, Option<SourceRange>), CompileError> {
         self.skip_ws();
         let start_local = self.pos;
@@ -970,61 +2107,222 @@ impl<'a> MarkupParser<'a> {
         Ok(self.input[start..self.pos].to_string())
     }
 
-    fn parse_attributes(&mut self) -> Result<Vec<AttributeNode>, CompileError> {
+    /// Attributes up to the `>` or `/>` of a start tag. A broken attribute is
+    /// recorded in `errors` and skipped.
+    fn parse_attributes(&mut self) -> Vec<AttributeNode> {
+        self.parse_attributes_with_ranges().into_iter().map(|(attr, _)| attr).collect()
+    }
+
+    /// Like `parse_attributes`, with the source range each attribute was
+    /// written at, value included.
+    fn parse_attributes_with_ranges(&mut self) -> Vec<(AttributeNode, DiagnosticSourceRange)> {
         let mut attrs: Vec<AttributeNode> = Vec::new();
+        let mut ranges: Vec<DiagnosticSourceRange> = Vec::new();
+        let mut attr_start = self.pos;
 
         loop {
+            while ranges.len() < attrs.len() {
+                ranges.push(DiagnosticSourceRange {
+                    start: self.base_offset + attr_start,
+                    end: self.base_offset + self.pos,
+                });
+            }
             self.skip_ws();
+            attr_start = self.pos;
             if self.is_eof() {
-                return Err(CompileError::Template {
+                self.errors.push(CompileError::Template {
                     message: "unexpected end of input while parsing tag".into(),
                     range: Some(DiagnosticSourceRange {
                         start: self.base_offset + self.pos,
                         end: self.base_offset + self.pos,
                     }),
                 });
+                break;
             }
             if self.starts_with(">") || self.starts_with("/>") {
                 break;
             }
+            if self.starts_with("{") {
+                match self.parse_attribute_shorthand() {
+                    Ok(attr) => attrs.push(attr),
+                    Err(err) => self.errors.push(err),
+                }
+                continue;
+            }
 
-            let name = self.parse_attr_name()?;
+            let name_start = self.pos;
+            let name = match self.parse_attr_name() {
+                Ok(name) => name,
+                Err(err) => {
+                    self.errors.push(err);
+                    self.skip_attribute();
+                    continue;
+                }
+            };
+            let name_range = Some(DiagnosticSourceRange {
+                start: self.base_offset + name_start,
+                end: self.base_offset + self.pos,
+            });
+            let (name, modifiers) = match split_event_modifiers(&name) {
+                Ok(split) => split,
+                Err(message) => {
+                    self.errors.push(CompileError::Template { message, range: name_range });
+                    self.skip_attribute();
+                    continue;
+                }
+            };
             self.skip_ws();
 
+            if name == "class:" || name == "style:" {
+                self.errors.push(CompileError::Template {
+                    message: format!("expected a name after '{name}'"),
+                    range: name_range,
+                });
+                self.skip_attribute_value();
+                continue;
+            }
+
+            let has_handler = self.starts_with("=") && self.remaining()[1..].trim_start().starts_with('{');
+            if !modifiers.is_empty() && !has_handler {
+                self.errors.push(CompileError::Template {
+                    message: format!("'{name}' has event modifiers, so its value must be a {{handler}}"),
+                    range: name_range,
+                });
+                self.skip_attribute_value();
+                continue;
+            }
+
+            if let Some(action) = name.strip_prefix("use:") {
+                if !is_js_identifier(action) {
+                    self.errors.push(CompileError::Template {
+                        message: format!("expected an action name after 'use:', found '{action}'"),
+                        range: name_range,
+                    });
+                    self.skip_attribute_value();
+                    continue;
+                }
+                let params = if self.starts_with("=") {
+                    self.pos += 1;
+                    self.skip_ws();
+                    if !self.starts_with("{") {
+                        self.errors.push(CompileError::Template {
+                            message: format!("'{name}' takes its parameters as {{params}}"),
+                            range: name_range,
+                        });
+                        self.skip_attribute();
+                        continue;
+                    }
+                    match self.parse_braced_js_expr() {
+                        Ok(expr) => Some(expr),
+                        Err(err) => {
+                            self.errors.push(err);
+                            continue;
+                        }
+                    }
+                } else {
+                    None
+                };
+                let action = JsExpr {
+                    code: action.to_string(),
+                    span: name_range.map(|r| SourceRange {
+                        start: r.start + "use:".len(),
+                        end: r.end,
+                    }),
+                };
+                attrs.push(AttributeNode::Action { action, params });
+                continue;
+            }
+
             if self.starts_with("=") {
                 self.pos += 1;
                 self.skip_ws();
 
-                if self.starts_with("\"") {
-                    let value = self.parse_quoted_string('"')?;
-                    attrs.push(AttributeNode::Static { name, value });
-                    continue;
-                }
-                if self.starts_with("'") {
-                    let value = self.parse_quoted_string('\'')?;
-                    attrs.push(AttributeNode::Static { name, value });
+                if let Some(quote) = self.peek_char().filter(|c| *c == '"' || *c == '\'') {
+                    let value_start = self.pos;
+                    match self.parse_quoted_string(quote) {
+                        // `style:color="red"`: a constant value.
+                        Ok(value) if name.starts_with("style:") => attrs.push(expression_attribute(
+                            name,
+                            JsExpr {
+                                code: js_string(&html::decode_entities(&value)),
+                                span: None,
+                            },
+                            Vec::new(),
+                        )),
+                        Ok(_) if name.starts_with("class:") => self.errors.push(CompileError::Template {
+                            message: format!("'{name}' needs a {{condition}}"),
+                            range: name_range,
+                        }),
+                        Ok(value) => attrs.push(AttributeNode::Static {
+                            name,
+                            value: html::decode_entities(&value).into_owned(),
+                        }),
+                        Err(err) => {
+                            // Unterminated: assume the value ends with the line or tag.
+                            self.errors.push(err);
+                            self.pos = value_start + 1;
+                            while !self.is_eof() && !self.starts_with(">") && !self.starts_with("\n") {
+                                self.consume_char();
+                            }
+                        }
+                    }
                     continue;
                 }
                 if self.starts_with("{") {
-                    let expr = self.parse_braced_js_expr()?;
-                    if name.starts_with("bind:") {
-                        let property = name[5..].to_string();
-                        attrs.push(AttributeNode::Bind { property, expr });
-                    } else if name.starts_with("on") {
-                        attrs.push(AttributeNode::EventHandler { name, expr });
-                    } else {
-                        attrs.push(AttributeNode::Dynamic { name, expr });
+                    let expr = match self.parse_braced_js_expr() {
+                        Ok(expr) => expr,
+                        Err(err) => {
+                            self.errors.push(err);
+                            continue;
+                        }
+                    };
+                    if name == "bind:this" && !expr.code.trim().split('.').all(is_js_identifier) {
+                        self.errors.push(CompileError::Template {
+                            message: format!("'bind:this' needs a variable or signal to store the node in, not '{}'", expr.code.trim()),
+                            range: expr.span.map(|s| DiagnosticSourceRange { start: s.start, end: s.end }),
+                        });
+                        continue;
                     }
+                    attrs.push(expression_attribute(name, expr, modifiers));
                     continue;
                 }
 
-                return Err(CompileError::Template {
+                self.errors.push(CompileError::Template {
                     message: format!("invalid attribute value for '{name}'"),
                     range: Some(DiagnosticSourceRange {
                         start: self.base_offset + self.pos,
                         end: self.base_offset + self.pos + 1,
                     }),
                 });
+                self.skip_attribute();
+                continue;
+            }
+
+            // `class:active` is short for `class:active={active}`.
+            if let Some(class) = name.strip_prefix("class:") {
+                if !is_js_identifier(class) {
+                    self.errors.push(CompileError::Template {
+                        message: format!("'{name}' needs a {{condition}}"),
+                        range: name_range,
+                    });
+                    continue;
+                }
+                let expr = JsExpr {
+                    code: class.to_string(),
+                    span: name_range.map(|r| SourceRange {
+                        start: r.start + "class:".len(),
+                        end: r.end,
+                    }),
+                };
+                attrs.push(expression_attribute(name, expr, Vec::new()));
+                continue;
+            }
+            if name.starts_with("style:") {
+                self.errors.push(CompileError::Template {
+                    message: format!("'{name}' needs a value"),
+                    range: name_range,
+                });
+                continue;
             }
 
             // Boolean attribute
@@ -1034,13 +2332,69 @@ impl<'a> MarkupParser<'a> {
             });
         }
 
-        Ok(attrs)
+        attrs.into_iter().zip(ranges).collect()
+    }
+
+    /// Skips the rest of a malformed attribute: up to the next whitespace,
+    /// `>` or `/>`, stepping over quoted values and `{...}` expressions.
+    fn skip_attribute(&mut self) {
+        loop {
+            match self.peek_char() {
+                None => return,
+                Some('{') => {
+                    let _ = self.parse_braced_js_expr();
+                }
+                Some(quote @ ('"' | '\'')) => {
+                    let _ = self.parse_quoted_string(quote);
+                }
+                Some(_) => {
+                    self.consume_char();
+                }
+            }
+            if self.starts_with(">") || self.starts_with("/>") || self.peek_char().is_none_or(char::is_whitespace) {
+                return;
+            }
+        }
+    }
+
+    /// Skips the `=value` of an attribute whose name was already reported,
+    /// if it has one.
+    fn skip_attribute_value(&mut self) {
+        if self.starts_with("=") {
+            self.pos += 1;
+            self.skip_ws();
+            self.skip_attribute();
+        }
+    }
+
+    /// `{...expr}` or `{name}` in a start tag.
+    fn parse_attribute_shorthand(&mut self) -> Result<AttributeNode, CompileError> {
+        let expr = self.parse_braced_js_expr()?;
+        if let Some(rest) = expr.code.strip_prefix("...") {
+            let leading_ws = rest.len() - rest.trim_start().len();
+            return Ok(AttributeNode::Spread {
+                expr: JsExpr {
+                    code: rest.trim_start().to_string(),
+                    span: expr.span.map(|s| SourceRange {
+                        start: s.start + 3 + leading_ws,
+                        end: s.end,
+                    }),
+                },
+            });
+        }
+        if !is_js_identifier(&expr.code) {
+            return Err(CompileError::Template {
+                message: format!("expected an identifier or '...' in attribute shorthand {{{}}}", expr.code),
+                range: expr.span.map(|s| DiagnosticSourceRange { start: s.start, end: s.end }),
+            });
+        }
+        Ok(expression_attribute(expr.code.clone(), expr, Vec::new()))
     }
 
     fn parse_attr_name(&mut self) -> Result<String, CompileError> {
         let start = self.pos;
         while let Some(c) = self.peek_char() {
-            if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ':' {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ':' || c == '|' {
                 self.consume_char();
             } else {
                 break;
@@ -1197,12 +2551,18 @@ impl<'a> MarkupParser<'a> {
             }
         }
 
-        Err(CompileError::Template {
+        let err = CompileError::Template {
             message: "'{' expression without matching '}' in template".into(),
             range: Some(DiagnosticSourceRange {
                 start: self.base_offset + _brace_start,
                 end: self.base_offset + self.pos,
             }),
-        })
+        };
+        // Resume at the next closing tag or line rather than swallowing the
+        // rest of the file.
+        let rest = &self.input[start..];
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+        self.pos = start + rest[..line_end].find("</").unwrap_or(line_end);
+        Err(err)
     }
 }
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use lumixjs::cache::SourceCache;
+use lumixjs::diagnostic::has_errors;
+
+use crate::{
+    codegen_options, compile_input, diagnostics_payload, error_payload, output_file_name, success_payload,
+    CommentMode, Compiled, CssMode, SourceMapMode, Target,
+};
+
+// `luminc serve`: a long-lived compiler process for dev servers and editors.
+//
+// Requests and responses are JSON-RPC 2.0 objects, one per line, on
+// stdin/stdout. Results are the same payloads `build --format json` prints.
+// Sources can be sent in memory; parsed components stay cached between
+// requests and are reused while their source text is unchanged.
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Params of `compile` and `bundle`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompileParams {
+    /// Path of the `.lumix` file (the entry, for `bundle`).
+    file: PathBuf,
+    /// Source of `file`, instead of reading it from disk.
+    source: Option<String>,
+    /// More in-memory sources, by path (bundle dependencies).
+    #[serde(default)]
+    sources: HashMap<PathBuf, String>,
+    /// `none` (default), `inline` or `file`; `file` only returns the map.
+    source_map: Option<String>,
+    /// `injected` (default) or `external`.
+    css: Option<String>,
+    /// `dom` (default) or `ssr`.
+    target: Option<String>,
+    /// `strip` (default) or `keep`.
+    comments: Option<String>,
+    /// Prerender a `"use static"` page as islands (see `build --islands`).
+    #[serde(default)]
+    islands: bool,
+}
+
+/// Params of `parse`.
+#[derive(Deserialize)]
+struct ParseParams {
+    file: PathBuf,
+    source: Option<String>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl std::fmt::Display) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: message.to_string(),
+        }
+    }
+}
+
+pub fn run() -> anyhow::Result<()> {
+    let stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut server = Server {
+        cache: SourceCache::new(),
+        shutdown: false,
+    };
+
+    for line in stdin.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle_line(&line) {
+            writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+            stdout.flush()?;
+        }
+        if server.shutdown {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    cache: SourceCache,
+    shutdown: bool,
+}
+
+impl Server {
+    /// Response to one line of input; notifications (no `id`) get none.
+    fn handle_line(&mut self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(err) => return Some(error_response(Value::Null, PARSE_ERROR, &err.to_string())),
+        };
+        let request: Request = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(err) => return Some(error_response(Value::Null, INVALID_REQUEST, &err.to_string())),
+        };
+
+        let result = self.dispatch(&request.method, request.params);
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(id, err.code, &err.message),
+        })
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "compile" => self.compile(parse_params(params)?, false),
+            "bundle" => self.compile(parse_params(params)?, true),
+            "parse" => self.parse(parse_params(params)?),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method '{}'", method),
+            }),
+        }
+    }
+
+    fn compile(&mut self, params: CompileParams, bundle: bool) -> Result<Value, RpcError> {
+        let source_map = parse_enum(params.source_map.as_deref(), SourceMapMode::None)?;
+        let options = codegen_options(
+            parse_enum(params.css.as_deref(), CssMode::Injected)?,
+            parse_enum(params.target.as_deref(), Target::Dom)?,
+            parse_enum(params.comments.as_deref(), CommentMode::Strip)?,
+        );
+        let file = params.file;
+
+        // In-memory sources only shadow the disk for this request.
+        let mut overlaid: Vec<PathBuf> = params.sources.keys().cloned().collect();
+        for (path, source) in params.sources {
+            self.cache.set_source(&path, source);
+        }
+        if let Some(source) = params.source {
+            self.cache.set_source(&file, source);
+            overlaid.push(file.clone());
+        }
+
+        let result = compile_input(&file, bundle, params.islands, &options, &mut self.cache);
+        let source = self.cache.read(&file).ok();
+        for path in &overlaid {
+            self.cache.remove_source(path);
+        }
+
+        let payload = match result {
+            Err(err) => error_payload(&file, source.as_deref(), &err),
+            Ok(Compiled { diagnostics, .. }) if has_errors(&diagnostics) => {
+                diagnostics_payload(&file, source.as_deref().unwrap_or_default(), &diagnostics)
+            }
+            Ok(Compiled {
+                js,
+                mut map,
+                css,
+                static_build,
+                bundled,
+                diagnostics,
+            }) => {
+                if let Ok(name) = output_file_name(&file, bundled) {
+                    map.set_file(name);
+                }
+                let js = if source_map == SourceMapMode::Inline {
+                    format!("{}\n//# sourceMappingURL={}\n", js.trim_end(), map.to_data_url())
+                } else {
+                    js
+                };
+                let map = (source_map != SourceMapMode::None).then_some(&map);
+                success_payload(
+                    &file,
+                    source.as_deref().unwrap_or_default(),
+                    &js,
+                    &css,
+                    map,
+                    static_build.as_ref(),
+                    &diagnostics,
+                )
+            }
+        };
+        Ok(payload)
+    }
+
+    /// Parse and validate only: the diagnostics payload, without codegen.
+    fn parse(&mut self, params: ParseParams) -> Result<Value, RpcError> {
+        let source = match params.source {
+            Some(source) => source,
+            None => match self.cache.read(&params.file) {
+                Ok(source) => source,
+                Err(err) => return Ok(error_payload(&params.file, None, &err.into())),
+            },
+        };
+        let diagnostics = lumixjs::validate_source(&params.file, &source, &mut self.cache);
+        Ok(diagnostics_payload(&params.file, &source, &diagnostics))
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn parse_enum<T: ValueEnum>(value: Option<&str>, default: T) -> Result<T, RpcError> {
+    match value {
+        None => Ok(default),
+        Some(value) => T::from_str(value, true).map_err(RpcError::invalid_params),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
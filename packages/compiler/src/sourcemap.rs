@@ -0,0 +1,262 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::diagnostic::compute_line_starts;
+
+// Codegen builds its output by concatenating strings, so it cannot know final
+// line/column positions while it runs. Instead it embeds position markers
+// (two private-use characters around a source byte offset) next to the code
+// they describe; `SourceMap::add_generated` strips them and records the
+// mappings. The characters are picked per source so that none of them occur
+// in it: user text (icon font glyphs, say) passes through untouched.
+thread_local! {
+    static MARKERS: Cell<Option<(char, char)>> = const { Cell::new(None) };
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns a marker that maps the code emitted right after it to `offset`
+/// (a byte offset in the original `.lumix` source). Outside of
+/// `SourceMap::add_generated` no map is being built and this is empty.
+pub fn mark(offset: usize) -> String {
+    match MARKERS.with(Cell::get) {
+        Some((start, end)) => format!("{start}{offset}{end}"),
+        None => String::new(),
+    }
+}
+
+/// The first two private-use characters that do not occur in `source`.
+fn marker_chars(source: &str) -> Option<(char, char)> {
+    let used: HashSet<char> = source.chars().filter(|c| ('\u{E000}'..='\u{F8FF}').contains(c)).collect();
+    let mut free = ('\u{E000}'..='\u{F8FF}').filter(|c| !used.contains(c));
+    Some((free.next()?, free.next()?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub generated_line: u32,
+    pub generated_col: u32,
+    pub source: u32,
+    pub original_line: u32,
+    pub original_col: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Source {
+    name: String,
+    content: String,
+    line_starts: Vec<usize>,
+}
+
+/// Source Map v3 builder for generated JS.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    file: String,
+    sources: Vec<Source>,
+    mappings: Vec<Mapping>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceMapJson<'a> {
+    version: u32,
+    file: &'a str,
+    sources: Vec<&'a str>,
+    sources_content: Vec<&'a str>,
+    names: Vec<&'a str>,
+    mappings: String,
+}
+
+impl SourceMap {
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            sources: Vec::new(),
+            mappings: Vec::new(),
+        }
+    }
+
+    pub fn set_file(&mut self, file: impl Into<String>) {
+        self.file = file.into();
+    }
+
+    /// Registers an original source file and returns its index.
+    pub fn add_source(&mut self, name: impl Into<String>, content: &str) -> u32 {
+        self.sources.push(Source {
+            name: name.into(),
+            content: content.to_string(),
+            line_starts: compute_line_starts(content),
+        });
+        (self.sources.len() - 1) as u32
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Runs `generate` with position markers enabled for `source` (see
+    /// `mark`), then strips them from its output, recording a mapping for each
+    /// one. `line_offset` is the line the chunk will start at in the final
+    /// output. Returns the clean code.
+    pub fn add_generated(&mut self, source: u32, line_offset: u32, generate: impl FnOnce() -> String) -> String {
+        let markers = self.sources.get(source as usize).and_then(|s| marker_chars(&s.content));
+        let previous = MARKERS.with(|m| m.replace(markers));
+        let code = generate();
+        MARKERS.with(|m| m.set(previous));
+        match markers {
+            Some((start, end)) => self.add_marked(&code, source, line_offset, start, end),
+            None => code,
+        }
+    }
+
+    fn add_marked(&mut self, code: &str, source: u32, line_offset: u32, mark_start: char, mark_end: char) -> String {
+        let mut out = String::with_capacity(code.len());
+        let mut line = line_offset;
+        let mut col: u32 = 0;
+        let mut chars = code.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                c if c == mark_start => {
+                    let digits: String = chars.by_ref().take_while(|c| *c != mark_end).collect();
+                    if let Ok(offset) = digits.parse::<usize>() {
+                        if let Some((original_line, original_col)) = self.original_position(source, offset) {
+                            let mapping = Mapping {
+                                generated_line: line,
+                                generated_col: col,
+                                source,
+                                original_line,
+                                original_col,
+                            };
+                            // Several markers may land on the same spot; keep the first.
+                            let duplicate = self.mappings.last().is_some_and(|m| {
+                                m.generated_line == line && m.generated_col == col
+                            });
+                            if !duplicate {
+                                self.mappings.push(mapping);
+                            }
+                        }
+                    }
+                }
+                '\n' => {
+                    out.push(c);
+                    line += 1;
+                    col = 0;
+                }
+                _ => {
+                    out.push(c);
+                    col += c.len_utf16() as u32;
+                }
+            }
+        }
+
+        out
+    }
+
+    fn original_position(&self, source: u32, offset: usize) -> Option<(u32, u32)> {
+        let src = self.sources.get(source as usize)?;
+        if offset > src.content.len() {
+            return None;
+        }
+        let line = match src.line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i.saturating_sub(1),
+        };
+        let line_start = src.line_starts[line];
+        let col = src.content.get(line_start..offset)?.encode_utf16().count();
+        Some((line as u32, col as u32))
+    }
+
+    /// Encodes the `mappings` field (VLQ segments, `;` between lines).
+    fn encode_mappings(&self) -> String {
+        let mut mappings = self.mappings.clone();
+        mappings.sort_by_key(|m| (m.generated_line, m.generated_col));
+
+        let mut out = String::new();
+        let mut line = 0u32;
+        let mut prev_col = 0i64;
+        let mut prev_source = 0i64;
+        let mut prev_orig_line = 0i64;
+        let mut prev_orig_col = 0i64;
+        let mut first_in_line = true;
+
+        for m in &mappings {
+            while line < m.generated_line {
+                out.push(';');
+                line += 1;
+                prev_col = 0;
+                first_in_line = true;
+            }
+            if !first_in_line {
+                out.push(',');
+            }
+            first_in_line = false;
+
+            encode_vlq(&mut out, m.generated_col as i64 - prev_col);
+            encode_vlq(&mut out, m.source as i64 - prev_source);
+            encode_vlq(&mut out, m.original_line as i64 - prev_orig_line);
+            encode_vlq(&mut out, m.original_col as i64 - prev_orig_col);
+
+            prev_col = m.generated_col as i64;
+            prev_source = m.source as i64;
+            prev_orig_line = m.original_line as i64;
+            prev_orig_col = m.original_col as i64;
+        }
+
+        out
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let json = SourceMapJson {
+            version: 3,
+            file: &self.file,
+            sources: self.sources.iter().map(|s| s.name.as_str()).collect(),
+            sources_content: self.sources.iter().map(|s| s.content.as_str()).collect(),
+            names: Vec::new(),
+            mappings: self.encode_mappings(),
+        };
+        serde_json::to_value(json).unwrap_or(serde_json::Value::Null)
+    }
+
+    pub fn to_json_string(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// `data:` URL suitable for an inline `//# sourceMappingURL=` comment.
+    pub fn to_data_url(&self) -> String {
+        format!(
+            "data:application/json;charset=utf-8;base64,{}",
+            encode_base64(self.to_json_string().as_bytes())
+        )
+    }
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut vlq = if value < 0 { ((-value) << 1) | 1 } else { value << 1 };
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64_CHARS[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        out.push(BASE64_CHARS[(n >> 18) as usize & 63] as char);
+        out.push(BASE64_CHARS[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 { BASE64_CHARS[(n >> 6) as usize & 63] as char } else { '=' });
+        out.push(if chunk.len() > 2 { BASE64_CHARS[n as usize & 63] as char } else { '=' });
+    }
+    out
+}
@@ -0,0 +1,590 @@
+use crate::ast::*;
+use crate::codegen::{
+    arrow_params, const_declarations, dynamic_prop_value, event_handler_value, for_item_params, generate_imports,
+    generate_script_prologue, is_component_tag, js_string, mark, render_call, script_code_with_marks,
+    serialize_head_metadata, slot_snippet, snippet_params, split_slot_children, spread_prop, with_scope_attribute,
+};
+use crate::css;
+use crate::transpiler::transpile_ts_snippet;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+// Server codegen: every component compiles to a function returning an HTML
+// string instead of DOM nodes. The markup mirrors what `__LUMIX__.h` builds
+// on the client, including the `<!--cf-start-->`/`<!--cf-end-->` comments
+// around reactive blocks, so the two stay interchangeable.
+
+/// A piece of the rendered output: literal HTML known at compile time, or a
+/// JS expression evaluating to an HTML string.
+enum Chunk {
+    Html(String),
+    Js(String),
+}
+
+/// Island roots, keyed by node address, mapped to their island id.
+pub(crate) type IslandIds = HashMap<*const TemplateNode, String>;
+
+#[derive(Clone, Copy)]
+struct Ctx<'a> {
+    is_bundle: bool,
+    /// Emit `<!--cf-start-->`/`<!--cf-end-->` around reactive blocks. Off in
+    /// the static parts of a `"use static"` page, which never hydrate.
+    markers: bool,
+    islands: Option<&'a IslandIds>,
+}
+
+impl Ctx<'_> {
+    fn island_id(&self, node: &TemplateNode) -> Option<&String> {
+        self.islands?.get(&(node as *const TemplateNode))
+    }
+
+    fn open_block(&self, chunks: &mut Vec<Chunk>) {
+        if self.markers {
+            chunks.push(Chunk::Html("<!--cf-start-->".into()));
+        }
+    }
+
+    fn close_block(&self, chunks: &mut Vec<Chunk>) {
+        if self.markers {
+            chunks.push(Chunk::Html("<!--cf-end-->".into()));
+        }
+    }
+}
+
+const DEFAULT_CTX: Ctx<'static> = Ctx {
+    is_bundle: false,
+    markers: true,
+    islands: None,
+};
+
+/// ESM module exporting the component's server render function.
+pub(crate) fn generate_ssr_component_js_esm_marked(component: &ComponentFile, component_name: &str) -> String {
+    let scoped = scoped_component(component);
+    generate_ssr_module(component, &scoped, component_name, &DEFAULT_CTX)
+}
+
+/// Server module for a `"use static"` page: plain HTML, with each island
+/// wrapped in `<lumix-island>` and keeping its hydration markers.
+/// `scoped` must be the component the `islands` keys point into, with the
+/// scope attribute already stamped.
+pub(crate) fn generate_static_ssr_module_marked(
+    component: &ComponentFile,
+    scoped: &ComponentFile,
+    component_name: &str,
+    islands: &IslandIds,
+) -> String {
+    let ctx = Ctx {
+        markers: false,
+        islands: Some(islands),
+        ..DEFAULT_CTX
+    };
+    generate_ssr_module(component, scoped, component_name, &ctx)
+}
+
+fn generate_ssr_module(component: &ComponentFile, scoped: &ComponentFile, component_name: &str, ctx: &Ctx) -> String {
+    let mut out = String::new();
+
+    out.push_str("// Generated by LumixJS (SSR)\n");
+    out.push_str("import * as __LUMIX__ from 'lumix-js';\n");
+    out.push_str(&generate_imports(component));
+
+    out.push_str(&generate_ssr_component_body(scoped, component_name, ctx));
+    out.push_str(&format!("export default {};\n", component_name));
+    out.push_str(&format!("export {{ {} }};\n", component_name));
+
+    if let Some(head) = &component.head {
+        out.push_str("\n// Head metadata\n");
+        out.push_str("export const head = ");
+        out.push_str(&serialize_head_metadata(head));
+        out.push_str(";\n");
+    }
+
+    if let Some(css) = crate::codegen::generate_css(component) {
+        out.push_str(&format!("\nexport const css = {};\n", js_string(&css)));
+    }
+
+    out.push_str("\nexport function render(props) {\n");
+    out.push_str(&format!("  return __LUMIX__.ssrComponent({}, props || {{}});\n", component_name));
+    out.push_str("}\n");
+
+    out
+}
+
+/// Bundle factory for the server target, registered in `__lumixComponents`
+/// like the client one.
+pub(crate) fn generate_ssr_component_factory_js_marked(name: &str, component: &ComponentFile) -> String {
+    let mut out = String::new();
+
+    out.push_str(&format!("__lumixComponents[\"{}\"] = (function() {{\n", name));
+    let fn_name = "Component";
+    let ctx = Ctx {
+        is_bundle: true,
+        ..DEFAULT_CTX
+    };
+    out.push_str(&generate_ssr_component_body(&scoped_component(component), fn_name, &ctx));
+    out.push_str(&format!(
+        "\n  return {{ default: {}, render: (props) => __LUMIX__.ssrComponent({}, props || {{}}) }};\n",
+        fn_name, fn_name
+    ));
+    out.push_str("})();\n");
+
+    out
+}
+
+/// `component` with the scope attribute stamped on its elements when its
+/// `<style>` is scoped. Only the attribute is needed on the server; the CSS
+/// itself is exported separately since there is no document to inject it into.
+pub(crate) fn scoped_component(component: &ComponentFile) -> Cow<'_, ComponentFile> {
+    match &component.style {
+        Some(style) if !style.global => {
+            Cow::Owned(with_scope_attribute(component, &css::scope_attribute(&style.code)))
+        }
+        _ => Cow::Borrowed(component),
+    }
+}
+
+fn generate_ssr_component_body(component: &ComponentFile, fn_name: &str, ctx: &Ctx) -> String {
+    let mut out = String::new();
+
+    out.push_str(&format!("function {}(props = {{}}) {{\n", fn_name));
+
+    let script_body = component.script.as_ref().map(script_code_with_marks).unwrap_or_default();
+    out.push_str(&generate_script_prologue(component, &script_body, fn_name));
+
+    out.push_str("  return ");
+    out.push_str(&render_nodes(&component.template, ctx, false));
+    out.push_str(";\n");
+    out.push_str("}\n");
+
+    out
+}
+
+/// JS expression concatenating the HTML of `nodes`.
+fn render_nodes(nodes: &[TemplateNode], ctx: &Ctx, strip_slot_attr: bool) -> String {
+    let mut chunks = Vec::new();
+    for node in nodes {
+        push_node(&mut chunks, node, ctx, strip_slot_attr);
+    }
+    let html = join_chunks(chunks);
+    // `{@const}` tags and snippets are declared in a scope of their own.
+    let mut declarations = const_declarations(nodes);
+    for node in nodes {
+        if let TemplateNode::Snippet(snippet) = node {
+            declarations.push_str(&format!(
+                "const {}{} = {}; ",
+                mark(snippet.name_span),
+                snippet.name,
+                render_snippet(snippet, ctx)
+            ));
+        }
+    }
+    if declarations.is_empty() {
+        html
+    } else {
+        format!("(() => {{ {}return {}; }})()", declarations, html)
+    }
+}
+
+/// `(params) => html`: a snippet rendering to a string.
+fn render_snippet(snippet: &SnippetNode, ctx: &Ctx) -> String {
+    format!("({}) => {}", snippet_params(&snippet.params), render_nodes(&snippet.body, ctx, false))
+}
+
+fn push_node(chunks: &mut Vec<Chunk>, node: &TemplateNode, ctx: &Ctx, strip_slot_attr: bool) {
+    if let Some(id) = ctx.island_id(node) {
+        // Islands keep their hydration markers; the wrapper does not affect layout.
+        chunks.push(Chunk::Html(format!(
+            "<lumix-island data-lumix-island=\"{}\" style=\"display:contents\">",
+            escape_attr(id)
+        )));
+        push_node(chunks, node, &Ctx { islands: None, markers: true, ..*ctx }, strip_slot_attr);
+        chunks.push(Chunk::Html("</lumix-island>".into()));
+        return;
+    }
+
+    match node {
+        TemplateNode::Text(t) => {
+            chunks.push(Chunk::Html(escape_html(t)));
+        }
+        // Declared by `render_nodes`.
+        TemplateNode::Const(_) | TemplateNode::Snippet(_) => {}
+        TemplateNode::Render(tag) => {
+            ctx.open_block(chunks);
+            chunks.push(Chunk::Js(format!(
+                "{}__LUMIX__.ssrRaw({})",
+                mark(tag.snippet.span),
+                render_call(tag)
+            )));
+            ctx.close_block(chunks);
+        }
+        TemplateNode::Comment(text) => {
+            chunks.push(Chunk::Html(format!("<!--{}-->", text)));
+        }
+        TemplateNode::Declaration(text) => {
+            chunks.push(Chunk::Html(format!("<!{}>", text)));
+        }
+        TemplateNode::Expr(expr) => {
+            ctx.open_block(chunks);
+            chunks.push(Chunk::Js(format!(
+                "{}__LUMIX__.ssrText(() => ({}))",
+                mark(expr.span),
+                transpile_ts_snippet(&expr.code).trim()
+            )));
+            ctx.close_block(chunks);
+        }
+        TemplateNode::Html(expr) => {
+            ctx.open_block(chunks);
+            chunks.push(Chunk::Js(format!(
+                "{}__LUMIX__.ssrHtml(() => ({}))",
+                mark(expr.span),
+                transpile_ts_snippet(&expr.code).trim()
+            )));
+            ctx.close_block(chunks);
+        }
+        TemplateNode::Slot(slot) => {
+            let fallback = render_nodes(&slot.fallback, ctx, false);
+            let js = match &slot.name {
+                None => format!("(props.children ? __LUMIX__.ssrRaw(props.children()) : {})", fallback),
+                Some(name) => format!(
+                    "(props.slots?.{} ? __LUMIX__.ssrRaw(props.slots.{}()) : {})",
+                    name, name, fallback
+                ),
+            };
+            chunks.push(Chunk::Js(js));
+        }
+        TemplateNode::ControlFlow(cf) => {
+            ctx.open_block(chunks);
+            chunks.push(Chunk::Js(render_control_flow(cf, ctx)));
+            ctx.close_block(chunks);
+        }
+        TemplateNode::Element(el) if is_component_tag(&el.tag_name) => {
+            chunks.push(Chunk::Js(render_component(el, ctx)));
+        }
+        TemplateNode::Element(el) => {
+            chunks.push(Chunk::Html(format!("<{}", el.tag_name)));
+            if el.attributes.iter().any(|a| {
+                matches!(a, AttributeNode::Spread { .. } | AttributeNode::Class { .. } | AttributeNode::Style { .. })
+            }) {
+                // Which attribute wins, or what `class:`/`style:` merge
+                // into, is only known at runtime.
+                chunks.push(Chunk::Js(format!(
+                    "__LUMIX__.ssrSpreadAttrs({{ {} }})",
+                    spread_attribute_props(&el.attributes, strip_slot_attr).join(", ")
+                )));
+            } else {
+                push_attributes(chunks, &el.attributes, strip_slot_attr);
+            }
+            chunks.push(Chunk::Html(">".into()));
+            if is_void_element(&el.tag_name.to_ascii_lowercase()) {
+                return;
+            }
+            // The browser drops a newline right after `<pre>`/`<textarea>`.
+            if matches!(el.tag_name.as_str(), "pre" | "textarea")
+                && matches!(el.children.first(), Some(TemplateNode::Text(t)) if t.starts_with('\n'))
+            {
+                chunks.push(Chunk::Html("\n".into()));
+            }
+            // No block markers where the HTML parser would read them as text.
+            let ctx = &if is_escapable_raw_text_element(&el.tag_name) {
+                Ctx { markers: false, ..*ctx }
+            } else {
+                *ctx
+            };
+            for child in &el.children {
+                match child {
+                    // The HTML parser does not decode `<script>`/`<style>` content.
+                    TemplateNode::Text(t) if is_raw_text_element(&el.tag_name) => chunks.push(Chunk::Html(t.clone())),
+                    _ => push_node(chunks, child, ctx, false),
+                }
+            }
+            chunks.push(Chunk::Html(format!("</{}>", el.tag_name)));
+        }
+    }
+}
+
+fn push_attributes(chunks: &mut Vec<Chunk>, attributes: &[AttributeNode], strip_slot_attr: bool) {
+    for attr in attributes {
+        match attr {
+            AttributeNode::Static { name, value } => {
+                if strip_slot_attr && name == "slot" {
+                    continue;
+                }
+                chunks.push(Chunk::Html(format!(" {}=\"{}\"", name, escape_attr(value))));
+            }
+            AttributeNode::Dynamic { name, expr } => {
+                chunks.push(Chunk::Js(format!(
+                    "{}__LUMIX__.ssrAttr({}, {})",
+                    mark(expr.span),
+                    js_string(name),
+                    dynamic_prop_value(&expr.code)
+                )));
+            }
+            // Listeners, actions and node references are attached by the client.
+            AttributeNode::EventHandler { .. } | AttributeNode::Action { .. } => {}
+            AttributeNode::Bind { property, .. } if property == "this" => {}
+            AttributeNode::Bind { property, expr } => {
+                chunks.push(Chunk::Js(format!(
+                    "{}__LUMIX__.ssrAttr({}, {})",
+                    mark(expr.span),
+                    js_string(property),
+                    transpile_ts_snippet(&expr.code).trim()
+                )));
+            }
+            AttributeNode::Spread { .. } | AttributeNode::Class { .. } | AttributeNode::Style { .. } => {
+                unreachable!("rendered by ssrSpreadAttrs")
+            }
+        }
+    }
+}
+
+/// Entries of the props object `ssrSpreadAttrs` renders, in source order.
+fn spread_attribute_props(attributes: &[AttributeNode], strip_slot_attr: bool) -> Vec<String> {
+    let mut spreads = 0;
+    let mut props = Vec::new();
+    for attr in attributes {
+        match attr {
+            AttributeNode::Static { name, value } => {
+                if !(strip_slot_attr && name == "slot") {
+                    props.push(format!("{}: {}", js_string(name), js_string(value)));
+                }
+            }
+            AttributeNode::Dynamic { name, expr } => {
+                props.push(format!("{}{}: {}", mark(expr.span), js_string(name), dynamic_prop_value(&expr.code)));
+            }
+            AttributeNode::EventHandler { .. } | AttributeNode::Action { .. } => {}
+            AttributeNode::Bind { property, .. } if property == "this" => {}
+            AttributeNode::Bind { property, expr } => props.push(format!(
+                "{}{}: {}",
+                mark(expr.span),
+                js_string(property),
+                transpile_ts_snippet(&expr.code).trim()
+            )),
+            AttributeNode::Spread { expr } => props.push(spread_prop(&mut spreads, expr)),
+            AttributeNode::Class { name, expr } => props.push(format!(
+                "{}{}: {}",
+                mark(expr.span),
+                js_string(&format!("class:{}", name)),
+                dynamic_prop_value(&expr.code)
+            )),
+            AttributeNode::Style { property, expr } => props.push(format!(
+                "{}{}: {}",
+                mark(expr.span),
+                js_string(&format!("style:{}", property)),
+                dynamic_prop_value(&expr.code)
+            )),
+        }
+    }
+    props
+}
+
+fn render_control_flow(cf: &ControlFlowBlock, ctx: &Ctx) -> String {
+    match cf {
+        ControlFlowBlock::If {
+            condition,
+            then_branch,
+            else_ifs,
+            else_branch,
+        } => {
+            let mut s = String::from("(() => { ");
+            s.push_str(&format!(
+                "if ({}{}) return {}; ",
+                mark(condition.span),
+                transpile_ts_snippet(&condition.code).trim(),
+                render_nodes(then_branch, ctx, false)
+            ));
+            for (cond, branch) in else_ifs {
+                s.push_str(&format!(
+                    "if ({}{}) return {}; ",
+                    mark(cond.span),
+                    transpile_ts_snippet(&cond.code).trim(),
+                    render_nodes(branch, ctx, false)
+                ));
+            }
+            match else_branch {
+                Some(branch) => s.push_str(&format!("return {}; ", render_nodes(branch, ctx, false))),
+                None => s.push_str("return \"\"; "),
+            }
+            s.push_str("})()");
+            s
+        }
+        ControlFlowBlock::For {
+            binding,
+            iterable,
+            index,
+            body,
+            ..
+        } => {
+            // Keys only matter for reconciliation, so they are ignored here.
+            format!(
+                "__LUMIX__.ssrEach(() => ({}{}), {} => {})",
+                mark(iterable.span),
+                transpile_ts_snippet(&iterable.code).trim(),
+                arrow_params(&for_item_params(binding, index.as_ref())),
+                render_nodes(body, ctx, false)
+            )
+        }
+        // Rendering is synchronous: a promise renders the pending branch,
+        // which the client replaces once it settles.
+        ControlFlowBlock::Await {
+            promise,
+            pending,
+            then_binding,
+            then_branch,
+            ..
+        } => format!(
+            "__LUMIX__.ssrAwait(() => ({}{}), () => {}, ({}) => {})",
+            mark(promise.span),
+            transpile_ts_snippet(&promise.code).trim(),
+            render_nodes(pending, ctx, false),
+            then_binding.as_deref().unwrap_or(""),
+            render_nodes(then_branch, ctx, false)
+        ),
+        ControlFlowBlock::Switch {
+            discriminant,
+            cases,
+            default,
+        } => {
+            let mut s = format!(
+                "(() => {{ switch ({}{}) {{ ",
+                mark(discriminant.span),
+                transpile_ts_snippet(&discriminant.code).trim()
+            );
+            for (value, branch) in cases {
+                s.push_str(&format!(
+                    "case {}{}: return {}; ",
+                    mark(value.span),
+                    transpile_ts_snippet(&value.code).trim(),
+                    render_nodes(branch, ctx, false)
+                ));
+            }
+            if let Some(branch) = default {
+                s.push_str(&format!("default: return {}; ", render_nodes(branch, ctx, false)));
+            }
+            s.push_str("} return \"\"; })()");
+            s
+        }
+    }
+}
+
+fn render_component(el: &ElementNode, ctx: &Ctx) -> String {
+    let tag = if ctx.is_bundle {
+        format!("__lumixComponents['{}'].default", el.tag_name)
+    } else {
+        el.tag_name.clone()
+    };
+
+    let mut spreads = 0;
+    let mut props = Vec::new();
+    for attr in &el.attributes {
+        match attr {
+            AttributeNode::Static { name, value } => {
+                props.push(format!("{}: {}", js_string(name), js_string(value)));
+            }
+            AttributeNode::Dynamic { name, expr } => {
+                props.push(format!(
+                    "{}{}: {}",
+                    mark(expr.span),
+                    js_string(name),
+                    dynamic_prop_value(&expr.code)
+                ));
+            }
+            AttributeNode::EventHandler { name, expr, modifiers } => {
+                props.push(format!(
+                    "{}{}: {}",
+                    mark(expr.span),
+                    js_string(name),
+                    event_handler_value(&expr.code, modifiers)
+                ));
+            }
+            // Nothing is mounted on the server to reference.
+            AttributeNode::Bind { property, .. } if property == "this" => {}
+            AttributeNode::Bind { property, expr } => {
+                props.push(format!(
+                    "{}{}: {}",
+                    mark(expr.span),
+                    js_string(&format!("bind:{}", property)),
+                    transpile_ts_snippet(&expr.code).trim()
+                ));
+            }
+            AttributeNode::Spread { expr } => props.push(spread_prop(&mut spreads, expr)),
+            // Only allowed on elements; reported by the parser.
+            AttributeNode::Action { .. } => {}
+            AttributeNode::Class { name, expr } => props.push(format!(
+                "{}{}: {}",
+                mark(expr.span),
+                js_string(&format!("class:{}", name)),
+                dynamic_prop_value(&expr.code)
+            )),
+            AttributeNode::Style { property, expr } => props.push(format!(
+                "{}{}: {}",
+                mark(expr.span),
+                js_string(&format!("style:{}", property)),
+                dynamic_prop_value(&expr.code)
+            )),
+        }
+    }
+
+    let (default_children, slots) = split_slot_children(el);
+
+    if !default_children.is_empty() {
+        props.push(format!("\"children\": () => {}", render_nodes(&default_children, ctx, true)));
+    }
+    if !slots.is_empty() {
+        let entries: Vec<String> = slots
+            .iter()
+            .map(|(name, nodes)| match slot_snippet(nodes) {
+                Some(snippet) => format!("{}: {}", js_string(name), render_snippet(snippet, ctx)),
+                None => format!("{}: () => {}", js_string(name), render_nodes(nodes, ctx, true)),
+            })
+            .collect();
+        props.push(format!("\"slots\": {{ {} }}", entries.join(", ")));
+    }
+
+    format!(
+        "{}__LUMIX__.ssrComponent({}, {{ {} }})",
+        mark(el.tag_span),
+        tag,
+        props.join(", ")
+    )
+}
+
+fn join_chunks(chunks: Vec<Chunk>) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut html = String::new();
+    for chunk in chunks {
+        match chunk {
+            Chunk::Html(h) => html.push_str(&h),
+            Chunk::Js(js) => {
+                if !html.is_empty() {
+                    parts.push(js_string(&std::mem::take(&mut html)));
+                }
+                parts.push(js);
+            }
+        }
+    }
+    if !html.is_empty() {
+        parts.push(js_string(&html));
+    }
+    if parts.is_empty() {
+        return "\"\"".into();
+    }
+    if parts.len() == 1 {
+        return parts.remove(0);
+    }
+    format!("({})", parts.join(" + "))
+}
+
+/// Text content escaping, matching the browser's HTML serializer.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\u{a0}', "&nbsp;")
+}
+
+/// Attribute value escaping, matching the browser's HTML serializer.
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\u{a0}', "&nbsp;")
+}
@@ -1,4 +1,5 @@
-use swc_common::{FileName, SourceMap, sync::Lrc, GLOBALS, Globals, Mark, DUMMY_SP};
+use swc_common::{FileName, SourceMap, sync::Lrc, BytePos, GLOBALS, Globals, Mark, DUMMY_SP};
+use swc_common::source_map::LineCol;
 use swc_ecma_ast::{Program, Module};
 use swc_ecma_parser::{Parser, StringInput, Syntax, TsConfig};
 use swc_ecma_transforms_base::resolver;
@@ -51,6 +52,56 @@ pub fn emit_module_to_string(module: &Module) -> String {
     String::from_utf8_lossy(&buf).to_string().trim().to_string()
 }
 
+/// Like `emit_module_to_string`, but keeps spans so the emitter can record
+/// where each original position ends up. `cm` must be the map `module` was
+/// parsed with. Each returned pair is `(byte offset in output, original pos)`,
+/// keeping only the first position on each output line.
+pub fn emit_module_with_mappings(module: &Module, cm: Lrc<SourceMap>) -> (String, Vec<(usize, BytePos)>) {
+    let mut buf = vec![];
+    let mut entries: Vec<(BytePos, LineCol)> = Vec::new();
+    {
+        let mut emitter = Emitter {
+            cfg: Config::default().with_minify(false),
+            cm: cm.clone(),
+            comments: None,
+            wr: Box::new(swc_ecma_codegen::text_writer::JsWriter::new(cm, "\n", &mut buf, Some(&mut entries))),
+        };
+        let _ = emitter.emit_module(module);
+    }
+    let raw = String::from_utf8_lossy(&buf).to_string();
+    let leading = raw.len() - raw.trim_start().len();
+    let out = raw.trim().to_string();
+
+    let line_starts = crate::diagnostic::compute_line_starts(&raw);
+    let mut mappings: Vec<(usize, BytePos)> = Vec::new();
+    let mut last_line = None;
+    for (pos, lc) in entries {
+        if last_line == Some(lc.line) {
+            continue;
+        }
+        let Some(&line_start) = line_starts.get(lc.line as usize) else {
+            continue;
+        };
+        // Columns are UTF-16 units; convert back to a byte offset.
+        let mut units = 0u32;
+        let mut offset = line_start;
+        for c in raw[line_start..].chars() {
+            if units >= lc.col || c == '\n' {
+                break;
+            }
+            units += c.len_utf16() as u32;
+            offset += c.len_utf8();
+        }
+        if offset < leading || offset - leading > out.len() {
+            continue;
+        }
+        last_line = Some(lc.line);
+        mappings.push((offset - leading, pos));
+    }
+
+    (out, mappings)
+}
+
 pub fn transpile_ts_snippet(ts_code: &str) -> String {
     let cm: Lrc<SourceMap> = Default::default();
     let fm = cm.new_source_file(FileName::Custom("input.ts".into()), ts_code.into());
@@ -1,4 +1,6 @@
 use crate::ast::*;
+use crate::codegen::is_component_tag;
+use crate::parser::annotate_snippet_params;
 
 pub fn generate_ts(component: &ComponentFile, _component_name: &str) -> String {
     let mut out = String::new();
@@ -47,13 +49,20 @@ pub fn generate_ts(component: &ComponentFile, _component_name: &str) -> String {
 fn collect_template_expressions(nodes: &[TemplateNode], out: &mut String) {
     for node in nodes {
         match node {
-            TemplateNode::Expr(expr) => {
+            TemplateNode::Expr(expr) | TemplateNode::Html(expr) => {
                 if let Some(span) = expr.span {
                     out.push_str(&format!("  ((/* @L:{} */{}));\n", span.start, expr.code));
                 } else {
                     out.push_str(&format!("  (({}));\n", expr.code));
                 }
             }
+            TemplateNode::Const(tag) => match tag.expr.span {
+                Some(span) => out.push_str(&format!(
+                    "  const {} = (/* @L:{} */{});\n",
+                    tag.binding, span.start, tag.expr.code
+                )),
+                None => out.push_str(&format!("  const {} = ({});\n", tag.binding, tag.expr.code)),
+            },
             TemplateNode::Element(el) => {
                 for attr in &el.attributes {
                     match attr {
@@ -71,17 +80,68 @@ fn collect_template_expressions(nodes: &[TemplateNode], out: &mut String) {
                                 out.push_str(&format!("  (({}));\n", expr.code));
                             }
                         }
-                        AttributeNode::Bind { expr, .. } => {
+                        // The node has to fit the signal or variable, e.g.
+                        // an `HTMLInputElement` for `<input bind:this={el}>`.
+                        // A component hands over its root node or nodes.
+                        AttributeNode::Bind { property, expr } if property == "this" => {
+                            let node = if is_component_tag(&el.tag_name) {
+                                "Node | Node[]".to_string()
+                            } else {
+                                element_type(&el.tag_name)
+                            };
+                            let target = match expr.span {
+                                Some(span) => format!("/* @L:{} */{}", span.start, expr.code.trim()),
+                                None => expr.code.trim().to_string(),
+                            };
+                            out.push_str(&format!(
+                                "  ((__v: {}): __LUMIN__.RefTarget<typeof {}> => __v);\n",
+                                node, target
+                            ));
+                        }
+                        AttributeNode::Bind { expr, .. }
+                        | AttributeNode::Spread { expr }
+                        | AttributeNode::Class { expr, .. }
+                        | AttributeNode::Style { expr, .. } => {
                             if let Some(span) = expr.span {
                                 out.push_str(&format!("  ((/* @L:{} */{}));\n", span.start, expr.code));
                             } else {
                                 out.push_str(&format!("  (({}));\n", expr.code));
                             }
                         }
+                        // `action(node, params)`, so the parameters are
+                        // checked against the action's signature.
+                        AttributeNode::Action { action, params } => {
+                            let node = format!("null as unknown as {}", element_type(&el.tag_name));
+                            let action = match action.span {
+                                Some(span) => format!("/* @L:{} */{}", span.start, action.code),
+                                None => action.code.clone(),
+                            };
+                            match params {
+                                Some(expr) => match expr.span {
+                                    Some(span) => out.push_str(&format!(
+                                        "  ({})({}, (/* @L:{} */{}));\n",
+                                        action, node, span.start, expr.code
+                                    )),
+                                    None => out.push_str(&format!("  ({})({}, ({}));\n", action, node, expr.code)),
+                                },
+                                None => out.push_str(&format!("  ({})({});\n", action, node)),
+                            }
+                        }
                         _ => {}
                     }
                 }
-                collect_template_expressions(&el.children, out);
+                if is_component_tag(&el.tag_name) {
+                    // Snippets passed as slots are not in scope next to the component.
+                    for child in &el.children {
+                        if matches!(child, TemplateNode::Snippet(_)) {
+                            collect_branch_expressions(std::slice::from_ref(child), out);
+                        } else {
+                            collect_template_expressions(std::slice::from_ref(child), out);
+                        }
+                    }
+                } else {
+                    collect_template_expressions(&el.children, out);
+                }
             }
             TemplateNode::ControlFlow(cf) => match cf {
                 ControlFlowBlock::If { condition, then_branch, else_ifs, else_branch } => {
@@ -90,34 +150,143 @@ fn collect_template_expressions(nodes: &[TemplateNode], out: &mut String) {
                     } else {
                         out.push_str(&format!("  (({}));\n", condition.code));
                     }
-                    collect_template_expressions(then_branch, out);
+                    collect_branch_expressions(then_branch, out);
                     for (cond, branch) in else_ifs {
                         if let Some(span) = cond.span {
                             out.push_str(&format!("  ((/* @L:{} */{}));\n", span.start, cond.code));
                         } else {
                             out.push_str(&format!("  (({}));\n", cond.code));
                         }
-                        collect_template_expressions(branch, out);
+                        collect_branch_expressions(branch, out);
                     }
                     if let Some(branch) = else_branch {
-                        collect_template_expressions(branch, out);
+                        collect_branch_expressions(branch, out);
                     }
                 }
-                ControlFlowBlock::For { params, body, .. } => {
-                    // Try to extract variable from "item of items"
-                    if let Some((item, list)) = params.split_once(" of ") {
-                        out.push_str(&format!("  for (const {} of {}) {{\n", item.trim(), list.trim()));
-                        collect_template_expressions(body, out);
+                ControlFlowBlock::For {
+                    binding,
+                    iterable,
+                    index,
+                    body,
+                    ..
+                } => {
+                    match iterable.span {
+                        Some(span) => out.push_str(&format!(
+                            "  for (const {} of /* @L:{} */{}) {{\n",
+                            binding, span.start, iterable.code
+                        )),
+                        None => out.push_str(&format!("  for (const {} of {}) {{\n", binding, iterable.code)),
+                    }
+                    if let Some(index) = index {
+                        out.push_str(&format!("  const {}: number = 0;\n", index));
+                    }
+                    collect_template_expressions(body, out);
+                    out.push_str("  }\n");
+                }
+                ControlFlowBlock::Await {
+                    promise,
+                    pending,
+                    then_binding,
+                    then_branch,
+                    catch_binding,
+                    catch_branch,
+                } => {
+                    // `then (value)` is typed from what the promise resolves to.
+                    match promise.span {
+                        Some(span) => out.push_str(&format!(
+                            "  {{\n  const __promise = (/* @L:{} */{});\n",
+                            span.start, promise.code
+                        )),
+                        None => out.push_str(&format!("  {{\n  const __promise = ({});\n", promise.code)),
+                    }
+                    collect_template_expressions(pending, out);
+                    out.push_str("  {\n");
+                    if let Some(binding) = then_binding {
+                        out.push_str(&format!("  const {} = null as unknown as Awaited<typeof __promise>;\n", binding));
+                    }
+                    collect_template_expressions(then_branch, out);
+                    out.push_str("  }\n");
+                    if let Some(branch) = catch_branch {
+                        out.push_str("  {\n");
+                        if let Some(binding) = catch_binding {
+                            out.push_str(&format!("  const {} = null as any;\n", binding));
+                        }
+                        collect_template_expressions(branch, out);
                         out.push_str("  }\n");
-                    } else {
-                        collect_template_expressions(body, out);
                     }
+                    out.push_str("  }\n");
+                }
+                ControlFlowBlock::Switch {
+                    discriminant,
+                    cases,
+                    default,
+                } => {
+                    let marker = discriminant.span.map(|span| format!("/* @L:{} */", span.start)).unwrap_or_default();
+                    out.push_str(&format!("  {{\n  const __switch = ({}{});\n", marker, discriminant.code));
+                    out.push_str("  switch (__switch) {\n");
+                    for (value, branch) in cases {
+                        match value.span {
+                            Some(span) => out.push_str(&format!("  case (/* @L:{} */{}): {{\n", span.start, value.code)),
+                            None => out.push_str(&format!("  case ({}): {{\n", value.code)),
+                        }
+                        collect_template_expressions(branch, out);
+                        out.push_str("  break;\n  }\n");
+                    }
+                    match default {
+                        Some(branch) => {
+                            out.push_str("  default: {\n");
+                            collect_template_expressions(branch, out);
+                            out.push_str("  }\n");
+                        }
+                        // Without `default`, cases over a union of literals
+                        // must cover all of it; wider types are not checked.
+                        None if !cases.is_empty() => out.push_str(&format!(
+                            "  default: {{\n  const __unhandled: __LUMIN__.Exhaustive<typeof __switch> = ({}__switch);\n  }}\n",
+                            marker
+                        )),
+                        None => {}
+                    }
+                    out.push_str("  }\n  }\n");
                 }
             },
             TemplateNode::Slot(slot) => {
                 collect_template_expressions(&slot.fallback, out);
             }
-            TemplateNode::Text(_) => {}
+            // A function declaration, so it can be rendered above its definition.
+            TemplateNode::Snippet(snippet) => {
+                let marker = snippet.name_span.map(|span| format!("/* @L:{} */", span.start)).unwrap_or_default();
+                out.push_str(&format!(
+                    "  function {}{}({}) {{\n",
+                    marker,
+                    snippet.name,
+                    annotate_snippet_params(&snippet.params)
+                ));
+                collect_template_expressions(&snippet.body, out);
+                out.push_str("  }\n");
+            }
+            // A call, so the arguments are checked against the parameters.
+            TemplateNode::Render(tag) => {
+                let marked = |expr: &JsExpr| match expr.span {
+                    Some(span) => format!("/* @L:{} */{}", span.start, expr.code),
+                    None => expr.code.clone(),
+                };
+                let args: Vec<String> = tag.args.iter().map(marked).collect();
+                out.push_str(&format!("  (({})({}));\n", marked(&tag.snippet), args.join(", ")));
+            }
+            TemplateNode::Text(_) | TemplateNode::Comment(_) | TemplateNode::Declaration(_) => {}
         }
     }
 }
+
+/// A branch in its own block, which scopes its `{@const}` tags.
+fn collect_branch_expressions(nodes: &[TemplateNode], out: &mut String) {
+    out.push_str("  {\n");
+    collect_template_expressions(nodes, out);
+    out.push_str("  }\n");
+}
+
+/// The DOM type of a `<tag>` element: `HTMLInputElement` for `input`, and
+/// `HTMLElement` for tags TypeScript does not know.
+fn element_type(tag: &str) -> String {
+    format!("(HTMLElementTagNameMap & Record<string, HTMLElement>)['{}']", tag)
+}
@@ -20,12 +20,12 @@ function inc() {
 
     fs::write(&input_path, source).expect("write input");
 
-    let js = luminjs::compile_file(&input_path).expect("compile");
+    let js = lumixjs::compile_file(&input_path).expect("compile");
     println!("Generated JS:\n{}", js);
 
     assert!(js.contains("import * as __LUMIX__ from 'lumix-js';"));
-    assert!(js.contains("function Component"));
-    assert!(js.contains("export default Component;"));
+    assert!(js.contains("function App"));
+    assert!(js.contains("export default App;"));
     assert!(js.contains("h('div'"));
     assert!(js.contains("h('h1'"));
     assert!(js.contains("'onClick': inc"));
@@ -44,11 +44,11 @@ import X from "./Card.lumix"
 "#;
     fs::write(&input_path, source).expect("write input");
 
-    let (_js, diags) = luminjs::compile_file_with_diagnostics(&input_path)
+    let (_js, diags) = lumixjs::compile_file_with_diagnostics(&input_path)
         .expect("compile_with_diagnostics");
 
     assert!(
-        diags.iter().any(|d| d.message.contains("only .lumix component imports")),
+        diags.iter().any(|d| d.message.contains("not allowed inside <script>")),
         "expected a diagnostic about .lumix imports inside <script>"
     );
 }
@@ -69,10 +69,10 @@ const count = signal(0)
 
     fs::write(&input_path, source).expect("write input");
 
-    let js = luminjs::compile_file(&input_path).expect("compile");
+    let js = lumixjs::compile_file(&input_path).expect("compile");
 
     // Should contain the inline arrow in the h() call props
-    assert!(js.contains("'onClick': () => count(count() + 1)"));
+    assert!(js.contains("'onClick': ()=>count(count() + 1)"));
 }
 
 #[test]
@@ -83,7 +83,7 @@ fn errors_on_unclosed_tags() {
     let source = r#"<div><span>Hi</div>"#;
     fs::write(&input_path, source).expect("write input");
 
-    let err = luminjs::compile_file(&input_path).expect_err("should error");
+    let err = lumixjs::compile_file(&input_path).expect_err("should error");
     let msg = format!("{:#}", err);
     assert!(msg.to_lowercase().contains("mismatched closing tag") || msg.to_lowercase().contains("unclosed tag"));
 }
@@ -97,7 +97,7 @@ fn reports_js_diagnostics_with_line_and_column() {
     let source = r#"<div>{(() => }</div>"#;
     fs::write(&input_path, source).expect("write input");
 
-    let (_js, diags) = luminjs::compile_file_with_diagnostics(&input_path)
+    let (_js, diags) = lumixjs::compile_file_with_diagnostics(&input_path)
         .expect("compile_with_diagnostics");
 
     assert!(!diags.is_empty());
@@ -131,7 +131,7 @@ import Counter from "./Counter.lumix"
     fs::write(&counter_path, counter).expect("write counter");
     fs::write(&app_path, app).expect("write app");
 
-    let res = luminjs::bundler::bundle_entry(&app_path).expect("bundle");
+    let res = lumixjs::bundler::bundle_entry(&app_path).expect("bundle");
     println!("Bundled JS:\n{}", res.js);
     assert!(res.diagnostics.is_empty(), "Diagnostics: {:?}", res.diagnostics);
     
@@ -155,8 +155,1556 @@ fn supports_style_tags() {
 
     fs::write(&input_path, source).expect("write input");
 
-    let js = luminjs::compile_file(&input_path).expect("compile");
+    let js = lumixjs::compile_file(&input_path).expect("compile");
 
-    assert!(js.contains(".red { color: red; }"));
-    assert!(js.contains("const styleId = 'lumix-style-app'"));
+    let attr = lumixjs::css::scope_attribute("\n.red { color: red; }\n");
+    assert!(js.contains(&format!(".red[{}] {{ color: red; }}", attr)));
+    assert!(js.contains(&format!("'{}': ''", attr)));
+    assert!(js.contains("const styleId = 'lumix-style-"));
+}
+
+#[test]
+fn scoped_styles_support_global_escape_hatch_and_opt_out() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let scoped_path = tmp_dir.path().join("Scoped.lumix");
+    let global_path = tmp_dir.path().join("Global.lumix");
+
+    let scoped = r#"<style>
+:global(body) .title, .card > p::after { color: red; }
+@media (min-width: 600px) { .title { color: blue; } }
+</style>
+<div class="card"><p class="title">Hi</p></div>
+"#;
+    let global = r#"<style global>
+.title { color: red; }
+</style>
+<p class="title">Hi</p>
+"#;
+
+    fs::write(&scoped_path, scoped).expect("write scoped");
+    fs::write(&global_path, global).expect("write global");
+
+    let js = lumixjs::compile_file(&scoped_path).expect("compile scoped");
+    let attr = lumixjs::css::scope_attribute(
+        "\n:global(body) .title, .card > p::after { color: red; }\n@media (min-width: 600px) { .title { color: blue; } }\n",
+    );
+    assert!(js.contains(&format!("body .title[{a}], .card > p[{a}]::after", a = attr)));
+    assert!(js.contains(&format!("@media (min-width: 600px) {{ .title[{}] {{ color: blue; }} }}", attr)));
+    assert_eq!(js.matches(&format!("'{}': ''", attr)).count(), 2);
+
+    let js = lumixjs::compile_file(&global_path).expect("compile global");
+    assert!(js.contains(".title { color: red; }"));
+    assert!(!js.contains("data-lx-"));
+    assert!(js.contains("const styleId = 'lumix-style-global'"));
+}
+
+#[test]
+fn scoped_styles_skip_comments_in_selectors() {
+    let css = lumixjs::css::scope_css(".a /* {not; a block} */, .b { color: red; }", "data-lx-x");
+    assert_eq!(css, ".a[data-lx-x], .b[data-lx-x] { color: red; }");
+}
+
+#[test]
+fn scoped_styles_leave_document_selectors_unscoped() {
+    let css = lumixjs::css::scope_css(
+        ":root { --accent: red; } html, BODY.dark { margin: 0; } body > .app, :root.dark p { color: red; }",
+        "data-lx-x",
+    );
+    assert_eq!(
+        css,
+        ":root { --accent: red; } html, BODY.dark { margin: 0; } body > .app[data-lx-x], :root.dark p[data-lx-x] { color: red; }"
+    );
+}
+
+#[test]
+fn emits_source_maps_pointing_into_lumix_file() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let input_path = tmp_dir.path().join("App.lumix");
+
+    let source = r#"<script>
+const count = signal(0)
+</script>
+
+<div>
+  <h1>{count()}</h1>
+</div>
+"#;
+
+    fs::write(&input_path, source).expect("write input");
+
+    let (js, map, diags) = lumixjs::compile_file_with_source_map(&input_path).expect("compile");
+    assert!(diags.is_empty(), "Diagnostics: {:?}", diags);
+    assert!(!js.contains('\u{E000}'), "position markers must be stripped");
+
+    let json = map.to_json();
+    assert_eq!(json["version"], 3);
+    assert_eq!(json["sources"][0], "App.lumix");
+    assert_eq!(json["sourcesContent"][0], source);
+
+    // `const count` comes from line 2 of the .lumix file (0-based line 1).
+    let js_line = js.lines().position(|l| l.contains("const count")).expect("script line") as u32;
+    assert!(map
+        .mappings()
+        .iter()
+        .any(|m| m.generated_line == js_line && m.original_line == 1 && m.original_col == 0));
+
+    // The `{count()}` expression maps back to its position on line 6.
+    let expr_line = js.lines().position(|l| l.contains("() => (count())")).expect("expr line") as u32;
+    assert!(map
+        .mappings()
+        .iter()
+        .any(|m| m.generated_line == expr_line && m.original_line == 5 && m.original_col == 7));
+}
+
+#[test]
+fn keeps_private_use_characters_in_output() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let input_path = tmp_dir.path().join("App.lumix");
+
+    // Icon fonts map glyphs to the private use area.
+    let source = "<script>\nconst label = signal('\u{E001}')\n</script>\n\n<i class=\"icon\">\u{E000}</i>\n<b>\u{E000}12\u{E001} {label()}</b>\n";
+    fs::write(&input_path, source).expect("write input");
+
+    let js = lumixjs::compile_file(&input_path).expect("compile");
+    assert!(js.contains("`\u{E000}`"), "JS:\n{}", js);
+    assert!(js.contains("\u{E000}12\u{E001} "), "JS:\n{}", js);
+    assert!(js.contains("signal('\u{E001}')"), "JS:\n{}", js);
+
+    let (mapped, map, _) = lumixjs::compile_file_with_source_map(&input_path).expect("compile");
+    assert_eq!(mapped, js);
+    let expr_line = js.lines().position(|l| l.contains("() => (label())")).expect("expr line") as u32;
+    assert!(map.mappings().iter().any(|m| m.generated_line == expr_line && m.original_line == 5));
+}
+
+#[test]
+fn extracts_bundle_css_in_dependency_order() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let app_path = tmp_dir.path().join("App.lumix");
+    let card_path = tmp_dir.path().join("Card.lumix");
+    let badge_path = tmp_dir.path().join("Badge.lumix");
+
+    let badge = r#"<style global>.badge { color: red; }</style>
+<span class="badge">!</span>
+"#;
+    // Same global CSS as Badge: must only appear once in bundle.css.
+    let card = r#"---
+import Badge from "./Badge.lumix"
+---
+<style global>.badge { color: red; }</style>
+<div><Badge /></div>
+"#;
+    let app = r#"---
+import Card from "./Card.lumix"
+---
+<style>.app { margin: 0; }</style>
+<main class="app"><Card /></main>
+"#;
+
+    fs::write(&badge_path, badge).expect("write badge");
+    fs::write(&card_path, card).expect("write card");
+    fs::write(&app_path, app).expect("write app");
+
+    let options = lumixjs::codegen::CodegenOptions {
+        css: lumixjs::codegen::CssOutput::External,
+        ..Default::default()
+    };
+    let res = lumixjs::bundler::bundle_entry_with_options(&app_path, &options).expect("bundle");
+    assert!(res.diagnostics.is_empty(), "Diagnostics: {:?}", res.diagnostics);
+
+    assert_eq!(res.css.matches(".badge { color: red; }").count(), 1);
+    let badge_at = res.css.find(".badge").expect("badge css");
+    let app_at = res.css.find(".app[data-lx-").expect("scoped app css");
+    assert!(badge_at < app_at, "dependencies must come first:\n{}", res.css);
+
+    assert!(!res.js.contains("createElement('style')"));
+    assert!(res.js.contains("data-lx-"), "elements still carry the scope attribute");
+
+    let res = lumixjs::compile_file_with_options(&app_path, &options).expect("compile");
+    assert!(res.css.expect("component css").contains(".app[data-lx-"));
+    assert!(!res.js.contains("createElement('style')"));
+}
+
+#[test]
+fn ssr_target_renders_components_to_html_strings() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let input_path = tmp_dir.path().join("App.lumix");
+
+    let source = r#"---
+import Card from "./Card.lumix"
+---
+<script>
+const items = ["a", "b"]
+const show = true
+</script>
+
+<ul class="list" onClick={() => {}}>
+  @{for (let item of items) {
+    <li>{item}</li>
+  }}
+  @{if (show) {
+    <Card title="Hi"><p>Tom & Jerry</p></Card>
+  }}
+  <br/>
+</ul>
+"#;
+
+    fs::write(&input_path, source).expect("write input");
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let js = lumixjs::compile_file_with_options(&input_path, &options).expect("compile").js;
+    println!("Generated JS:\n{}", js);
+
+    assert!(js.contains("export function render(props)"));
+    assert!(!js.contains("__LUMIX__.h("));
+    assert!(!js.contains("onClick"));
+    assert!(js.contains("\"<ul class=\\\"list\\\">"));
+    assert!(js.contains("__LUMIX__.ssrEach(() => (items), item =>"));
+    assert!(js.contains("__LUMIX__.ssrText(() => (item))"));
+    assert!(js.contains("if (show) return"));
+    assert!(js.contains("__LUMIX__.ssrComponent(Card, { \"title\": \"Hi\", \"children\": () => \"<p>Tom &amp; Jerry</p>\" })"));
+    assert!(js.contains("<br>"));
+    assert!(!js.contains("</br>"));
+}
+
+#[test]
+fn emits_hydration_path_that_adopts_server_markup() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let input_path = tmp_dir.path().join("App.lumix");
+
+    let source = r#"<script>
+const count = signal(0)
+const items = signal([1, 2])
+</script>
+
+<div class="counter">
+  <p>Static</p>
+  <button onClick={() => count(count() + 1)}>{count()}</button>
+  @{if (count() > 1) {
+    <span>big</span>
+  }}
+  @{for (let item of items(); key=item) {
+    <i>{item}</i>
+  }}
+</div>
+"#;
+
+    fs::write(&input_path, source).expect("write input");
+
+    let js = lumixjs::compile_file(&input_path).expect("compile");
+    println!("Generated JS:\n{}", js);
+
+    assert!(js.contains("function App(props = {}, __c)"));
+    assert!(js.contains("if (__c instanceof __LUMIX__.HydrationCursor)"));
+    assert!(js.contains("return __LUMIX__.HYDRATED;"));
+    // Static subtrees are only stepped over.
+    assert!(js.contains("__c.element('p');"));
+    assert!(js.contains("__LUMIX__.__hydrateElement(__c, 'button', { 'onClick': ()=>count(count() + 1) }"));
+    assert!(js.contains("__LUMIX__.__hydrateBlock(__c, () => (count()));"));
+    assert!(js.contains("__LUMIX__.__hydrateIf(__c, () => (count() > 1), ["));
+    assert!(js.contains("__LUMIX__.__hydrateFor(__c, () => (items()), item => ["));
+    assert!(js.contains("], (__c, item) => {"));
+    assert!(js.contains("}, item => (item));"));
+}
+
+#[test]
+fn use_static_pages_split_into_islands() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let page_path = tmp_dir.path().join("Page.lumix");
+    let card_path = tmp_dir.path().join("Card.lumix");
+    let counter_path = tmp_dir.path().join("Counter.lumix");
+
+    fs::write(
+        &card_path,
+        "<script>\nconst { heading } = props;\n</script>\n<section><h2>{heading}</h2></section>\n",
+    )
+    .expect("write card");
+    fs::write(
+        &counter_path,
+        "<script>\nconst n = signal(props.start);\n</script>\n<button onClick={() => n(n() + 1)}>{n()}</button>\n",
+    )
+    .expect("write counter");
+
+    let source = r#"---
+import Card from './Card.lumix'
+import Counter from './Counter.lumix'
+---
+<script>
+"use static";
+const count = signal(0);
+const title = "Hello";
+</script>
+
+<div class="page">
+  <h1>{title}</h1>
+  <Card heading="About" />
+  <p>Clicks: <button onClick={() => count(count() + 1)}>{count()}</button></p>
+  <Counter start={3} />
+</div>
+"#;
+    fs::write(&page_path, source).expect("write page");
+
+    let build = lumixjs::compile_static_file(&page_path).expect("compile");
+    println!("Generated JS:\n{}", build.js);
+
+    assert!(build.diagnostics.is_empty());
+    // Static parts are plain HTML: no hydration markers, no island wrapper.
+    assert!(build.js.contains("<h1>\" + __LUMIX__.ssrText(() => (title)) + \"</h1>"));
+    assert!(build.js.contains("__LUMIX__.ssrComponent(Card, { \"heading\": \"About\" }) + \" <p>"));
+    assert!(build.js.contains("<p>Clicks: <lumix-island data-lumix-island=\\\"Page-0\\\""));
+    assert!(build.js.contains("<lumix-island data-lumix-island=\\\"Page-1\\\""));
+    assert_eq!(build.islands.len(), 2);
+
+    // The button hydrates with the page script's bindings.
+    let button = &build.islands[0];
+    assert_eq!(button.component, "./Page.lumix");
+    assert!(button.js.contains("__LUMIX__.__hydrateElement(__c, 'button'"));
+    assert!(button.js.contains("const { count, title } = __islandScript(props);"));
+
+    // The counter hydrates on its own, with serialized props.
+    let counter = &build.islands[1];
+    assert_eq!(counter.js, "export { default } from './Counter.lumix';\n");
+
+    let manifest = build.manifest("Page.lumix");
+    assert_eq!(manifest["islands"][1]["id"], "Page-1");
+    assert_eq!(manifest["islands"][1]["component"], "./Counter.lumix");
+    assert_eq!(manifest["islands"][1]["entry"], "Page.island-1.js");
+    assert_eq!(manifest["islands"][1]["props"]["start"], 3);
+}
+
+#[test]
+fn use_static_islands_share_the_page_script() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let page_path = tmp_dir.path().join("Page.lumix");
+
+    let source = r#"<script>
+"use static";
+import { signal } from 'lumix-js';
+const count = signal(0);
+const [first, { second = 2 }] = [1, {}];
+function inc() { count(count() + 1); }
+</script>
+
+<div>
+  <p><button onClick={inc}>+</button></p>
+  <h2>Static</h2>
+  <p>{count()}</p>
+</div>
+"#;
+    fs::write(&page_path, source).expect("write page");
+
+    let build = lumixjs::compile_static_file(&page_path).expect("compile");
+    assert_eq!(build.islands.len(), 2);
+
+    // The script runs once, in the shared module, and hands out its bindings.
+    let script = build.script.as_ref().expect("island script");
+    assert_eq!(script.entry, "Page.island-script.js");
+    println!("Island script:\n{}", script.js);
+    assert_eq!(script.js.matches("signal(0)").count(), 1);
+    assert!(script.js.contains("import { signal } from 'lumix-js';"));
+    assert!(script.js.contains("  if (__script) return __script;"));
+    assert!(script.js.contains("__script = { count, first, second, inc };"));
+
+    // The island writing `count` and the one reading it use that same signal.
+    let (writer, reader) = (&build.islands[0], &build.islands[1]);
+    assert!(writer.js.contains("'onClick': inc"));
+    assert!(reader.js.contains("__LUMIX__.__hydrateBlock(__c, () => (count()))"));
+    for island in [writer, reader] {
+        assert!(island.js.contains("import __islandScript from './Page.island-script.js';"));
+        assert!(island.js.contains("const { count, first, second, inc } = __islandScript(props);"));
+        assert!(!island.js.contains("signal(0)"));
+    }
+
+    let modules: Vec<&str> = build.client_modules().map(|(entry, _)| entry).collect();
+    assert_eq!(modules, ["Page.island-0.js", "Page.island-1.js", "Page.island-script.js"]);
+}
+
+#[test]
+fn parses_render_directive_prologue() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let input_path = tmp_dir.path().join("Page.lumix");
+
+    let source = r#"<script>
+// Rendered on every request
+"use strict";
+"use server";
+const user = "Ada";
+</script>
+
+<p>{user}</p>
+"#;
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    assert_eq!(component.render_mode, lumixjs::ast::RenderMode::Server);
+    assert_eq!(
+        lumixjs::parser::parse_component("<p>hi</p>").expect("parse").render_mode,
+        lumixjs::ast::RenderMode::Prerender
+    );
+
+    let source = r#"<script>
+"use server";
+"use static";
+const user = "Ada";
+"use prerender";
+</script>
+
+<p>{user}</p>
+"#;
+    fs::write(&input_path, source).expect("write input");
+
+    let (_, diags) = lumixjs::compile_file_with_diagnostics(&input_path).expect("compile");
+    println!("Diagnostics: {:#?}", diags);
+
+    assert_eq!(diags.len(), 2);
+    assert!(diags[0].message.contains("'use static' conflicts with 'use server'"));
+    assert_eq!((diags[0].start.line, diags[0].start.col), (3, 1));
+    assert!(diags[1].message.contains("'use prerender' has no effect here"));
+    assert_eq!((diags[1].start.line, diags[1].start.col), (5, 1));
+}
+
+#[test]
+fn parser_recovers_and_reports_every_syntax_error() {
+    let source = r#"---
+import Card from './Card.lumix'
+improt Bad from './Bad.lumix'
+---
+<script>
+const count = signal(0);
+const x = ;
+</script>
+<div class="a>
+  <span>{count()</span>
+  <p @click={() => x}>hi</p>
+  <ul><li>one</ul>
+  <Card title={} />
+</div>
+"#;
+
+    let (component, diagnostics) = lumixjs::parser::parse_component_recovering(source);
+    let found: Vec<(usize, &str)> = diagnostics.iter().map(|d| (d.start.line, d.message.as_str())).collect();
+    assert_eq!(
+        found,
+        vec![
+            (3, "syntax error: invalid import line in imports block: improt Bad from './Bad.lumix'"),
+            (7, "template error: JS parse error in <script>: Expression expected"),
+            (9, "template error: unterminated string literal"),
+            (10, "template error: '{' expression without matching '}' in template"),
+            (11, "template error: expected attribute name"),
+            (12, "template error: mismatched closing tag </ul>; expected </li>"),
+            (13, "template error: empty {} expression in template"),
+        ]
+    );
+
+    // The rest of the file is still there for validation and type checking.
+    assert_eq!(component.imports.len(), 1);
+    assert!(component.script.as_ref().unwrap().original_code.contains("signal(0)"));
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    let tags: Vec<&str> = div
+        .children
+        .iter()
+        .filter_map(|n| match n {
+            lumixjs::ast::TemplateNode::Element(el) => Some(el.tag_name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(tags, ["span", "p", "ul", "Card"]);
+
+    // The strict parser stops at the first of them.
+    let err = lumixjs::parser::parse_component(source).expect_err("should error");
+    assert_eq!(err.to_string(), diagnostics[0].message);
+}
+
+#[test]
+fn serve_answers_json_rpc_requests_over_stdio() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let app_path = tmp_dir.path().join("App.lumix");
+    let child_path = tmp_dir.path().join("Child.lumix");
+    fs::write(&app_path, "---\nimport Child from './Child.lumix'\n---\n<div><Child /></div>\n").expect("write app");
+    fs::write(&child_path, "<span>on disk</span>\n").expect("write child");
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_lumixjs"))
+        .arg("serve")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn serve");
+    let mut stdin = server.stdin.take().expect("stdin");
+    let mut stdout = BufReader::new(server.stdout.take().expect("stdout"));
+    let mut request = |message: serde_json::Value| -> serde_json::Value {
+        writeln!(stdin, "{}", message).expect("write request");
+        let mut line = String::new();
+        stdout.read_line(&mut line).expect("read response");
+        serde_json::from_str(&line).expect("response json")
+    };
+
+    // In-memory source, compiled as a module.
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "compile",
+        "params": { "file": app_path, "source": "<script>\n\"use server\";\n</script>\n<p>{1 + 1}</p>\n", "target": "ssr" }
+    }));
+    assert_eq!(res["id"], 1);
+    assert_eq!(res["result"]["renderMode"], "server");
+    assert!(res["result"]["js"].as_str().unwrap().contains("ssrText"));
+
+    // Bundle with an unsaved dependency, then again from disk.
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "bundle",
+        "params": { "file": app_path, "sources": { child_path.display().to_string(): "<span>in memory</span>\n" } }
+    }));
+    assert!(res["result"]["js"].as_str().unwrap().contains("in memory"));
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 3, "method": "bundle", "params": { "file": app_path }
+    }));
+    assert!(res["result"]["js"].as_str().unwrap().contains("on disk"));
+
+    // Compile errors come back as `--format json` payloads, protocol errors as JSON-RPC errors.
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 4, "method": "parse", "params": { "file": app_path, "source": "<p>" }
+    }));
+    assert_eq!(res["result"]["diagnostics"][0]["message"], "template error: unclosed tag <p>");
+    assert_eq!(res["result"]["diagnostics"][0]["start"]["line"], 1);
+    let res = request(serde_json::json!({ "jsonrpc": "2.0", "id": 5, "method": "format" }));
+    assert_eq!(res["error"]["code"], -32601);
+
+    // A "use static" page follows the requested target unless islands are asked for.
+    let page = "<script>\n\"use static\";\nconst count = signal(0);\n</script>\n<button onClick={() => count(1)}>{count()}</button>\n";
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 6, "method": "compile", "params": { "file": app_path, "source": page }
+    }));
+    assert!(res["result"]["js"].as_str().unwrap().starts_with("// Generated by LumixJS\n"));
+    assert!(res["result"].get("islandEntries").is_none());
+    let res = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 7, "method": "compile", "params": { "file": app_path, "source": page, "islands": true }
+    }));
+    assert!(res["result"]["js"].as_str().unwrap().starts_with("// Generated by LumixJS (SSR)\n"));
+    let entries: Vec<&String> = res["result"]["islandEntries"].as_object().unwrap().keys().collect();
+    assert_eq!(entries, ["App.island-0.js", "App.island-script.js"]);
+
+    let res = request(serde_json::json!({ "jsonrpc": "2.0", "id": 8, "method": "shutdown" }));
+    assert_eq!(res["id"], 8);
+    assert!(server.wait().expect("wait").success());
+}
+
+#[test]
+fn source_cache_drops_least_recently_used_components() {
+    use lumixjs::cache::SourceCache;
+    use std::path::Path;
+
+    let mut cache = SourceCache::with_capacity(2);
+    cache.parse(Path::new("A.lumix"), "<p>a</p>").expect("parse a");
+    cache.parse(Path::new("B.lumix"), "<p>b</p>").expect("parse b");
+    cache.parse(Path::new("A.lumix"), "<p>a</p>").expect("reuse a");
+    cache.parse(Path::new("C.lumix"), "<p>c</p>").expect("parse c");
+
+    // B was the least recently used, so it is the one that went.
+    assert!(cache.is_cached(Path::new("A.lumix")));
+    assert!(!cache.is_cached(Path::new("B.lumix")));
+    assert!(cache.is_cached(Path::new("C.lumix")));
+}
+
+#[test]
+fn language_server_serves_lumix_documents() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::{Command, Stdio};
+
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let card_path = tmp_dir.path().join("Card.lumix");
+    fs::write(
+        &card_path,
+        "<script>\nimport { model } from \"lumix-js\";\nexport let title = \"Untitled\";\nconst open = model(false);\n</script>\n<section>{@slot header}{@slot}</section>\n",
+    )
+    .expect("write card");
+    let app_uri = format!("file://{}", tmp_dir.path().join("App.lumix").display());
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_lumixjs"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn lsp");
+    let mut stdin = server.stdin.take().expect("stdin");
+    let mut stdout = BufReader::new(server.stdout.take().expect("stdout"));
+    let mut send = |message: serde_json::Value| {
+        let body = message.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).expect("write message");
+        stdin.flush().expect("flush");
+    };
+    let mut receive = || -> serde_json::Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            stdout.read_line(&mut header).expect("read header");
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().expect("content length");
+            }
+        }
+        let mut body = vec![0; length];
+        stdout.read_exact(&mut body).expect("read body");
+        serde_json::from_slice(&body).expect("message json")
+    };
+    let position = |id: u64, method: &str, line: u64, character: u64| {
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": id, "method": method,
+            "params": { "textDocument": { "uri": app_uri }, "position": { "line": line, "character": character } }
+        })
+    };
+
+    send(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    assert_eq!(receive()["result"]["capabilities"]["hoverProvider"], true);
+
+    let app = "---\nimport Card from './Card.lumix'\n---\n<Card title=\"Hi\">\n  <h1 slot=\"header\">Hi</h1>\n</Card>\n";
+    send(serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": app_uri, "languageId": "lumix", "version": 1, "text": app } }
+    }));
+    assert_eq!(receive()["params"]["diagnostics"], serde_json::json!([]));
+
+    // Half-typed edits report the error and keep the last good parse around.
+    let broken = format!("{}<Ca", app);
+    send(serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": { "textDocument": { "uri": app_uri, "version": 2 }, "contentChanges": [{ "text": broken }] }
+    }));
+    let diagnostics = receive()["params"]["diagnostics"].clone();
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    send(position(2, "textDocument/completion", 6, 3));
+    assert_eq!(receive()["result"][0]["label"], "Card");
+    send(position(3, "textDocument/completion", 4, 12));
+    assert_eq!(receive()["result"], serde_json::json!([{ "label": "header", "kind": 12 }]));
+    send(position(4, "textDocument/definition", 3, 2));
+    assert!(receive()["result"]["uri"].as_str().unwrap().ends_with("/Card.lumix"));
+    send(position(5, "textDocument/hover", 3, 2));
+    let hover = receive()["result"]["contents"]["value"].as_str().unwrap().to_string();
+    assert!(hover.contains("`title` = `\"Untitled\"`"), "{}", hover);
+    assert!(hover.contains("Slots: `header`"), "{}", hover);
+
+    // `bind:` on a component offers the props it declares with `model()`.
+    send(serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didChange",
+        "params": { "textDocument": { "uri": app_uri, "version": 3 }, "contentChanges": [{ "text": format!("{}<Card bind:", app) }] }
+    }));
+    receive();
+    send(position(7, "textDocument/completion", 6, 11));
+    let labels: Vec<String> = receive()["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(labels, ["open", "this"]);
+
+    // A stray `%` in a URI is kept as it is.
+    send(serde_json::json!({
+        "jsonrpc": "2.0", "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": "file:///tmp/100%€/A%2.lumix%", "languageId": "lumix", "version": 1, "text": "<p></p>\n" } }
+    }));
+    assert_eq!(receive()["params"]["uri"], "file:///tmp/100%€/A%2.lumix%");
+
+    send(serde_json::json!({ "jsonrpc": "2.0", "id": 6, "method": "shutdown" }));
+    assert_eq!(receive()["id"], 6);
+    send(serde_json::json!({ "jsonrpc": "2.0", "method": "exit" }));
+    assert!(server.wait().expect("wait").success());
+}
+
+#[test]
+fn parses_comments_doctype_and_cdata() {
+    let source = r#"<!DOCTYPE html>
+<html>
+  <head><!--[if IE]><p>old</p><![endif]--></head>
+  <body>
+    <!-- note -->
+    <p><![CDATA[a < b]]></p>
+  </body>
+</html>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    assert!(matches!(
+        &component.template[0],
+        lumixjs::ast::TemplateNode::Declaration(text) if text == "DOCTYPE html"
+    ));
+
+    let ssr = |comments| {
+        let options = lumixjs::codegen::CodegenOptions {
+            target: lumixjs::codegen::CodegenTarget::Ssr,
+            comments,
+            ..Default::default()
+        };
+        lumixjs::codegen::generate_js_with_options(&component, "Layout", &options)
+    };
+    let stripped = ssr(lumixjs::codegen::CommentOutput::Strip);
+    println!("Generated JS:\n{}", stripped);
+    assert!(stripped.contains("<!DOCTYPE html>"));
+    assert!(stripped.contains("<!--[if IE]><p>old</p><![endif]-->"));
+    assert!(!stripped.contains("note"));
+    assert!(stripped.contains("a &lt; b"));
+
+    let kept = ssr(lumixjs::codegen::CommentOutput::Keep);
+    assert!(kept.contains("<!-- note -->"));
+
+    let dom = lumixjs::codegen::generate_js_with_options(
+        &component,
+        "Layout",
+        &lumixjs::codegen::CodegenOptions {
+            comments: lumixjs::codegen::CommentOutput::Keep,
+            ..Default::default()
+        },
+    );
+    assert!(dom.contains("document.createComment(` note `)"));
+}
+
+#[test]
+fn parses_void_and_raw_text_elements() {
+    let source = r#"<script>
+const name = signal("x")
+</script>
+<form>
+  <input type="text"><br><img src="a.png"></img>
+  <p>1 < 2</p>
+  <pre>if (a < b) return;</pre>
+  <textarea>Hi <b>{name()}</b></textarea>
+  <script type="application/ld+json">{"name": "<x>"}</script>
+</form>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(form) = &component.template[0] else {
+        panic!("expected <form>");
+    };
+    let children: Vec<(&str, usize)> = form
+        .children
+        .iter()
+        .filter_map(|n| match n {
+            lumixjs::ast::TemplateNode::Element(el) => Some((el.tag_name.as_str(), el.children.len())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        children,
+        [("input", 0), ("br", 0), ("img", 0), ("p", 1), ("pre", 1), ("textarea", 3), ("script", 1)]
+    );
+
+    let ssr = lumixjs::codegen::generate_js_with_options(
+        &component,
+        "App",
+        &lumixjs::codegen::CodegenOptions {
+            target: lumixjs::codegen::CodegenTarget::Ssr,
+            ..Default::default()
+        },
+    );
+    println!("Generated JS:\n{}", ssr);
+    assert!(ssr.contains("<input type=\\\"text\\\"><br><img src=\\\"a.png\\\">"));
+    assert!(ssr.contains("<p>1 &lt; 2</p>"));
+    assert!(ssr.contains("<textarea>Hi &lt;b&gt;\" + __LUMIX__.ssrText(() => (name())) + \"&lt;/b&gt;</textarea>"));
+    assert!(ssr.contains("{\\\"name\\\": \\\"<x>\\\"}</script>"));
+
+    let dom = lumixjs::codegen::generate_js(&component, "App");
+    assert!(dom.contains("__LUMIX__.__hydrateText(__c, () => [\"Hi <b>\", () => (name()), \"</b>\"])"));
+}
+
+#[test]
+fn decodes_entities_and_collapses_whitespace() {
+    let source = r#"<div title="Tom &amp; Jerry&#39;s">
+  <p>
+    &copy; 2024   &nbsp;{year}
+    <b>x</b>  &#x2014; &bogus;
+  </p>
+  <ul>
+    <li>one</li>
+    <li>two</li>
+  </ul>
+  <pre>
+  a  b</pre>
+  <p preserve-whitespace> c  <i>d</i>
+  </p>
+</div>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    assert!(matches!(
+        &div.attributes[0],
+        lumixjs::ast::AttributeNode::Static { value, .. } if value == "Tom & Jerry's"
+    ));
+
+    let texts = |el: &lumixjs::ast::TemplateNode| -> Vec<String> {
+        let lumixjs::ast::TemplateNode::Element(el) = el else {
+            panic!("expected an element");
+        };
+        el.children
+            .iter()
+            .map(|n| match n {
+                lumixjs::ast::TemplateNode::Text(t) => t.clone(),
+                lumixjs::ast::TemplateNode::Expr(_) => "{}".into(),
+                lumixjs::ast::TemplateNode::Element(el) => format!("<{}>", el.tag_name),
+                _ => "?".into(),
+            })
+            .collect()
+    };
+    // Indentation between tags is gone.
+    assert_eq!(texts(&component.template[0]), ["<p>", "<ul>", "<pre>", "<p>"]);
+    assert_eq!(texts(&div.children[0]), ["© 2024 \u{a0}", "{}", " ", "<b>", " — &bogus;"]);
+    assert_eq!(texts(&div.children[1]), ["<li>", "<li>"]);
+    assert_eq!(texts(&div.children[2]), ["  a  b"]);
+    assert_eq!(texts(&div.children[3]), [" c  ", "<i>", "\n  "]);
+    let lumixjs::ast::TemplateNode::Element(kept) = &div.children[3] else {
+        unreachable!()
+    };
+    assert!(kept.attributes.is_empty());
+
+    let dom = lumixjs::codegen::generate_js(&component, "App");
+    assert!(dom.contains("'title': 'Tom & Jerry\\'s'"));
+}
+
+#[test]
+fn supports_attribute_spread_and_shorthand() {
+    let source = r#"<script>
+const rest = signal({ placeholder: "Name" });
+const title = "Hi";
+const onClick = () => {};
+</script>
+<div>
+  <input type="text" {...rest} class="y" {onClick}>
+  <Card {title} {... rest()} />
+  <p {a + b}></p>
+</div>
+"#;
+
+    let (component, diagnostics) = lumixjs::parser::parse_component_recovering(source);
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        ["template error: expected an identifier or '...' in attribute shorthand {a + b}"]
+    );
+
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    let lumixjs::ast::TemplateNode::Element(input) = &div.children[0] else {
+        panic!("expected <input>");
+    };
+    assert!(matches!(
+        &input.attributes[1],
+        lumixjs::ast::AttributeNode::Spread { expr } if expr.code == "rest"
+            && expr.span.map(|s| &source[s.start..s.end]) == Some("rest")
+    ));
+    assert!(matches!(
+        &input.attributes[3],
+        lumixjs::ast::AttributeNode::EventHandler { name, expr, .. } if name == "onClick" && expr.code == "onClick"
+    ));
+
+    let component = lumixjs::parser::parse_component(&source.replace("  <p {a + b}></p>\n", "")).expect("parse");
+    let dom = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", dom);
+    assert!(dom.contains("'type': 'text',\n      '...0': () => (rest),\n      'class': 'y',\n      'onClick': onClick"));
+    assert!(dom.contains("'title': () => { const __v = (title);"));
+    assert!(dom.contains("'...0': () => (rest())"));
+    // Hydration passes the static attributes too, so later ones still win.
+    assert!(dom.contains("__LUMIX__.__hydrateElement(__c, 'input', { 'type': \"text\", '...0': () => (rest), 'class': \"y\""));
+
+    let ssr = lumixjs::codegen::generate_js_with_options(
+        &component,
+        "App",
+        &lumixjs::codegen::CodegenOptions {
+            target: lumixjs::codegen::CodegenTarget::Ssr,
+            ..Default::default()
+        },
+    );
+    assert!(ssr.contains("\"<div><input\" + __LUMIX__.ssrSpreadAttrs({ \"type\": \"text\", '...0': () => (rest), \"class\": \"y\" }) + \"> \""));
+}
+
+#[test]
+fn compiles_event_modifiers() {
+    let source = r#"<script>
+const save = (e: Event) => {};
+</script>
+<form onSubmit|preventDefault={save}>
+  <div onClick|self|stopPropagation|once={save}></div>
+  <div onScroll|passive={save}></div>
+  <p title|x="a" onClick|bogus={save} onWheel|passive|preventDefault={save} onKeydown|once="x"></p>
+</form>
+"#;
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering(source);
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: modifiers are only allowed on event handlers, not 'title'",
+            "template error: unknown event modifier 'bogus'; expected one of preventDefault, stopPropagation, stopImmediatePropagation, self, trusted, once, capture, passive",
+            "template error: 'passive' and 'preventDefault' cannot be combined: passive listeners cannot cancel the event",
+            "template error: 'onKeydown' has event modifiers, so its value must be a {handler}",
+        ]
+    );
+
+    let component = lumixjs::parser::parse_component(&source.replace(
+        "  <p title|x=\"a\" onClick|bogus={save} onWheel|passive|preventDefault={save} onKeydown|once=\"x\"></p>\n",
+        "",
+    ))
+    .expect("parse");
+    let lumixjs::ast::TemplateNode::Element(form) = &component.template[0] else {
+        panic!("expected <form>");
+    };
+    assert!(matches!(
+        &form.attributes[0],
+        lumixjs::ast::AttributeNode::EventHandler { name, modifiers, .. }
+            if name == "onSubmit" && modifiers == &[lumixjs::ast::EventModifier::PreventDefault]
+    ));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("'onSubmit': function (e) { e.preventDefault(); return (save).call(this, e); }"));
+    assert!(js.contains(
+        "'onClick': __LUMIX__.listener(function (e) { if (e.target !== e.currentTarget) return; e.stopPropagation(); return (save).call(this, e); }, { once: true })"
+    ));
+    assert!(js.contains("'onScroll': __LUMIX__.listener(save, { passive: true })"));
+}
+
+#[test]
+fn compiles_class_and_style_directives() {
+    let source = r#"<script>
+const active = signal(true);
+const color = signal("red");
+</script>
+<div class="card" class:active={active()} class:hidden style="margin: 0" style:color={color()} style:--gap="4px"></div>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    assert!(matches!(
+        &div.attributes[1],
+        lumixjs::ast::AttributeNode::Class { name, expr } if name == "active" && expr.code == "active()"
+    ));
+    assert!(matches!(
+        &div.attributes[2],
+        lumixjs::ast::AttributeNode::Class { name, expr } if name == "hidden" && expr.code == "hidden"
+    ));
+    assert!(matches!(
+        &div.attributes[5],
+        lumixjs::ast::AttributeNode::Style { property, expr } if property == "--gap" && expr.code == "\"4px\""
+    ));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("'class': 'card'"));
+    assert!(js.contains("'class:active': () => { const __v = (active());"));
+    assert!(js.contains("'style:color': () => { const __v = (color());"));
+    assert!(js.contains("'style:--gap': \"4px\""));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    println!("Generated SSR:\n{}", ssr);
+    assert!(ssr.contains("__LUMIX__.ssrSpreadAttrs({ \"class\": \"card\", \"class:active\":"));
+
+    let (_, diagnostics) =
+        lumixjs::parser::parse_component_recovering("<p class:a=\"x\" class:a-b style:color class:={x}></p>\n");
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: 'class:a' needs a {condition}",
+            "template error: 'class:a-b' needs a {condition}",
+            "template error: 'style:color' needs a value",
+            "template error: expected a name after 'class:'",
+        ]
+    );
+}
+
+#[test]
+fn compiles_use_actions() {
+    let source = r#"<script>
+const text = signal("hi");
+</script>
+<div use:tooltip={{ text: text() }} use:focus><p>x</p></div>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    assert!(matches!(
+        &div.attributes[0],
+        lumixjs::ast::AttributeNode::Action { action, params: Some(params) }
+            if action.code == "tooltip" && params.code == "{ text: text() }"
+    ));
+    assert!(matches!(
+        &div.attributes[1],
+        lumixjs::ast::AttributeNode::Action { action, params: None } if action.code == "focus"
+    ));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("'use:tooltip': [tooltip, () => ({"));
+    assert!(js.contains("'use:focus': [focus]"));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    assert!(ts.contains("(HTMLElementTagNameMap & Record<string, HTMLElement>)['div'], ("));
+    assert!(ts.contains("(/* @L:"));
+
+    let (_, diagnostics) =
+        lumixjs::parser::parse_component_recovering("<p use:a-b use:x=\"y\"></p>\n<Card use:focus />\n");
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: expected an action name after 'use:', found 'a-b'",
+            "template error: 'use:x' takes its parameters as {params}",
+            "template error: 'use:focus' is only allowed on elements, not on <Card>",
+        ]
+    );
+}
+
+#[test]
+fn compiles_bind_this_references() {
+    let source = r#"---
+import Card from "./Card.lumix";
+---
+<script>
+let box: HTMLDivElement | undefined;
+const input = signal<HTMLInputElement | null>(null);
+</script>
+<div bind:this={box}><input bind:this={input} /></div>
+<Card bind:this={box} />
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("'bind:this': (__v) => { if (__LUMIX__.isSignal(input)) input(__v); else input = __v; }"));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    println!("Generated TS:\n{}", ts);
+    assert!(ts.contains(
+        "((__v: (HTMLElementTagNameMap & Record<string, HTMLElement>)['input']): __LUMIN__.RefTarget<typeof /* @L:"
+    ));
+    assert!(ts.contains("((__v: Node | Node[]): __LUMIN__.RefTarget<typeof /* @L:"));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("\"<div><input></div> \""));
+    assert!(!ssr.contains("bind:this"));
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering("<div bind:this={refs[0]}></div>\n");
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        ["template error: 'bind:this' needs a variable or signal to store the node in, not 'refs[0]'"]
+    );
+}
+
+#[test]
+fn binds_model_props_of_child_components() {
+    let tmp_dir = tempfile::tempdir().expect("tempdir");
+    let app_path = tmp_dir.path().join("App.lumix");
+    let field_path = tmp_dir.path().join("TextField.lumix");
+
+    let field = r#"<script>
+const value = model("");
+const label = prop("Name");
+</script>
+<label>{label}<input bind:value={value} /></label>
+"#;
+
+    let app = r#"---
+import TextField from "./TextField.lumix"
+---
+<script>
+const name = signal("Ada");
+</script>
+<div><TextField bind:value={name} /></div>
+"#;
+
+    fs::write(&field_path, field).expect("write field");
+    fs::write(&app_path, app).expect("write app");
+
+    let res = lumixjs::bundler::bundle_entry(&app_path).expect("bundle");
+    println!("Bundled JS:\n{}", res.js);
+    assert!(res.diagnostics.is_empty(), "Diagnostics: {:?}", res.diagnostics);
+    assert!(res.js.contains("let { label = \"Name\" } = props;"));
+    assert!(res.js.contains("const value = __LUMIX__.__modelProp(props, 'value', \"\");"));
+    assert!(res.js.contains("'bind:value': name"));
+
+    fs::write(
+        &app_path,
+        app.replace("bind:value={name} />", "bind:value={name} bind:label={name} bind:size={name} />"),
+    )
+    .expect("write app");
+    let res = lumixjs::bundler::bundle_entry(&app_path).expect("bundle");
+    let found: Vec<&str> = res.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "Prop 'label' of component 'TextField' is not bindable; declare it with model()",
+            "Component 'TextField' has no prop named 'size' to bind",
+        ]
+    );
+}
+
+#[test]
+fn parses_for_headers_with_swc() {
+    let source = r#"<ul>
+@{for (const { id, name } of users(); index=i; key=id) {
+  <li>{i}: {name}</li>
+}}
+@{for ([k, v] of Object.entries(counts)) {
+  <li>{k}={v}</li>
+}}
+</ul>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(ul) = &component.template[0] else {
+        panic!("expected <ul>");
+    };
+    let loops: Vec<_> = ul
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            lumixjs::ast::TemplateNode::ControlFlow(lumixjs::ast::ControlFlowBlock::For {
+                binding,
+                iterable,
+                index,
+                key_expr,
+                ..
+            }) => Some((binding.as_str(), iterable, index.as_deref(), key_expr.as_deref())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(loops.len(), 2);
+    assert_eq!(loops[0].0, "{ id, name }");
+    assert_eq!(loops[0].1.code, "users()");
+    let span = loops[0].1.span.expect("iterable span");
+    assert_eq!(&source[span.start..span.end], "users()");
+    assert_eq!((loops[0].2, loops[0].3), (Some("i"), Some("id")));
+    assert_eq!((loops[1].0, loops[1].1.code.as_str()), ("[k, v]", "Object.entries(counts)"));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("({ id, name }, i) => ["));
+    assert!(js.contains("], ({ id, name }) => (id))"));
+    assert!(js.contains("([k, v]) => ["));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("__LUMIX__.ssrEach(() => (users()), ({ id, name }, i) =>"));
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering(
+        "@{for (item in items) { <p>{item}</p> }}\n@{for (const x of xs; index=1; sort=x) { <p>{x}</p> }}\n",
+    );
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: expected 'item of list' in @{for}, e.g. @{for (const item of items)}",
+            "template error: 'index=' needs a variable name, not '1'",
+            "template error: unknown clause 'sort=x' in @{for}; expected 'index=name' or 'key=expr'",
+        ]
+    );
+}
+
+#[test]
+fn compiles_await_blocks() {
+    let source = r#"<script>
+const id = signal(1);
+</script>
+@{await (loadUser(id())) {
+  <p>Loading</p>
+} then ({ name }) {
+  <p>{name}</p>
+} catch (err) {
+  <p>{err.message}</p>
+}}
+@{await (loadUser(2)) then (user) <b>{user.name}</b>}
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let blocks: Vec<_> = component
+        .template
+        .iter()
+        .filter_map(|node| match node {
+            lumixjs::ast::TemplateNode::ControlFlow(block @ lumixjs::ast::ControlFlowBlock::Await { .. }) => Some(block),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(blocks.len(), 2);
+    let lumixjs::ast::ControlFlowBlock::Await {
+        promise,
+        pending,
+        then_binding,
+        catch_binding,
+        catch_branch,
+        ..
+    } = blocks[0]
+    else {
+        unreachable!()
+    };
+    assert_eq!(promise.code, "loadUser(id())");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(then_binding.as_deref(), Some("{ name }"));
+    assert_eq!(catch_binding.as_deref(), Some("err"));
+    assert!(catch_branch.is_some());
+    let lumixjs::ast::ControlFlowBlock::Await { pending, then_binding, catch_branch, .. } = blocks[1] else {
+        unreachable!()
+    };
+    assert!(pending.is_empty() && catch_branch.is_none());
+    assert_eq!(then_binding.as_deref(), Some("user"));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("__LUMIX__.__await(() => (loadUser(id())), () => ["));
+    assert!(js.contains("], ({ name }) => ["));
+    assert!(js.contains("], (err) => ["));
+    assert!(js.contains("__LUMIX__.__hydrateAwait(__c, () => (loadUser(id())), {"));
+    assert!(js.contains("hydrate: (__c, { name }) => {"));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("__LUMIX__.ssrAwait(() => (loadUser(2)), () => \"\", (user) =>"));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    println!("Generated TS:\n{}", ts);
+    assert!(ts.contains("const { name } = null as unknown as Awaited<typeof __promise>;"));
+
+    let (_, diagnostics) =
+        lumixjs::parser::parse_component_recovering("@{await (p) then (a + b) { <p>x</p> }}\n");
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        ["template error: expected a variable name or destructuring pattern, found 'a + b'"]
+    );
+}
+
+#[test]
+fn compiles_switch_blocks() {
+    let source = r#"<script>
+const route = signal<"home" | "about">("home");
+</script>
+@{switch (route()) {
+  default { <p>Not found</p> }
+  case "home" { <h1>Home</h1> }
+  case "about" { <h1>About</h1> }
+}}
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::ControlFlow(lumixjs::ast::ControlFlowBlock::Switch {
+        discriminant,
+        cases,
+        default,
+    }) = &component.template[0]
+    else {
+        panic!("expected @{{switch}}");
+    };
+    assert_eq!(discriminant.code, "route()");
+    let values: Vec<&str> = cases.iter().map(|(value, _)| value.code.as_str()).collect();
+    assert_eq!(values, ["\"home\"", "\"about\""]);
+    assert!(default.is_some());
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("__LUMIX__.__switch(() => (route()), ["));
+    assert!(js.contains("{ value: () => (\"home\"), body: () => ["));
+    assert!(js.contains("__LUMIX__.__hydrateSwitch(__c, () => (route()), ["));
+    // The default branch is matched last.
+    let about = js.find("{ value: () => (\"about\")").unwrap();
+    assert!(js[about..].contains("{ body: () => ["));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("switch (route()) { case \"home\": return \"<h1>Home</h1>\";"));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    assert!(ts.contains("switch (__switch) {"));
+    assert!(!ts.contains("__unhandled"));
+    let without_default = source.replace("  default { <p>Not found</p> }\n", "");
+    let component = lumixjs::parser::parse_component(&without_default).expect("parse");
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    println!("Generated TS:\n{}", ts);
+    assert!(ts.contains("const __unhandled: __LUMIN__.Exhaustive<typeof __switch> = (/* @L:"));
+
+    // `@{match}` is the same block.
+    let matched = lumixjs::parser::parse_component(&source.replace("@{switch", "@{match")).expect("parse");
+    assert!(matches!(
+        &matched.template[0],
+        lumixjs::ast::TemplateNode::ControlFlow(lumixjs::ast::ControlFlowBlock::Switch { .. })
+    ));
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering(
+        "@{switch (x) { case { <p>a</p> } default { <p>b</p> } default { <p>c</p> } }}\n",
+    );
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: expected a value after 'case'",
+            "template error: @{switch} can only have one 'default'",
+        ]
+    );
+}
+
+#[test]
+fn compiles_const_tags_in_branches() {
+    let source = r#"<script>
+const items = signal([{ price: 2, qty: 3 }]);
+</script>
+@{for (const item of items()) {
+  {@const total = item.price * item.qty}
+  {@const { qty } = item}
+  <li>{qty}: {total}</li>
+}}
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::ControlFlow(lumixjs::ast::ControlFlowBlock::For { body, .. }) = &component.template[0]
+    else {
+        panic!("expected @{{for}}");
+    };
+    let lumixjs::ast::TemplateNode::Const(tag) = &body[0] else {
+        panic!("expected {{@const}} first, got {:?}", body[0]);
+    };
+    assert_eq!(tag.binding, "total");
+    assert_eq!(tag.expr.code, "item.price * item.qty");
+    let span = tag.expr.span.expect("span");
+    assert_eq!(&source[span.start..span.end], "item.price * item.qty");
+    assert!(matches!(&body[1], lumixjs::ast::TemplateNode::Const(tag) if tag.binding == "{ qty }"));
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains(
+        "__LUMIX__.__for(() => (items()), item => { const total = item.price * item.qty; const { qty } = item; return ["
+    ));
+    assert!(js.contains("(__c, item) => {\n      const total = item.price * item.qty;"));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("item => (() => { const total = item.price * item.qty; const { qty } = item; return ("));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    println!("Generated TS:\n{}", ts);
+    assert!(ts.contains("  for (const item of /* @L:"));
+    assert!(ts.contains("  const total = (/* @L:"));
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering(
+        "{@const a = 1}\n@{if (x) {\n  <p></p>\n  {@const b = 1}\n} else {\n  {@const c = 1}\n  {@const [c] = [2]}\n}}\n",
+    );
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        [
+            "template error: {@const} is only allowed at the top of an @{if}, @{for}, @{await} or @{switch} branch",
+            "template error: {@const} must come before the other content of its branch",
+            "template error: 'c' is already declared by a {@const} in this branch",
+        ]
+    );
+}
+
+#[test]
+fn compiles_html_tags() {
+    let source = r#"<script>
+const body = signal("<b>hi</b>");
+</script>
+<div>{@html body()}</div>
+<pre>{@html highlight(code)}</pre>
+<p>{@html DOMPurify.sanitize(body())} {@html trustedHtml(note)} {@html "<hr>"}</p>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    let lumixjs::ast::TemplateNode::Html(expr) = &div.children[0] else {
+        panic!("expected {{@html}}, got {:?}", div.children[0]);
+    };
+    assert_eq!(expr.code, "body()");
+    let span = expr.span.expect("span");
+    assert_eq!(&source[span.start..span.end], "body()");
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("__LUMIX__.__html(() => (body()))"));
+    assert!(js.contains("__LUMIX__.__hydrateHtml(__c, () => (highlight(code)));"));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    assert!(ssr.contains("<div><!--cf-start-->\" + __LUMIX__.ssrHtml(() => (body())) + \"<!--cf-end--></div>"));
+
+    let diagnostics = lumixjs::validate_source(
+        std::path::Path::new("App.lumix"),
+        source,
+        &mut lumixjs::cache::SourceCache::new(),
+    );
+    assert!(!lumixjs::diagnostic::has_errors(&diagnostics));
+    let flagged: Vec<&str> = diagnostics.iter().map(|d| &source[d.range.start..d.range.end]).collect();
+    assert_eq!(flagged, ["body()", "highlight(code)"]);
+
+    let (_, diagnostics) = lumixjs::parser::parse_component_recovering("<p>{@html}</p>\n");
+    assert_eq!(diagnostics[0].message, "template error: expected '{@html expression}'");
+}
+
+#[test]
+fn compiles_snippets() {
+    let source = r#"<script>
+const items = signal([{ name: "a" }]);
+</script>
+@{snippet row(item: { name: string }, i) {
+  <li>{item.name} #{i}</li>
+}}
+<ul>
+  @{for (const item of items(); index=i) {
+    {@render row(item, i)}
+  }}
+</ul>
+<Table>
+  @{snippet cell(value) { <td>{value}</td> }}
+</Table>
+"#;
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Snippet(snippet) = &component.template[0] else {
+        panic!("expected @{{snippet}}, got {:?}", component.template[0]);
+    };
+    assert_eq!(snippet.name, "row");
+    assert_eq!(snippet.params, "item: { name: string }, i");
+    let span = snippet.name_span.expect("span");
+    assert_eq!(&source[span.start..span.end], "row");
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    println!("Generated JS:\n{}", js);
+    assert!(js.contains("const row = __LUMIX__.__snippet((item, i) => ["));
+    assert!(js.contains("() => (row(item, i))"));
+    assert!(js.contains("__LUMIX__.__hydrateRender(__c, () => (row), () => [item, i]);"));
+    assert!(js.contains("'cell': __LUMIX__.__snippet((value) => ["));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    println!("Generated SSR:\n{}", ssr);
+    assert!(ssr.contains("const row = (item, i) => (\"<li>"));
+    assert!(ssr.contains("\"<!--cf-start-->\" + __LUMIX__.ssrRaw(row(item, i)) + \"<!--cf-end-->\""));
+
+    let ts = lumixjs::ts_codegen::generate_ts(&component, "App");
+    println!("Generated TS:\n{}", ts);
+    assert!(ts.contains("row(item: { name: string }, i: any)"));
+
+    let (_, diagnostics) =
+        lumixjs::parser::parse_component_recovering("<div>\n  @{snippet row() { <p>x</p> }}\n</div>\n");
+    let found: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        found,
+        ["template error: @{snippet} can only be defined at the top level of the template, in a control-flow branch or inside a component"]
+    );
+}
+
+#[test]
+fn reports_style_attribute_errors_at_the_attribute() {
+    for (source, attr, message) in [
+        (
+            "<style global=\"false\">p {}</style>\n",
+            "global=\"false\"",
+            "template error: 'global' on <style> takes no value",
+        ),
+        (
+            "<style lang=\"scss\">p {}</style>\n",
+            "lang=\"scss\"",
+            "template error: unsupported attribute 'lang' on <style>; only 'global' is allowed",
+        ),
+    ] {
+        let (_, diagnostics) = lumixjs::parser::parse_component_recovering(source);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].message, message);
+        let range = &diagnostics[0].range;
+        assert_eq!(&source[range.start..range.end], attr);
+    }
+
+    let component = lumixjs::parser::parse_component("<style global>p {}</style>\n").expect("parse");
+    assert!(component.style.expect("style").global);
+}
+
+#[test]
+fn parses_markup_inside_pre() {
+    let source = "<div><pre>\n<code class=\"x\">let  a = 1;</code>\n  <b>x</b></pre></div>\n";
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let lumixjs::ast::TemplateNode::Element(div) = &component.template[0] else {
+        panic!("expected <div>");
+    };
+    let lumixjs::ast::TemplateNode::Element(pre) = &div.children[0] else {
+        panic!("expected <pre>");
+    };
+    let tags: Vec<&str> = pre
+        .children
+        .iter()
+        .map(|n| match n {
+            lumixjs::ast::TemplateNode::Element(el) => el.tag_name.as_str(),
+            lumixjs::ast::TemplateNode::Text(t) => t.as_str(),
+            _ => "?",
+        })
+        .collect();
+    // The newline after `<pre>` is dropped; the rest keeps its whitespace.
+    assert_eq!(tags, ["code", "\n  ", "b"]);
+
+    let js = lumixjs::codegen::generate_js(&component, "App");
+    assert!(js.contains("__LUMIX__.h('code', {"));
+    assert!(js.contains("__LUMIX__.h('b', null"));
+
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    println!("Generated SSR:\n{}", ssr);
+    assert!(ssr.contains("<div><pre><code class=\\\"x\\\">let  a = 1;</code>\\n  <b>x</b></pre></div>"));
+}
+
+#[test]
+fn keeps_spaces_between_inline_elements() {
+    let source = "<p>\n  <b>Hello</b>\n  <i>world</i>\n</p>\n<ul>\n  <li>a</li>\n  <!-- b -->\n  <li>c</li>\n</ul>\n";
+
+    let component = lumixjs::parser::parse_component(source).expect("parse");
+    let options = lumixjs::codegen::CodegenOptions {
+        target: lumixjs::codegen::CodegenTarget::Ssr,
+        ..Default::default()
+    };
+    let ssr = lumixjs::codegen::generate_js_with_options(&component, "App", &options);
+    println!("Generated SSR:\n{}", ssr);
+    assert!(ssr.contains("<p><b>Hello</b> <i>world</i></p>"));
+    assert!(ssr.contains("<ul><li>a</li><li>c</li></ul>"));
 }
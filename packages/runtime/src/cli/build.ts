@@ -10,75 +10,6 @@ function escapeHtml(s: string): string {
     .replace(/"/g, "&quot;");
 }
 
-/**
- * Parse directive from .lumix file using proper parsing instead of regex
- * Supported directives:
- * - "use prerender" -> PIR (Progressive Instant Rendering) - default
- * - "use server" -> SSR (Server-Side Rendering)
- * - "use static" -> SSG (Static Site Generation) - future islands architecture
- */
-function parseDirectiveFromLumix(lumixContent: string, routePath: string): string {
-  // Extract script section
-  const scriptMatch = lumixContent.match(/<script[^>]*>([\s\S]*?)<\/script>/);
-  if (!scriptMatch) {
-    return "prerender"; // No script section, default to prerender (PIR)
-  }
-  
-  const scriptContent = scriptMatch[1];
-  
-  // Parse the JavaScript content to find directives
-  try {
-    // Simple tokenizer to find string literals at the beginning
-    const lines = scriptContent.split('\n');
-    let foundDirective: string | null = null;
-    let directiveCount = 0;
-    
-    for (let i = 0; i < lines.length; i++) {
-      const line = lines[i].trim();
-      
-      // Skip empty lines and comments
-      if (!line || line.startsWith('//') || line.startsWith('/*')) {
-        continue;
-      }
-      
-      // Check if this line contains a directive
-      if (line.startsWith('"use server";') || line.startsWith("'use server';")) {
-        foundDirective = "server";
-        directiveCount++;
-      } else if (line.startsWith('"use prerender";') || line.startsWith("'use prerender';")) {
-        foundDirective = "prerender";
-        directiveCount++;
-      } else if (line.startsWith('"use static";') || line.startsWith("'use static';")) {
-        foundDirective = "static";
-        directiveCount++;
-      } else if (line.startsWith('"use server"') || line.startsWith("'use server'")) {
-        foundDirective = "server";
-        directiveCount++;
-      } else if (line.startsWith('"use prerender"') || line.startsWith("'use prerender'")) {
-        foundDirective = "prerender";
-        directiveCount++;
-      } else if (line.startsWith('"use static"') || line.startsWith("'use static'")) {
-        foundDirective = "static";
-        directiveCount++;
-      } else {
-        // If we hit a non-directive, non-comment, non-empty line, stop looking
-        // Directives must be at the top
-        break;
-      }
-    }
-    
-    // Validate directive count
-    if (directiveCount > 1) {
-      throw new Error(`Multiple directives found. Only one directive per component is allowed.`);
-    }
-    
-    return foundDirective || "prerender";
-    
-  } catch (error) {
-    throw new Error(`Failed to parse directive: ${(error as Error).message}`);
-  }
-}
-
 import lumix from "../../../vite-plugin-lumix/dist/index.js";
 import pc from "picocolors";
 import { loadConfig } from "./loader.js";
@@ -88,7 +19,9 @@ import { __dirname } from "./utils.js";
 import { VERSION } from "./constants.js";
 
 /**
- * Recursively compile a .lumix file and all its dependencies
+ * Recursively compile a .lumix file and all its dependencies. Returns the
+ * URL of the compiled module and, unless it was already compiled, the render
+ * mode the compiler read from its directive.
  */
 async function compileWithDependencies(
   lumixPath: string,
@@ -97,7 +30,7 @@ async function compileWithDependencies(
   compile: any,
   compiled: Set<string> = new Set(),
   routeSafeName?: string
-): Promise<string> {
+): Promise<{ url: string; renderMode?: string }> {
   // Avoid recompiling
   const normalizedPath = path.normalize(lumixPath);
   
@@ -117,13 +50,13 @@ async function compileWithDependencies(
   const compiledPath = path.join(tempDir, `${safeName}.mjs`);
   
   if (compiled.has(normalizedPath)) {
-    return pathToFileURL(compiledPath).href;
+    return { url: pathToFileURL(compiledPath).href };
   }
   
   compiled.add(normalizedPath);
   
   // Compile this file
-  const js = await compile({ input: lumixPath, bundle: false, checkTypes: false });
+  const { js, renderMode } = await compile({ input: lumixPath, bundle: false, checkTypes: false });
   
   // Fix runtime imports
   let fixedJs = js.replace(/from ["']lumin-js["']/g, 'from "lumix-js"')
@@ -175,7 +108,7 @@ async function compileWithDependencies(
   // Write compiled file
   fs.writeFileSync(compiledPath, fixedJs, 'utf8');
   
-  return pathToFileURL(compiledPath).href;
+  return { url: pathToFileURL(compiledPath).href, renderMode };
 }
 
 /**
@@ -183,7 +116,7 @@ async function compileWithDependencies(
  * Each route gets its own bundle to avoid shipping unnecessary code
  */
 async function generateRouteEntries(cwd: string, config: any) {
-  const { compile } = await import("@lumix-js/compiler");
+  const { compileWithMetadata } = await import("@lumix-js/compiler");
   
   // Read routes
   const routesPath = path.join(cwd, ".lumix", "routes.mjs");
@@ -207,22 +140,14 @@ async function generateRouteEntries(cwd: string, config: any) {
     if (!fs.existsSync(lumixPath)) continue;
     
     try {
-      const lumixContent = fs.readFileSync(lumixPath, "utf8");
-      let directive = "prerender"; // Default to PIR
-      
-      try {
-        directive = parseDirectiveFromLumix(lumixContent, route.path);
-      } catch (error) {
-        console.warn(`[lumix] Warning in ${route.path}: Failed to parse directive, using default 'prerender'`);
-      }
-      
-      routeDirectives[route.path] = directive;
-      
       // Determine the safe name for this route
       const safeName = route.path === "/" ? "index" : route.path.slice(1).replace(/\//g, "-");
       
-      // Compile the route and all its dependencies recursively
-      await compileWithDependencies(lumixPath, cwd, tempDir, compile, new Set(), safeName);
+      // Compile the route and all its dependencies recursively; the compiler
+      // reports the route's render mode (prerender, i.e. PIR, by default)
+      const { renderMode } = await compileWithDependencies(lumixPath, cwd, tempDir, compileWithMetadata, new Set(), safeName);
+      const directive = renderMode ?? "prerender";
+      routeDirectives[route.path] = directive;
       
       // Get the compiled path
       const tempPath = path.join(tempDir, `${safeName}.mjs`);
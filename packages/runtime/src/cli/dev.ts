@@ -37,77 +37,10 @@ function mergeHead(configHead: LuminConfig['head'], routeHead: RouteHeadConfig |
   return merged;
 }
 
-/**
- * Parse directive from .lumix file using proper parsing instead of regex
- * Supported directives:
- * - "use prerender" -> PIR (Progressive Instant Rendering) - default
- * - "use server" -> SSR (Server-Side Rendering)
- * - "use static" -> SSG (Static Site Generation) - future islands architecture
- */
-function parseDirectiveFromLumix(lumixContent: string, routePath: string): string {
-  // Extract script section
-  const scriptMatch = lumixContent.match(/<script[^>]*>([\s\S]*?)<\/script>/);
-  if (!scriptMatch) {
-    return "prerender"; // No script section, default to prerender (PIR)
-  }
-  
-  const scriptContent = scriptMatch[1];
-  
-  // Parse the JavaScript content to find directives
-  try {
-    // Simple tokenizer to find string literals at the beginning
-    const lines = scriptContent.split('\n');
-    let foundDirective: string | null = null;
-    let directiveCount = 0;
-    
-    for (let i = 0; i < lines.length; i++) {
-      const line = lines[i].trim();
-      
-      // Skip empty lines and comments
-      if (!line || line.startsWith('//') || line.startsWith('/*')) {
-        continue;
-      }
-      
-      // Check if this line contains a directive
-      if (line.startsWith('"use server";') || line.startsWith("'use server';")) {
-        foundDirective = "server";
-        directiveCount++;
-      } else if (line.startsWith('"use prerender";') || line.startsWith("'use prerender';")) {
-        foundDirective = "prerender";
-        directiveCount++;
-      } else if (line.startsWith('"use static";') || line.startsWith("'use static';")) {
-        foundDirective = "static";
-        directiveCount++;
-      } else if (line.startsWith('"use server"') || line.startsWith("'use server'")) {
-        foundDirective = "server";
-        directiveCount++;
-      } else if (line.startsWith('"use prerender"') || line.startsWith("'use prerender'")) {
-        foundDirective = "prerender";
-        directiveCount++;
-      } else if (line.startsWith('"use static"') || line.startsWith("'use static'")) {
-        foundDirective = "static";
-        directiveCount++;
-      } else {
-        // If we hit a non-directive, non-comment, non-empty line, stop looking
-        // Directives must be at the top
-        break;
-      }
-    }
-    
-    // Validate directive count
-    if (directiveCount > 1) {
-      throw new Error(`Multiple directives found. Only one directive per component is allowed.`);
-    }
-    
-    return foundDirective || "prerender";
-    
-  } catch (error) {
-    throw new Error(`Failed to parse directive: ${(error as Error).message}`);
-  }
-}
-
 async function generateDirectivesForDev(cwd: string) {
   try {
+    const { compileWithMetadata } = await import("@lumix-js/compiler");
+
     // Read routes
     const routesPath = path.join(cwd, ".lumix", "routes.mjs");
     if (!fs.existsSync(routesPath)) return;
@@ -123,11 +56,10 @@ async function generateDirectivesForDev(cwd: string) {
       const lumixPath = path.join(cwd, route.file.replace(/^\//, ""));
       
       if (fs.existsSync(lumixPath)) {
-        const lumixContent = fs.readFileSync(lumixPath, "utf8");
-        
         try {
-          const directive = parseDirectiveFromLumix(lumixContent, route.path);
-          routeDirectives[route.path] = directive;
+          // The compiler reads the `"use ..."` directive from the script
+          const { renderMode } = await compileWithMetadata({ input: lumixPath, bundle: false, checkTypes: false });
+          routeDirectives[route.path] = renderMode;
         } catch (error) {
           console.warn(`[lumix dev] Warning in ${route.path}: ${(error as Error).message}, using default 'prerender'`);
           routeDirectives[route.path] = "prerender";
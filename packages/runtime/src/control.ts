@@ -1,3 +1,5 @@
+import { computed, effect, signal, untrack } from "./signals.js";
+
 export type ControlBranch = {
   cond?: () => any;
   body: () => any | any[];
@@ -19,6 +21,55 @@ export function __if(condition: () => any, branches: ControlBranch[]) {
   };
 }
 
+export type SwitchCase = {
+  value?: () => any;
+  body: () => any | any[];
+};
+
+type IsLiteral<T> = T extends string
+  ? string extends T ? false : true
+  : T extends number
+    ? number extends T ? false : true
+    : T extends boolean | null | undefined
+      ? true
+      : false;
+
+/**
+ * Type the discriminant of an `@{switch}` without `default` must fit once its
+ * cases are ruled out: `never` when it is a union of literals, so a missing
+ * case is a type error, and `unknown` for wider types such as `string`.
+ */
+export type Exhaustive<T> = [T] extends [never]
+  ? never
+  : false extends (T extends any ? IsLiteral<T> : never)
+    ? unknown
+    : never;
+
+/**
+ * Index of the case matching `discriminant()` with `===`; a case without a
+ * value is the default. -1 when nothing matches.
+ */
+export function switchIndex(discriminant: () => any, cases: { value?: () => any }[]) {
+  return computed(() => {
+    const value = discriminant();
+    return cases.findIndex((c) => !c.value || c.value() === value);
+  });
+}
+
+/**
+ * Reactive Switch helper: the discriminant is evaluated once per change, and
+ * the rendered branch is kept until a different case matches.
+ * @param discriminant Closure returning the value to match
+ * @param cases Cases in order, the default (without `value`) last
+ */
+export function __switch(discriminant: () => any, cases: SwitchCase[]) {
+  const index = switchIndex(discriminant, cases);
+  return () => {
+    const i = index();
+    return i >= 0 ? untrack(cases[i].body) : [];
+  };
+}
+
 /**
  * Reactive For loop helper
  * @param list Closure returning the array to iterate over
@@ -55,3 +106,71 @@ export function __for<T>(
     return newNodes;
   };
 }
+
+export type AwaitState<T> =
+  | { status: "pending" }
+  | { status: "then"; value: T }
+  | { status: "catch"; error: any };
+
+export function isPromiseLike(value: any): value is PromiseLike<any> {
+  return value != null && typeof value.then === "function";
+}
+
+/**
+ * Follows the promise returned by `promise()`, which is re-run when the
+ * signals it reads change. Results of superseded promises are dropped, and
+ * a non-promise value counts as already resolved. Without `handled`, a
+ * rejection of the current promise is also logged, since no `@{catch}`
+ * branch shows it.
+ */
+export function awaitState<T>(
+  promise: () => T | PromiseLike<T>,
+  handled: boolean,
+): () => AwaitState<T> {
+  const state = signal<AwaitState<T>>({ status: "pending" });
+  let latest = 0;
+
+  effect(() => {
+    const value = promise();
+    const run = ++latest;
+    if (!isPromiseLike(value)) {
+      state({ status: "then", value });
+      return;
+    }
+    state({ status: "pending" });
+    value.then(
+      (resolved) => {
+        if (run === latest) state({ status: "then", value: resolved });
+      },
+      (error) => {
+        if (run !== latest) return;
+        if (!handled) console.error("Unhandled rejection in @{await}:", error);
+        state({ status: "catch", error });
+      },
+    );
+  });
+
+  return state;
+}
+
+/**
+ * Reactive `@{await}` helper
+ * @param promise Closure returning the promise (or a plain value)
+ * @param pending Rendered while the promise is pending
+ * @param then Rendered with the resolved value
+ * @param catchBranch Rendered with the rejection reason, if given
+ */
+export function __await<T>(
+  promise: () => T | PromiseLike<T>,
+  pending: () => any | any[],
+  then: (value: T) => any | any[],
+  catchBranch?: (error: any) => any | any[],
+) {
+  const state = awaitState(promise, !!catchBranch);
+  return () => {
+    const current = state();
+    if (current.status === "pending") return pending();
+    if (current.status === "then") return then(current.value);
+    return catchBranch ? catchBranch(current.error) : [];
+  };
+}
@@ -1,6 +1,11 @@
-import { Signal, effect } from "./signals.js";
+import { Signal, effect, isSignal, signal, untrack } from "./signals.js";
 import { bind } from "./bind.js";
 import { withHooks, runHooks } from "./lifecycle.js";
+import {
+  HydrationCursor,
+  HydrationMismatch,
+  __hydrateComponent,
+} from "./hydration.js";
 
 export type AttrValue = string | number | boolean | (() => any) | Signal<any>;
 
@@ -15,8 +20,9 @@ export function h(
 ): any {
   if (typeof tag === "function") {
     const { result, mount, destroy } = withHooks(() =>
-      tag(props || {}, ...children),
+      tag(resolveSpreads(componentProps(props)), ...children),
     );
+    bindComponentRef(props, result);
 
     // Attach hooks for single element or Fragment(array) roots.
     const roots = Array.isArray(result) ? result : [result];
@@ -26,7 +32,7 @@ export function h(
           setTimeout(() => runHooks(mount), 0);
         }
         if (destroy.length > 0) {
-          (r as any)._luminDestroy = destroy;
+          addDestroyHooks(r, destroy);
         }
       }
     }
@@ -35,49 +41,7 @@ export function h(
 
   const el = document.createElement(tag);
 
-  if (props) {
-    for (const [key, value] of Object.entries(props)) {
-      // ── bind: directive ──────────────────────────────
-      if (key.startsWith("bind:")) {
-        const property = key.slice(5); // "bind:value" → "value"
-        if (typeof value === "function" && "_peek" in value) {
-          // It's a Signal — set up two-way binding
-          bind(el, property, value as Signal<any>);
-        }
-        continue;
-      }
-
-      // ── Event handlers ───────────────────────────────
-      if (key.startsWith("on") && typeof value === "function") {
-        const eventName = key.slice(2).toLowerCase();
-        el.addEventListener(eventName, value as EventListener);
-      }
-      // ── Reactive attribute (Signal or closure) ───────
-      else if (typeof value === "function") {
-        effect(() => {
-          const v = (value as Function)();
-          if (
-            key === "value" ||
-            key === "checked" ||
-            key === "disabled" ||
-            key === "selected"
-          ) {
-            // DOM properties not attributes
-            (el as any)[key] = v;
-          } else if (typeof v === "boolean") {
-            if (v) el.setAttribute(key, "");
-            else el.removeAttribute(key);
-          } else {
-            el.setAttribute(key, String(v));
-          }
-        });
-      }
-      // ── Static attribute ─────────────────────────────
-      else {
-        el.setAttribute(key, String(value));
-      }
-    }
-  }
+  if (props) applyProps(el, props);
 
   for (const child of children.flat(Infinity)) {
     if (child === null || child === undefined) continue;
@@ -88,71 +52,453 @@ export function h(
       const endMarker = document.createComment("cf-end");
       el.appendChild(startMarker);
       el.appendChild(endMarker);
+      reactiveRange(el, endMarker, child as () => any);
+    } else if (child instanceof Node) {
+      el.appendChild(child);
+    } else {
+      // Handle primitives - convert to string properly
+      const textValue = child === null || child === undefined ? '' : String(child);
+      el.appendChild(document.createTextNode(textValue));
+    }
+  }
 
-      let prevNodes: Node[] = [];
+  if (props) finishElement(el, props);
 
-      effect(() => {
-        let v = (child as Function)();
+  return el;
+}
 
-        // Normalize to array of nodes
-        let newNodes: Node[] = [];
-        const items = Array.isArray(v) ? v.flat(Infinity) : [v];
+const LISTENER_OPTIONS = Symbol("lumix.listenerOptions");
 
-        for (let item of items) {
-          if (item === null || item === undefined) continue;
+/**
+ * An event handler carrying `addEventListener` options (the `once`,
+ * `capture` and `passive` modifiers). `handler` itself is left untouched.
+ */
+export function listener(
+  handler: (this: any, e: Event) => any,
+  options: AddEventListenerOptions,
+): (e: Event) => any {
+  const wrapped = function (this: any, e: Event) {
+    return handler.call(this, e);
+  };
+  (wrapped as any)[LISTENER_OPTIONS] = options;
+  return wrapped;
+}
 
-          // Unwrap nested functions
-          while (typeof item === "function") {
-            item = item();
-          }
+function addListener(el: HTMLElement, key: string, handler: any) {
+  const eventName = key.slice(2).toLowerCase();
+  el.addEventListener(eventName, handler, handler[LISTENER_OPTIONS]);
+  return eventName;
+}
 
-          if (item instanceof Node) {
-            newNodes.push(item);
-          } else {
-            // Convert to string, handling primitives properly
-            const textValue = item === null || item === undefined ? '' : String(item);
-            newNodes.push(document.createTextNode(textValue));
-          }
-        }
+/** Key prefix of a compiled `{...expr}` entry, e.g. `"...0"`. */
+const SPREAD = "...";
 
-        // --- Improved Reconciliation ---
-        const newNodeSet = new Set(newNodes);
+function isSpread(key: string): boolean {
+  return key.startsWith(SPREAD);
+}
 
-        // 1. Remove and unmount only nodes that are NOT in the new set
-        for (const node of prevNodes) {
-          if (!newNodeSet.has(node)) {
-            unmount(node);
-            if (node.parentNode === el) {
-              el.removeChild(node);
-            }
-          }
-        }
+/** The object a spread evaluates to. Signals and closures are unwrapped. */
+export function spreadSource(value: any): Record<string, any> {
+  while (typeof value === "function") value = value();
+  return value !== null && typeof value === "object" ? value : {};
+}
+
+/**
+ * Component props with their `{...expr}` entries merged in source order
+ * (later entries win). The keys are those of the spread objects at creation;
+ * values are read through getters, so they stay reactive. Functions (event
+ * handlers, signals, slots) are passed as they are.
+ */
+export function resolveSpreads(props: Props): Props {
+  if (!Object.keys(props).some(isSpread)) return props;
+  const out: Props = {};
+  for (const [key, value] of Object.entries(props)) {
+    if (!isSpread(key)) {
+      delete out[key];
+      out[key] = value;
+      continue;
+    }
+    const source = untrack(() => spreadSource(value));
+    for (const [name, current] of Object.entries(source)) {
+      delete out[name];
+      out[name] =
+        typeof current === "function"
+          ? current
+          : () => spreadSource(value)[name];
+    }
+  }
+  return out;
+}
+
+/**
+ * Current `class:` and `style:` directive values per element. Writing the
+ * `class` or `style` attribute replaces the whole value, so the directives
+ * are applied again on top of it.
+ */
+const directives = new WeakMap<
+  HTMLElement,
+  { classes: Map<string, boolean>; styles: Map<string, string | null> }
+>();
+
+function directiveState(el: HTMLElement) {
+  let state = directives.get(el);
+  if (!state) {
+    state = { classes: new Map(), styles: new Map() };
+    directives.set(el, state);
+  }
+  return state;
+}
+
+function applyClass(el: HTMLElement, name: string, on: boolean) {
+  directiveState(el).classes.set(name, on);
+  el.classList.toggle(name, on);
+}
+
+/** null, undefined and `false` remove the property. */
+function applyStyle(el: HTMLElement, property: string, v: any) {
+  const value = v === null || v === undefined || v === false ? null : String(v);
+  directiveState(el).styles.set(property, value);
+  if (value === null) el.style.removeProperty(property);
+  else el.style.setProperty(property, value);
+}
+
+function unwrap(value: any): any {
+  while (typeof value === "function") value = value();
+  return value;
+}
+
+function restoreDirectives(el: HTMLElement, key: string) {
+  const state = directives.get(el);
+  if (!state) return;
+  if (key === "class") {
+    for (const [name, on] of state.classes) el.classList.toggle(name, on);
+  } else if (key === "style") {
+    for (const [property, value] of state.styles) {
+      if (value !== null) el.style.setProperty(property, value);
+    }
+  }
+}
+
+/**
+ * Declares a prop the parent can bind two-way: `const value = model("")`,
+ * then `<TextField bind:value={name} />`. The compiler turns the declaration
+ * into `__modelProp`; outside a component it is a plain signal.
+ */
+export function model<T>(initial?: T): Signal<T> {
+  return signal(initial as T);
+}
+
+/**
+ * The signal behind a `model()` prop. With `bind:name={signal}` it is the
+ * parent's signal, so writes reach the parent. Otherwise it is a local
+ * signal that starts at `initial` and follows the `name` prop, if passed.
+ */
+export function __modelProp<T>(props: Props, name: string, initial: T): Signal<T> {
+  const bound = props[`bind:${name}`];
+  if (isSignal(bound)) return bound;
+  const local = signal(initial);
+  const source = name in props ? props[name] : bound;
+  if (source !== undefined) {
+    effect(() => {
+      local(unwrap(source));
+    });
+  }
+  return local;
+}
+
+function setAttribute(el: HTMLElement, key: string, v: any) {
+  if (
+    key === "value" ||
+    key === "checked" ||
+    key === "disabled" ||
+    key === "selected"
+  ) {
+    // DOM properties not attributes
+    (el as any)[key] = v;
+  } else if (typeof v === "boolean") {
+    if (v) el.setAttribute(key, "");
+    else el.removeAttribute(key);
+  } else if (v === null || v === undefined) {
+    el.removeAttribute(key);
+  } else {
+    el.setAttribute(key, String(v));
+  }
+  restoreDirectives(el, key);
+}
+
+/**
+ * Applies a `{...expr}` entry. It is re-applied whenever the object changes:
+ * attributes and listeners it no longer has are removed, and keys in `skip`
+ * (attributes written after it) are left alone.
+ */
+function applySpread(el: HTMLElement, value: AttrValue, skip: Set<string>) {
+  let attributes = new Set<string>();
+  let listeners = new Map<string, EventListener>();
+  effect(() => {
+    const source = spreadSource(value);
+    for (const [event, handler] of listeners) {
+      el.removeEventListener(event, handler, (handler as any)[LISTENER_OPTIONS]);
+    }
+    listeners = new Map();
+    const next = new Set<string>();
+    for (const [key, v] of Object.entries(source)) {
+      if (skip.has(key) || /^(bind|class|style|use):/.test(key)) continue;
+      if (key.startsWith("on") && typeof v === "function") {
+        listeners.set(addListener(el, key, v), v);
+        continue;
+      }
+      let current = v;
+      while (typeof current === "function") current = current();
+      setAttribute(el, key, current);
+      next.add(key);
+    }
+    for (const key of attributes) {
+      if (!next.has(key)) setAttribute(el, key, null);
+    }
+    attributes = next;
+  });
+}
+
+/**
+ * Applies `h` props to an element: `bind:`, `class:` and `style:`
+ * directives, event listeners, reactive attributes (Signals/closures),
+ * spreads and static attributes. `use:` actions and `bind:this` are left to
+ * `finishElement`.
+ */
+export function applyProps(el: HTMLElement, props: Props) {
+  const entries = Object.entries(props);
+  for (const [index, [key, value]] of entries.entries()) {
+    // ── {...spread} ──────────────────────────────────
+    if (isSpread(key)) {
+      const later = entries.slice(index + 1).map(([k]) => k.replace(/^bind:/, ""));
+      applySpread(el, value, new Set(later));
+      continue;
+    }
+
+    // ── use: / bind:this, run by finishElement ───────
+    if (key.startsWith("use:") || key === "bind:this") continue;
 
-        // 2. Insert or move nodes
-        // insertBefore naturally handles moves (reaches same state if already correctly positioned)
-        for (const node of newNodes) {
-          el.insertBefore(node, endMarker);
+    // ── bind: directive ──────────────────────────────
+    if (key.startsWith("bind:")) {
+      const property = key.slice(5); // "bind:value" → "value"
+      if (isSignal(value)) {
+        // It's a Signal — set up two-way binding
+        bind(el, property, value as Signal<any>);
+      }
+      continue;
+    }
+
+    // ── class: / style: directives ───────────────────
+    if (key.startsWith("class:")) {
+      const name = key.slice(6);
+      effect(() => applyClass(el, name, !!unwrap(value)));
+      continue;
+    }
+    if (key.startsWith("style:")) {
+      const property = key.slice(6);
+      effect(() => applyStyle(el, property, unwrap(value)));
+      continue;
+    }
+
+    // ── Event handlers ───────────────────────────────
+    if (key.startsWith("on") && typeof value === "function") {
+      addListener(el, key, value);
+    }
+    // ── Reactive attribute (Signal or closure) ───────
+    else if (typeof value === "function") {
+      effect(() => setAttribute(el, key, (value as Function)()));
+    }
+    // ── Static attribute ─────────────────────────────
+    else {
+      el.setAttribute(key, String(value));
+      restoreDirectives(el, key);
+    }
+  }
+}
+
+/**
+ * Keeps the nodes right before `endMarker` in sync with `fn()`.
+ * `adopt` holds nodes already in the DOM (from server markup); on the first
+ * run their text nodes are reused instead of being recreated.
+ */
+export function reactiveRange(
+  parent: Node,
+  endMarker: Node,
+  fn: () => any,
+  adopt: Node[] = [],
+) {
+  let prevNodes: Node[] = adopt;
+  let reusable = adopt.filter((n) => n.nodeType === Node.TEXT_NODE) as Text[];
+
+  effect(() => {
+    const newNodes = toNodes(fn(), reusable);
+    reusable = [];
+
+    // --- Improved Reconciliation ---
+    const newNodeSet = new Set(newNodes);
+
+    // 1. Remove and unmount only nodes that are NOT in the new set
+    for (const node of prevNodes) {
+      if (!newNodeSet.has(node)) {
+        unmount(node);
+        if (node.parentNode === parent) {
+          parent.removeChild(node);
         }
+      }
+    }
 
-        prevNodes = newNodes;
-      });
-    } else if (child instanceof Node) {
-      el.appendChild(child);
+    // 2. Insert or move nodes
+    // insertBefore naturally handles moves (reaches same state if already correctly positioned)
+    for (const node of newNodes) {
+      parent.insertBefore(node, endMarker);
+    }
+
+    prevNodes = newNodes;
+  });
+}
+
+/**
+ * Normalizes a rendered value (node, primitive, function or nested arrays)
+ * to a flat list of nodes. Text nodes from `reuse` are recycled for primitives.
+ */
+export function toNodes(v: any, reuse: Text[] = []): Node[] {
+  const nodes: Node[] = [];
+  const items = Array.isArray(v) ? v.flat(Infinity) : [v];
+
+  for (let item of items) {
+    if (item === null || item === undefined) continue;
+
+    // Unwrap nested functions
+    while (typeof item === "function") {
+      item = item();
+    }
+
+    if (item instanceof Node) {
+      nodes.push(item);
     } else {
-      // Handle primitives - convert to string properly
-      const textValue = child === null || child === undefined ? '' : String(child);
-      el.appendChild(document.createTextNode(textValue));
+      // Convert to string, handling primitives properly
+      const textValue = item === null || item === undefined ? '' : String(item);
+      const text = reuse.shift();
+      if (text) {
+        if (text.data !== textValue) text.data = textValue;
+        nodes.push(text);
+      } else {
+        nodes.push(document.createTextNode(textValue));
+      }
     }
   }
 
-  return el;
+  return nodes;
+}
+
+/**
+ * `{@html expr}`: the value is parsed as markup instead of being escaped, and
+ * parsed again whenever it changes.
+ */
+export function __html(fn: () => any): () => Node[] {
+  return () => parseHtml(fn());
+}
+
+/** The nodes of a markup string; null and undefined give none. */
+export function parseHtml(value: any): Node[] {
+  while (typeof value === "function") value = value();
+  if (value === null || value === undefined) return [];
+  const template = document.createElement("template");
+  template.innerHTML = String(value);
+  return Array.from(template.content.childNodes);
+}
+
+/**
+ * Marks markup as safe for `{@html}`, which otherwise gets a compiler warning
+ * unless the value comes from a sanitizer. Returns `html` unchanged.
+ */
+export function trustedHtml(html: string): string {
+  return html;
 }
 
 export function Fragment(_props: any, ...children: any[]) {
   return children.flat(Infinity);
 }
 
-function unmount(node: Node) {
+/** Hooks run when `el` is unmounted, after those it already has. */
+export function addDestroyHooks(el: HTMLElement, hooks: (() => void)[]) {
+  const current = (el as any)._luminDestroy;
+  (el as any)._luminDestroy = current ? [...current, ...hooks] : hooks;
+}
+
+export interface ActionReturn<P = any> {
+  update?: (params: P) => void;
+  destroy?: () => void;
+}
+
+/** A `use:` action: called with the element and its parameters. */
+export type Action<E extends HTMLElement = HTMLElement, P = any> = (
+  node: E,
+  params: P,
+) => ActionReturn<P> | void;
+
+/** What `bind:this={ref}` stores: the signal's value type, or the variable's. */
+export type RefTarget<R> = R extends Signal<infer V> ? V : R;
+
+/**
+ * Runs the props that need the finished element, once its children are in
+ * place: `bind:this` stores it (and `null` once it is unmounted), and each
+ * `use:action={params}` is called with it. `update` is called when the
+ * parameters change and `destroy` when the element is unmounted.
+ */
+export function finishElement(el: HTMLElement, props: Props) {
+  for (const [key, value] of Object.entries(props)) {
+    if (key === "bind:this") {
+      const setRef = value as (node: HTMLElement | null) => void;
+      setRef(el);
+      addDestroyHooks(el, [() => setRef(null)]);
+      continue;
+    }
+    if (!key.startsWith("use:")) continue;
+    const [action, params] = value as unknown as [Action, (() => any)?];
+    let result: ActionReturn | void;
+    let started = false;
+    const stop = effect(() => {
+      const current = params?.();
+      untrack(() => {
+        if (!started) {
+          started = true;
+          result = action(el, current);
+        } else {
+          result?.update?.(current);
+        }
+      });
+    });
+    addDestroyHooks(el, [
+      () => {
+        stop();
+        result?.destroy?.();
+      },
+    ]);
+  }
+}
+
+/** The props a component receives: its `bind:this` is for the parent only. */
+export function componentProps(props: Props | null): Props {
+  if (!props || !("bind:this" in props)) return props || {};
+  const { ["bind:this"]: _ref, ...rest } = props;
+  return rest;
+}
+
+/**
+ * Hands a component's rendered root to its `bind:this`: the element, or the
+ * list of nodes for a fragment. It is reset to `null` when the root unmounts.
+ */
+export function bindComponentRef(props: Props | null, root: any) {
+  const setRef = props?.["bind:this"] as ((node: any) => void) | undefined;
+  if (typeof setRef !== "function") return;
+  const nodes: any[] = Array.isArray(root) ? root : [root];
+  setRef(nodes.length === 1 ? nodes[0] : nodes);
+  const el = nodes.find((n) => n instanceof HTMLElement);
+  if (el) addDestroyHooks(el, [() => setRef(null)]);
+}
+
+export function unmount(node: Node) {
   if (node instanceof HTMLElement) {
     const hooks = (node as any)._luminDestroy;
     if (hooks) runHooks(hooks);
@@ -160,11 +506,27 @@ function unmount(node: Node) {
   node.childNodes.forEach(unmount);
 }
 
+/**
+ * Attaches `component` to `root`. Markup already in `root` (from SSR or
+ * prerendering) is adopted in place when the component was compiled with
+ * hydration support; otherwise, or if the markup does not match, `root` is
+ * cleared and rendered from scratch.
+ */
 export function hydrate(
   root: HTMLElement,
   component: (props?: any) => any,
   props?: any,
 ) {
+  if (root.firstChild) {
+    try {
+      __hydrateComponent(new HydrationCursor(root), component, props || {});
+      return;
+    } catch (e) {
+      if (!(e instanceof HydrationMismatch)) throw e;
+      console.warn(`${e.message}; rendering from scratch.`);
+    }
+  }
+
   while (root.firstChild) root.removeChild(root.firstChild);
   const out = h(component, props || {});
   const nodes = Array.isArray(out) ? out : [out];
@@ -407,3 +769,28 @@ export async function hydrateIsland(
   if (!Comp) return;
   hydrate(el, Comp, props);
 }
+
+export type IslandManifest = {
+  component: string;
+  islands: { id: string; component: string; entry: string; props: any }[];
+};
+
+/**
+ * Hydrate every island of a `"use static"` page from its
+ * `<name>.islands.json` manifest. Entries resolve against `base`, usually
+ * the URL the manifest was served from.
+ */
+export async function hydrateIslands(
+  manifest: IslandManifest,
+  base: string | URL = document.baseURI,
+) {
+  await Promise.all(
+    manifest.islands.map((island) => {
+      const el = document.querySelector<HTMLElement>(
+        `[data-lumix-island="${island.id}"]`,
+      );
+      if (!el) return;
+      return hydrateIsland(el, new URL(island.entry, base).href, island.props);
+    }),
+  );
+}
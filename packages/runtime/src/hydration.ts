@@ -0,0 +1,446 @@
+import { effect, untrack } from "./signals.js";
+import { withHooks, runHooks } from "./lifecycle.js";
+import { awaitState, switchIndex } from "./control.js";
+import {
+  Props,
+  addDestroyHooks,
+  applyProps,
+  bindComponentRef,
+  componentProps,
+  finishElement,
+  parseHtml,
+  reactiveRange,
+  resolveSpreads,
+  toNodes,
+  unmount,
+} from "./dom.js";
+
+// ─── Hydration (adopting server markup) ─────────────────────
+//
+// Compiled components take an optional second argument, a HydrationCursor.
+// When it is present they walk the existing DOM through the helpers below
+// instead of calling `h`: nodes are claimed in template order, and only
+// effects, listeners and `bind:` directives are attached. Reactive blocks are
+// delimited by the same `<!--cf-start-->`/`<!--cf-end-->` comments the client
+// renderer and the SSR codegen emit.
+
+/** Returned by a compiled component once it has adopted its markup. */
+export const HYDRATED = Symbol("lumix.hydrated");
+
+/** The existing DOM does not match the template being hydrated. */
+export class HydrationMismatch extends Error {
+  constructor(message: string) {
+    super(`[lumix] hydration mismatch: ${message}`);
+    this.name = "HydrationMismatch";
+  }
+}
+
+function isMarker(node: Node | null, data: string): node is Comment {
+  return (
+    node !== null &&
+    node.nodeType === Node.COMMENT_NODE &&
+    (node as Comment).data === data
+  );
+}
+
+function describe(node: Node | null): string {
+  if (!node) return "end of parent";
+  if (node.nodeType === Node.ELEMENT_NODE)
+    return `<${(node as Element).tagName.toLowerCase()}>`;
+  if (node.nodeType === Node.COMMENT_NODE)
+    return `<!--${(node as Comment).data}-->`;
+  return "text";
+}
+
+/**
+ * Walks the children of `parent`, from `node` up to (not including) `end`.
+ */
+export class HydrationCursor {
+  constructor(
+    public parent: Node,
+    public node: Node | null = parent.firstChild,
+    public end: Node | null = null,
+    public start: Node | null = null,
+  ) {}
+
+  private peek(): Node | null {
+    return this.node === this.end ? null : this.node;
+  }
+
+  private advance(): Node {
+    const node = this.node!;
+    this.node = node.nextSibling;
+    return node;
+  }
+
+  /** Claims a static text node, if the parser produced one. */
+  text() {
+    const next = this.peek();
+    if (next && next.nodeType === Node.TEXT_NODE) this.advance();
+  }
+
+  /** Claims a template comment, if the server markup kept it. */
+  comment() {
+    const next = this.peek();
+    if (
+      next &&
+      next.nodeType === Node.COMMENT_NODE &&
+      !isMarker(next, "cf-start") &&
+      !isMarker(next, "cf-end")
+    )
+      this.advance();
+  }
+
+  /** Claims the next element, which must be a `<tag>`. */
+  element(tag: string): HTMLElement {
+    let next = this.peek();
+    // Whitespace the HTML parser moved or merged is not significant.
+    while (next && next.nodeType === Node.TEXT_NODE) {
+      this.advance();
+      next = this.peek();
+    }
+    if (
+      !next ||
+      next.nodeType !== Node.ELEMENT_NODE ||
+      (next as Element).tagName.toLowerCase() !== tag.toLowerCase()
+    ) {
+      throw new HydrationMismatch(`expected <${tag}>, found ${describe(next)}`);
+    }
+    return this.advance() as HTMLElement;
+  }
+
+  /** Cursor over the children of `el`. */
+  enter(el: Node): HydrationCursor {
+    return new HydrationCursor(el);
+  }
+
+  /**
+   * Claims a `<!--cf-start-->` … `<!--cf-end-->` range and returns a cursor
+   * limited to its contents.
+   */
+  block(): HydrationCursor {
+    let next = this.peek();
+    while (next && next.nodeType === Node.TEXT_NODE && !next.nodeValue?.trim()) {
+      this.advance();
+      next = this.peek();
+    }
+    if (!isMarker(next, "cf-start")) {
+      throw new HydrationMismatch(`expected <!--cf-start-->, found ${describe(next)}`);
+    }
+    const start = this.advance();
+    let depth = 0;
+    let node = this.peek();
+    while (node) {
+      if (isMarker(node, "cf-start")) depth++;
+      else if (isMarker(node, "cf-end")) {
+        if (depth === 0) break;
+        depth--;
+      }
+      this.advance();
+      node = this.peek();
+    }
+    if (!node) throw new HydrationMismatch("unterminated <!--cf-start--> block");
+    this.advance();
+    return new HydrationCursor(this.parent, start.nextSibling, node, start);
+  }
+
+  /** Nodes between `from` and the current position. */
+  claimedSince(from: Node | null): Node[] {
+    const nodes: Node[] = [];
+    for (let n = from; n && n !== this.node; n = n.nextSibling) nodes.push(n);
+    return nodes;
+  }
+
+  /** Every node currently inside the range, claimed or not. */
+  contents(): Node[] {
+    const nodes: Node[] = [];
+    const first = this.start ? this.start.nextSibling : this.parent.firstChild;
+    for (let n = first; n && n !== this.end; n = n.nextSibling) nodes.push(n);
+    return nodes;
+  }
+}
+
+function replaceRange(range: HydrationCursor, nodes: Node[]) {
+  for (const node of range.contents()) {
+    if (!nodes.includes(node)) {
+      unmount(node);
+      range.parent.removeChild(node);
+    }
+  }
+  for (const node of nodes) range.parent.insertBefore(node, range.end);
+}
+
+/** Adopts a native element: attaches `props` and hydrates its children. */
+export function __hydrateElement(
+  c: HydrationCursor,
+  tag: string,
+  props: Props | null,
+  children?: (c: HydrationCursor) => void,
+): HTMLElement {
+  const el = c.element(tag);
+  if (props) applyProps(el, props);
+  if (children) children(c.enter(el));
+  if (props) finishElement(el, props);
+  return el;
+}
+
+/** Adopts a `{expr}` block. */
+export function __hydrateBlock(c: HydrationCursor, fn: () => any) {
+  const range = c.block();
+  reactiveRange(range.parent, range.end!, fn, range.contents());
+}
+
+/**
+ * Adopts a `{@html expr}` block: the server markup stays as it is until the
+ * value changes.
+ */
+export function __hydrateHtml(c: HydrationCursor, fn: () => any) {
+  const range = c.block();
+  let adopted = false;
+  effect(() => {
+    const html = fn();
+    if (!adopted) {
+      adopted = true;
+      return;
+    }
+    untrack(() => replaceRange(range, parseHtml(html)));
+  });
+}
+
+/** A compiled `@{snippet}`: renders its body, and can adopt it from server markup. */
+export type Snippet<A extends any[] = any[]> = ((...args: A) => any) & {
+  hydrate?: (c: HydrationCursor, ...args: A) => void;
+};
+
+/** Pairs a snippet's render function with the function that hydrates its body. */
+export function __snippet<A extends any[]>(
+  render: (...args: A) => any,
+  hydrate: (c: HydrationCursor, ...args: A) => void,
+): Snippet<A> {
+  return Object.assign(render, { hydrate });
+}
+
+/**
+ * Adopts a `{@render snippet(args)}` block: the server markup is hydrated by
+ * the snippet, later changes of the snippet or its arguments render.
+ */
+export function __hydrateRender(
+  c: HydrationCursor,
+  snippet: () => Snippet | null | undefined,
+  args: () => any[],
+) {
+  const range = c.block();
+  let adopted = false;
+  effect(() => {
+    const fn = snippet();
+    const values = args();
+    untrack(() => {
+      if (!adopted) {
+        adopted = true;
+        if (fn?.hydrate) return fn.hydrate(range, ...values);
+      }
+      replaceRange(range, fn ? toNodes(fn(...values)) : []);
+    });
+  });
+}
+
+function textOf(value: any): string {
+  while (typeof value === "function") value = value();
+  if (Array.isArray(value)) return value.map(textOf).join("");
+  return value === null || value === undefined ? "" : String(value);
+}
+
+/**
+ * Adopts the content of a `<textarea>` or `<title>`: the HTML parser turns it
+ * into one text node, so it is rewritten whole whenever a part changes.
+ */
+export function __hydrateText(c: HydrationCursor, parts: () => any[]) {
+  effect(() => {
+    const text = textOf(parts());
+    if (c.parent.textContent !== text) c.parent.textContent = text;
+  });
+}
+
+export type HydrateBranch = {
+  cond?: () => any;
+  body: () => any | any[];
+  hydrate: (c: HydrationCursor) => void;
+};
+
+/** Adopts an `@{if}` block: the active branch is hydrated, later switches render. */
+export function __hydrateIf(
+  c: HydrationCursor,
+  condition: () => any,
+  branches: HydrateBranch[],
+) {
+  const range = c.block();
+  const pick = () => {
+    if (condition()) return 0;
+    for (let i = 1; i < branches.length; i++) {
+      const b = branches[i];
+      if (!b.cond || b.cond()) return i;
+    }
+    return -1;
+  };
+
+  let current: number | undefined;
+  effect(() => {
+    const index = pick();
+    if (index === current) return;
+    const previous = current;
+    current = index;
+    untrack(() => {
+      if (previous === undefined) {
+        if (index >= 0) branches[index].hydrate(range);
+      } else {
+        const nodes = index >= 0 ? toNodes(branches[index].body()) : [];
+        replaceRange(range, nodes);
+      }
+    });
+  });
+}
+
+export type HydrateSwitchCase = {
+  value?: () => any;
+  body: () => any | any[];
+  hydrate: (c: HydrationCursor) => void;
+};
+
+/** Adopts an `@{switch}` block: the matching case is hydrated, later matches render. */
+export function __hydrateSwitch(
+  c: HydrationCursor,
+  discriminant: () => any,
+  cases: HydrateSwitchCase[],
+) {
+  const range = c.block();
+  const index = switchIndex(discriminant, cases);
+  let hydrated = false;
+
+  effect(() => {
+    const i = index();
+    untrack(() => {
+      if (!hydrated) {
+        hydrated = true;
+        if (i >= 0) cases[i].hydrate(range);
+      } else {
+        replaceRange(range, i >= 0 ? toNodes(cases[i].body()) : []);
+      }
+    });
+  });
+}
+
+export type HydrateAwaitBranch<V> = {
+  body: (value: V) => any | any[];
+  hydrate: (c: HydrationCursor, value: V) => void;
+};
+
+/**
+ * Adopts an `@{await}` block: the branch the server rendered (pending, or
+ * `then` for a plain value) is hydrated, later states render.
+ */
+export function __hydrateAwait<T>(
+  c: HydrationCursor,
+  promise: () => T | PromiseLike<T>,
+  pending: HydrateAwaitBranch<void>,
+  then: HydrateAwaitBranch<T>,
+  catchBranch?: HydrateAwaitBranch<any>,
+) {
+  const range = c.block();
+  const state = awaitState(promise, !!catchBranch);
+  let hydrated = false;
+
+  effect(() => {
+    const current = state();
+    untrack(() => {
+      const [branch, value]: [HydrateAwaitBranch<any> | undefined, any] =
+        current.status === "pending"
+          ? [pending, undefined]
+          : current.status === "then"
+            ? [then, current.value]
+            : [catchBranch, current.error];
+      if (!hydrated) {
+        hydrated = true;
+        branch?.hydrate(range, value);
+      } else {
+        replaceRange(range, branch ? toNodes(branch.body(value)) : []);
+      }
+    });
+  });
+}
+
+/** Adopts an `@{for}` block: each item hydrates its own slice of the markup. */
+export function __hydrateFor<T>(
+  c: HydrationCursor,
+  list: () => T[],
+  render: (item: T, index: number) => any | any[],
+  hydrate: (c: HydrationCursor, item: T, index: number) => void,
+  keyFn?: (item: T) => any,
+) {
+  const range = c.block();
+  const cache = new Map<any, Node[]>();
+  let hydrated = false;
+
+  effect(() => {
+    const items = list() || [];
+    untrack(() => {
+      if (!hydrated) {
+        hydrated = true;
+        items.forEach((item, index) => {
+          const from = range.node;
+          hydrate(range, item, index);
+          if (keyFn) cache.set(keyFn(item), range.claimedSince(from));
+        });
+        return;
+      }
+
+      const nodes: Node[] = [];
+      const keys = new Set<any>();
+      items.forEach((item, index) => {
+        if (!keyFn) {
+          nodes.push(...toNodes(render(item, index)));
+          return;
+        }
+        const key = keyFn(item);
+        keys.add(key);
+        let rendered = cache.get(key);
+        if (!rendered) {
+          rendered = toNodes(render(item, index));
+          cache.set(key, rendered);
+        }
+        nodes.push(...rendered);
+      });
+      for (const key of cache.keys()) {
+        if (!keys.has(key)) cache.delete(key);
+      }
+      replaceRange(range, nodes);
+    });
+  });
+}
+
+/** Adopts a child component rendered in place by the server. */
+export function __hydrateComponent(
+  c: HydrationCursor,
+  Comp: (props: any, cursor?: HydrationCursor) => any,
+  props: any,
+) {
+  const from = c.node;
+  const { result, mount, destroy } = withHooks(() =>
+    Comp(resolveSpreads(componentProps(props)), c),
+  );
+  if (result !== HYDRATED) {
+    throw new HydrationMismatch(
+      `${Comp.name || "component"} was not compiled with hydration support`,
+    );
+  }
+
+  const roots = c.claimedSince(from);
+  bindComponentRef(props, roots);
+  if (mount.length > 0) {
+    setTimeout(() => runHooks(mount), 0);
+  }
+  if (destroy.length > 0) {
+    for (const r of roots) {
+      if (r instanceof HTMLElement) addDestroyHooks(r, destroy);
+    }
+  }
+}
@@ -2,6 +2,8 @@ export * from "./signals.js";
 export * from "./store.js";
 export * from "./bind.js";
 export * from "./dom.js";
+export * from "./hydration.js";
 export * from "./control.js";
 export * from "./lifecycle.js";
 export * from "./config.js";
+export * from "./ssr.js";
@@ -110,6 +110,11 @@ export function signal<T>(initialValue: T): Signal<T> {
   return readWrite as Signal<T>;
 }
 
+/** Whether `value` is a writable signal created by `signal()`. */
+export function isSignal(value: any): value is Signal<any> {
+  return typeof value === "function" && "_peek" in value;
+}
+
 // ─── effect() ──────────────────────────────────────────────
 export function effect(fn: () => void | CleanupFn): Unsubscribe {
   const node: EffectNode = {
@@ -0,0 +1,183 @@
+import { withHooks } from "./lifecycle.js";
+import { resolveSpreads, spreadSource } from "./dom.js";
+import { isPromiseLike } from "./control.js";
+
+// ─── String renderer helpers (used by `luminc build --target ssr`) ──────
+//
+// The server codegen turns templates into string concatenation; these helpers
+// cover the parts that depend on runtime values. They follow what `h` does on
+// the client so both renderers produce the same markup.
+
+export function escapeHtml(value: string): string {
+  return value
+    .replace(/&/g, "&amp;")
+    .replace(/</g, "&lt;")
+    .replace(/>/g, "&gt;")
+    .replace(/\u00a0/g, "&nbsp;");
+}
+
+export function escapeAttr(value: string): string {
+  return value
+    .replace(/&/g, "&amp;")
+    .replace(/"/g, "&quot;")
+    .replace(/\u00a0/g, "&nbsp;");
+}
+
+function unwrap(value: any): any {
+  while (typeof value === "function") value = value();
+  return value;
+}
+
+/**
+ * Escaped text for a `{expr}` node. Signals and closures are unwrapped,
+ * arrays are flattened, null/undefined render nothing.
+ */
+export function ssrText(value: any): string {
+  value = unwrap(value);
+  if (Array.isArray(value)) return value.map(ssrText).join("");
+  if (value === null || value === undefined) return "";
+  return escapeHtml(String(value));
+}
+
+/**
+ * Already-rendered HTML (slot content, child components).
+ */
+export function ssrRaw(value: any): string {
+  value = unwrap(value);
+  if (Array.isArray(value)) return value.map(ssrRaw).join("");
+  if (value === null || value === undefined) return "";
+  return String(value);
+}
+
+/**
+ * Markup from `{@html expr}`, inserted as it is.
+ */
+export function ssrHtml(value: any): string {
+  value = unwrap(value);
+  return value === null || value === undefined ? "" : String(value);
+}
+
+/**
+ * ` name="value"` for a dynamic attribute. `true` renders an empty value,
+ * `false`/null/undefined drop the attribute.
+ */
+export function ssrAttr(name: string, value: any): string {
+  value = unwrap(value);
+  if (value === false || value === null || value === undefined) return "";
+  if (value === true) return ` ${name}=""`;
+  return ` ${name}="${escapeAttr(String(value))}"`;
+}
+
+/**
+ * The attributes of an element with a `{...expr}` or `class:`/`style:`
+ * directives: entries are merged in source order, later ones winning, then
+ * rendered like `ssrAttr`, with the directives applied on top of `class` and
+ * `style`. Event handlers, `bind:` and directive keys from spreads are
+ * skipped.
+ */
+export function ssrSpreadAttrs(props: Record<string, any>): string {
+  const attrs = new Map<string, any>();
+  const classes = new Map<string, boolean>();
+  const styles = new Map<string, any>();
+  for (const [key, value] of Object.entries(props)) {
+    if (key.startsWith("class:")) {
+      classes.set(key.slice(6), !!unwrap(value));
+      continue;
+    }
+    if (key.startsWith("style:")) {
+      styles.set(key.slice(6), unwrap(value));
+      continue;
+    }
+    if (!key.startsWith("...")) {
+      attrs.delete(key);
+      attrs.set(key, value);
+      continue;
+    }
+    for (const [name, v] of Object.entries(spreadSource(value))) {
+      if (/^(bind|class|style|use):/.test(name)) continue;
+      if (name.startsWith("on") && typeof v === "function") continue;
+      attrs.delete(name);
+      attrs.set(name, v);
+    }
+  }
+  if (classes.size > 0) {
+    attrs.set("class", ssrClass(unwrap(attrs.get("class")), classes));
+  }
+  if (styles.size > 0) {
+    attrs.set("style", ssrStyle(unwrap(attrs.get("style")), styles));
+  }
+  let out = "";
+  for (const [name, value] of attrs) out += ssrAttr(name, value);
+  return out;
+}
+
+/** `base` with each `class:` directive toggled; null when nothing is left. */
+function ssrClass(base: any, classes: Map<string, boolean>): string | null {
+  const names = typeof base === "string" ? base.split(/\s+/).filter(Boolean) : [];
+  for (const [name, on] of classes) {
+    const index = names.indexOf(name);
+    if (on && index < 0) names.push(name);
+    if (!on && index >= 0) names.splice(index, 1);
+  }
+  return names.length > 0 ? names.join(" ") : null;
+}
+
+/**
+ * `base` with each `style:` directive set, replacing a declaration of the same
+ * property. null, undefined and `false` remove it, like on the client.
+ */
+function ssrStyle(base: any, styles: Map<string, any>): string | null {
+  let declarations = (typeof base === "string" ? base.split(";") : [])
+    .map((d) => d.trim())
+    .filter(Boolean);
+  for (const [property, value] of styles) {
+    declarations = declarations.filter(
+      (d) => d.slice(0, d.indexOf(":")).trim() !== property,
+    );
+    if (value !== null && value !== undefined && value !== false) {
+      declarations.push(`${property}: ${value}`);
+    }
+  }
+  return declarations.length > 0 ? declarations.join("; ") + ";" : null;
+}
+
+/**
+ * Eager `@{for}`: renders every item and joins the results.
+ */
+export function ssrEach<T>(
+  list: () => T[],
+  render: (item: T, index: number) => string,
+): string {
+  const items = unwrap(list) || [];
+  return Array.from(items as T[], (item, index) => render(item, index)).join(
+    "",
+  );
+}
+
+/**
+ * `@{await}`: a promise renders the pending branch (the client takes over
+ * once it settles), any other value renders `then` with it.
+ */
+export function ssrAwait<T>(
+  promise: () => T | PromiseLike<T>,
+  pending: () => string,
+  then: (value: T) => string,
+): string {
+  const value = promise();
+  if (!isPromiseLike(value)) return then(value);
+  // Settled on the client; don't let a rejection crash the server.
+  value.then(undefined, () => {});
+  return pending();
+}
+
+/**
+ * Renders a child component to HTML. Lifecycle hooks are collected and
+ * dropped, since nothing is mounted on the server.
+ */
+export function ssrComponent(
+  Comp: (props: any) => any,
+  props: any,
+): string {
+  const { result } = withHooks(() => Comp(resolveSpreads(props || {})));
+  return ssrRaw(result);
+}